@@ -1,9 +1,56 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-//! The `render` example generates a WAV file from a serialized [Project].
+//! The `render` example generates a WAV file from a serialized [Project], and
+//! optionally a WAV file for each of its tracks.
 
-use clap::Parser;
-use ensnare_v1::prelude::*;
+use clap::{Parser, ValueEnum};
+use ensnare_v1::{
+    orchestration::{
+        Dither, Normalization, OfflineRenderJob, OfflineRenderTarget, StemExportOptions,
+        WavExportOptions, WavSampleFormat,
+    },
+    prelude::*,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum FormatArg {
+    /// 16-bit integer
+    #[default]
+    Int16,
+    /// 24-bit integer
+    Int24,
+    /// 32-bit floating point
+    Float32,
+}
+impl From<FormatArg> for WavSampleFormat {
+    fn from(value: FormatArg) -> Self {
+        match value {
+            FormatArg::Int16 => WavSampleFormat::Int16,
+            FormatArg::Int24 => WavSampleFormat::Int24,
+            FormatArg::Float32 => WavSampleFormat::Float32,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum DitherArg {
+    /// No dither
+    None,
+    /// Triangular (TPDF) dither
+    #[default]
+    Triangular,
+    /// Triangular dither with noise shaping
+    NoiseShaped,
+}
+impl From<DitherArg> for Dither {
+    fn from(value: DitherArg) -> Self {
+        match value {
+            DitherArg::None => Dither::None,
+            DitherArg::Triangular => Dither::Triangular,
+            DitherArg::NoiseShaped => Dither::NoiseShaped,
+        }
+    }
+}
 
 #[derive(Parser, Debug, Default)]
 #[clap(author, about, long_about = None)]
@@ -15,6 +62,39 @@ struct Args {
     #[clap(short = 'w', long, value_parser)]
     wav: bool,
 
+    /// Render each track as its own WAVE file (files will appear in a directory
+    /// next to source file)
+    #[clap(short = 's', long, value_parser)]
+    stems: bool,
+
+    /// With --stems, capture each track before its fader and pan
+    #[clap(long, value_parser)]
+    pre_fader: bool,
+
+    /// With --stems, skip the aux tracks that the exported tracks send to
+    #[clap(long, value_parser)]
+    no_sends: bool,
+
+    /// With --stems, export only the tracks with these uids
+    #[clap(long, value_delimiter = ',')]
+    tracks: Vec<usize>,
+
+    /// Sample format of rendered WAVE files
+    #[clap(short = 'f', long, value_enum, default_value_t)]
+    format: FormatArg,
+
+    /// Dither for integer sample formats
+    #[clap(long, value_enum, default_value_t)]
+    dither: DitherArg,
+
+    /// Normalize the render so that its peak is at this level, in dBFS
+    #[clap(long, allow_negative_numbers = true, conflicts_with = "normalize_lufs")]
+    normalize_peak: Option<f64>,
+
+    /// Normalize the render so that its integrated loudness is this many LUFS
+    #[clap(long, allow_negative_numbers = true)]
+    normalize_lufs: Option<f64>,
+
     /// Enable debug mode
     #[clap(short = 'd', long, value_parser)]
     debug: bool,
@@ -44,11 +124,48 @@ fn main() -> anyhow::Result<()> {
                             panic!("would overwrite input file; couldn't generate output filename");
                         }
                         let output_path = std::path::PathBuf::from(output_filename.to_string());
-                        if let Err(e) = project.export_to_wav(output_path) {
+                        let options = WavExportOptions {
+                            sample_format: args.format.into(),
+                            dither: args.dither.into(),
+                            normalization: args
+                                .normalize_peak
+                                .map(Normalization::Peak)
+                                .or(args.normalize_lufs.map(Normalization::Loudness)),
+                        };
+                        if let Err(e) = render_with_progress(
+                            &mut project,
+                            OfflineRenderTarget::Wav(output_path, options),
+                        ) {
                             eprintln!("error while writing {input_filename} render to {output_filename}: {e:?}");
                             return Err(e);
                         }
                     }
+                    if args.stems {
+                        let re = regex::Regex::new(r"\.json$").unwrap();
+                        let output_dirname = re.replace(&input_filename, "-stems");
+                        if input_filename == output_dirname {
+                            panic!("would overwrite input file; couldn't generate output directory name");
+                        }
+                        let options = StemExportOptions {
+                            track_uids: if args.tracks.is_empty() {
+                                None
+                            } else {
+                                Some(args.tracks.iter().map(|uid| TrackUid(*uid)).collect())
+                            },
+                            is_post_fader: !args.pre_fader,
+                            include_sends: !args.no_sends,
+                            sample_format: args.format.into(),
+                            dither: args.dither.into(),
+                        };
+                        let output_dir = std::path::PathBuf::from(output_dirname.to_string());
+                        if let Err(e) = render_with_progress(
+                            &mut project,
+                            OfflineRenderTarget::Stems(output_dir, options),
+                        ) {
+                            eprintln!("error while writing {input_filename} stems to {output_dirname}: {e:?}");
+                            return Err(e);
+                        }
+                    }
                 }
                 Err(e) => eprintln!("error while parsing {input_filename}: {e:?}"),
             },
@@ -57,3 +174,24 @@ fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Renders on a separate thread, printing progress as it goes.
+fn render_with_progress(project: &mut Project, target: OfflineRenderTarget) -> anyhow::Result<()> {
+    let job = OfflineRenderJob::new_with(project, target)?;
+    let path = job.target().path().clone();
+    let progress = job.progress();
+    let handle = std::thread::spawn(move || job.run());
+    while !handle.is_finished() {
+        eprint!(
+            "\rRendering {}: {:3.0}%",
+            path.display(),
+            progress.fraction() * 100.0
+        );
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+    eprintln!("\rRendered {}      ", path.display());
+    handle
+        .join()
+        .map_err(|_| anyhow::anyhow!("the render thread panicked"))?
+        .map(|_| ())
+}
@@ -43,13 +43,18 @@ struct LegendSettings {
         value = "(MusicalTime::START..MusicalTime::new_with_beats(128)).into()"
     ))]
     range: ViewRange,
+    time_signature_map: TimeSignatureMap,
 }
 impl LegendSettings {
     const NAME: &'static str = "Legend";
 
     fn show(&mut self, ui: &mut eframe::egui::Ui) {
         if !self.hide {
-            ui.add(LegendWidget::widget(&mut self.range));
+            ui.add(LegendWidget::widget(
+                &mut self.range,
+                TimeSignature::default(),
+                &self.time_signature_map,
+            ));
         }
     }
 }
@@ -293,6 +298,7 @@ struct GridSettings {
         value = "(MusicalTime::START..MusicalTime::new_with_beats(128)).into()"
     ))]
     view_range: ViewRange,
+    time_signature_map: TimeSignatureMap,
 }
 impl GridSettings {
     const NAME: &'static str = "Grid";
@@ -301,7 +307,12 @@ impl GridSettings {
         if !self.hide {
             ui.add_enabled(
                 false,
-                GridWidget::widget(self.range.clone(), self.view_range.clone()),
+                GridWidget::widget(
+                    self.range.clone(),
+                    self.view_range.clone(),
+                    TimeSignature::default(),
+                    self.time_signature_map.clone(),
+                ),
             );
         }
     }
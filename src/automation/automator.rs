@@ -129,6 +129,12 @@ impl Controls for Automator {
     fn is_finished(&self) -> bool {
         self.is_finished
     }
+
+    fn skip_to_start(&mut self) {
+        self.paths
+            .values_mut()
+            .for_each(|path| path.skip_to_start());
+    }
 }
 impl ControlsAsProxy for Automator {
     fn work_as_proxy(&mut self, control_events_fn: &mut ControlProxyEventsFn) {
@@ -151,7 +151,10 @@ impl Controls for SignalPath {
 
     fn stop(&mut self) {}
 
-    fn skip_to_start(&mut self) {}
+    fn skip_to_start(&mut self) {
+        // Targets might have been changed since the last broadcast.
+        self.e.broadcasted_value = None;
+    }
 
     fn is_performing(&self) -> bool {
         false
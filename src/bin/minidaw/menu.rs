@@ -12,9 +12,13 @@ pub(crate) enum MenuBarAction {
     ProjectOpen,
     ProjectSave,
     ProjectExportToWav,
+    ProjectExportToSmf,
+    EditUndo,
+    EditRedo,
     TrackNewMidi,
     TrackNewAudio,
     TrackNewAux,
+    TrackNewGroup,
     TrackDuplicate,
     TrackDelete,
     TrackRemoveSelectedPatterns,
@@ -109,15 +113,28 @@ impl Displays for MenuBar {
                         MenuBarItem::leaf("Open", MenuBarAction::ProjectOpen, true),
                         MenuBarItem::leaf("Save", MenuBarAction::ProjectSave, true),
                         MenuBarItem::leaf("Export to WAV", MenuBarAction::ProjectExportToWav, true),
+                        MenuBarItem::leaf(
+                            "Export to MIDI",
+                            MenuBarAction::ProjectExportToSmf,
+                            true,
+                        ),
                         MenuBarItem::leaf("Quit", MenuBarAction::Quit, true),
                     ],
                 ),
+                MenuBarItem::node(
+                    "Edit",
+                    vec![
+                        MenuBarItem::leaf("Undo", MenuBarAction::EditUndo, true),
+                        MenuBarItem::leaf("Redo", MenuBarAction::EditRedo, true),
+                    ],
+                ),
                 MenuBarItem::node(
                     "Track",
                     vec![
                         MenuBarItem::leaf("New MIDI", MenuBarAction::TrackNewMidi, true),
                         MenuBarItem::leaf("New Audio", MenuBarAction::TrackNewAudio, true),
                         MenuBarItem::leaf("New Aux", MenuBarAction::TrackNewAux, true),
+                        MenuBarItem::leaf("New Group", MenuBarAction::TrackNewGroup, true),
                         MenuBarItem::leaf(
                             "Duplicate",
                             MenuBarAction::TrackDuplicate,
@@ -9,7 +9,9 @@ use crate::{
 };
 use crossbeam_channel::{Select, Sender};
 use eframe::{
-    egui::{CentralPanel, Context, Layout, Modifiers, TopBottomPanel, Ui, WidgetText},
+    egui::{
+        CentralPanel, Context, Key, Layout, Modifiers, ProgressBar, TopBottomPanel, Ui, WidgetText,
+    },
     emath::{Align, Align2},
     epaint::Vec2,
     App, CreationContext,
@@ -21,10 +23,11 @@ use ensnare_services::{prelude::*, AudioStereoSampleType};
 use ensnare_v1::{
     app_version,
     egui::{
-        ComposerWidget, ControlBar, ControlBarAction, ControlBarWidget, EntityPaletteWidget,
-        ObliqueStrategiesWidget, ProjectAction, ProjectWidget, TransportWidget,
+        ComposerWidget, ComposerWidgetAction, ControlBar, ControlBarAction, ControlBarWidget,
+        EntityPaletteWidget, ObliqueStrategiesWidget, ProjectAction, ProjectWidget,
+        TransportWidget,
     },
-    orchestration::AudioSenderFn,
+    orchestration::{AudioSenderFn, RenderProgress, WavExportOptions},
     prelude::*,
     traits::DisplaysAction,
 };
@@ -43,6 +46,8 @@ pub(super) struct RenderingState {
 #[derive(Debug, Default)]
 pub(super) struct MiniDawEphemeral {
     pub(super) is_project_performing: bool,
+    /// The export that's running in the background, if any.
+    pub(super) export_progress: Option<RenderProgress>,
 }
 
 #[derive(Debug, Display, PartialEq)]
@@ -86,19 +91,28 @@ impl<'a> TabViewer for MiniDawTabViewer<'a> {
                             None
                         };
                         if let Some(pattern_uid) = project.composer.e.edited_pattern {
+                            let mut action = None;
                             if let Some(pattern) = project.composer.patterns.get_mut(&pattern_uid) {
                                 let widget = ComposerWidget::new(&mut pattern.notes)
-                                    .color_scheme(pattern.color_scheme);
+                                    .color_scheme(pattern.color_scheme)
+                                    .action(&mut action);
                                 let widget = if let Some(metadata) = metadata {
                                     widget.midi_note_label_metadata(metadata)
                                 } else {
                                     widget
                                 };
-
-                                if ui.add(widget).changed() {
-                                    project.notify_pattern_change();
-                                }
+                                let _ = ui.add(widget);
                             }
+                            // The widget leaves the notes alone and tells us
+                            // what to change, so that the change can be undone.
+                            let _ =
+                                match action {
+                                    Some(ComposerWidgetAction::AddNotes(notes)) => project
+                                        .edit_pattern_notes(pattern_uid, Vec::default(), notes),
+                                    Some(ComposerWidgetAction::RemoveNotes(notes)) => project
+                                        .edit_pattern_notes(pattern_uid, notes, Vec::default()),
+                                    None => Ok(()),
+                                };
                         }
                     }
                 }
@@ -106,7 +120,7 @@ impl<'a> TabViewer for MiniDawTabViewer<'a> {
             TabType::Arrangement => {
                 if let Some(project) = self.project {
                     if let Ok(mut project) = project.write() {
-                        project.view_state.cursor = Some(project.transport.current_time());
+                        project.view_state.cursor = Some(project.current_time());
                         project.view_state.view_range = Self::calculate_project_view_range(
                             &project.time_signature(),
                             project.composer.extent(),
@@ -251,6 +265,9 @@ impl MiniDaw {
         r.send_to_project(ProjectServiceInput::VisualizationQueue(
             r.control_bar.visualization_queue.clone(),
         ));
+        r.send_to_project(ProjectServiceInput::MeterReadingsQueue(
+            r.control_bar.meter_readings_queue.clone(),
+        ));
 
         r.spawn_app_channel_watcher(cc.egui_ctx.clone());
         r
@@ -408,12 +425,34 @@ impl MiniDaw {
                             .error(format!("Error saving {}", e).to_string())
                             .set_duration(Some(Duration::from_secs(5)));
                     }
+                    ProjectServiceEvent::ExportStarted(_export_path, progress) => {
+                        self.e.export_progress = Some(progress);
+                    }
+                    ProjectServiceEvent::ExportCancelled(export_path) => {
+                        self.e.export_progress = None;
+                        self.toasts
+                            .info(format!("Cancelled export to {}", export_path.display()))
+                            .set_duration(Some(Duration::from_secs(2)));
+                    }
                     ProjectServiceEvent::Exported(export_path) => {
+                        // An SMF export can finish while a WAV export is still
+                        // running.
+                        self.e.export_progress = self
+                            .e
+                            .export_progress
+                            .take()
+                            .filter(|progress| !progress.is_finished());
                         self.toasts
                             .success(format!("Exported to {}", export_path.display()).to_string())
                             .set_duration(Some(Duration::from_secs(2)));
                     }
                     ProjectServiceEvent::ExportFailed(e) => {
+                        // So can a refused export, or a failed SMF export.
+                        self.e.export_progress = self
+                            .e
+                            .export_progress
+                            .take()
+                            .filter(|progress| !progress.is_finished());
                         self.toasts
                             .error(format!("Error exporting {}", e).to_string())
                             .set_duration(Some(Duration::from_secs(5)));
@@ -444,13 +483,33 @@ impl MiniDaw {
         ui.horizontal_centered(|ui| {
             if let Some(project) = self.project.as_mut() {
                 if let Ok(mut project) = project.write() {
+                    let current_time = project.current_time();
                     if ui
-                        .add(TransportWidget::widget(&mut project.transport))
+                        .add(TransportWidget::widget(
+                            &mut project.transport,
+                            current_time,
+                        ))
                         .changed()
                     {
                         project.notify_transport_tempo_change();
                         project.notify_transport_time_signature_change();
                     }
+                    let mut is_loop_enabled = project.is_loop_enabled();
+                    if ui.checkbox(&mut is_loop_enabled, "Loop").changed() {
+                        project.set_loop_enabled(is_loop_enabled);
+                        if is_loop_enabled && project.loop_range().is_none() {
+                            // Nothing to loop yet, so loop every bar that has
+                            // something in it.
+                            let extent = project.composer.extent();
+                            if !extent.0.is_empty() {
+                                let start = project.bar_start(project.bar_at(extent.0.start));
+                                let end = project.bar_start(
+                                    project.bar_at(extent.0.end - MusicalTime::ONE_UNIT) + 1,
+                                );
+                                let _ = project.set_loop_range(TimeRange(start..end));
+                            }
+                        }
+                    }
                 }
             } else {
                 // there might be some flicker here while we wait for the
@@ -485,6 +544,20 @@ impl MiniDaw {
     fn show_bottom(&mut self, ui: &mut eframe::egui::Ui) {
         ui.horizontal(|ui| {
             eframe::egui::warn_if_debug_build(ui);
+            if let Some(progress) = self.e.export_progress.as_ref() {
+                ui.add(
+                    ProgressBar::new(progress.fraction())
+                        .desired_width(160.0)
+                        .show_percentage(),
+                )
+                .on_hover_text("Exporting");
+                if ui.button("Cancel").clicked() {
+                    self.send_to_project(ProjectServiceInput::ProjectExportCancel);
+                }
+
+                // Nothing else wakes the UI while an export runs.
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            }
             ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                 ui.label(app_version());
                 if let Some(seed) = self.oblique_strategies_mgr.check_seed() {
@@ -516,7 +589,13 @@ impl MiniDaw {
                         pressed,
                         physical_key,
                     } => {
-                        if !repeat && !modifiers.any() {
+                        if *pressed && !repeat && modifiers.command && *key == Key::Z {
+                            self.send_to_project(if modifiers.shift {
+                                ProjectServiceInput::ProjectRedo
+                            } else {
+                                ProjectServiceInput::ProjectUndo
+                            });
+                        } else if !repeat && !modifiers.any() {
                             self.send_to_project(ProjectServiceInput::KeyEvent(
                                 *key,
                                 *pressed,
@@ -613,12 +692,13 @@ impl MiniDaw {
         self.send_to_project(ProjectServiceInput::ProjectSave(path));
     }
 
-    fn handle_ui_export_action(&mut self) {
+    // Suggests a filename for an export based on the project's load path.
+    fn suggested_export_filename(&self, extension: &str) -> String {
         let suggested_filename = if let Some(project) = self.project.as_ref() {
             if let Ok(project) = project.read() {
                 if let Some(path) = project.load_path() {
                     let mut path_copy = path.clone();
-                    path_copy.set_extension("wav");
+                    path_copy.set_extension(extension);
                     if let Some(s) = path_copy.into_os_string().to_str() {
                         Some(s.to_string())
                     } else {
@@ -633,14 +713,31 @@ impl MiniDaw {
         } else {
             None
         };
-        let suggested_filename = suggested_filename.unwrap_or("exported.wav".into());
+        suggested_filename.unwrap_or(format!("exported.{extension}"))
+    }
 
+    fn handle_ui_export_action(&mut self) {
+        let suggested_filename = self.suggested_export_filename("wav");
         if let Ok(Some(path)) = FileDialog::new()
             .add_filter("WAV", &["wav"])
             .set_filename(&suggested_filename)
             .show_save_single_file()
         {
-            self.send_to_project(ProjectServiceInput::ProjectExportToWav(Some(path)));
+            self.send_to_project(ProjectServiceInput::ProjectExportToWav(
+                Some(path),
+                WavExportOptions::default(),
+            ));
+        }
+    }
+
+    fn handle_ui_export_smf_action(&mut self) {
+        let suggested_filename = self.suggested_export_filename("mid");
+        if let Ok(Some(path)) = FileDialog::new()
+            .add_filter("MIDI", &["mid"])
+            .set_filename(&suggested_filename)
+            .show_save_single_file()
+        {
+            self.send_to_project(ProjectServiceInput::ProjectExportToSmf(Some(path)));
         }
     }
 
@@ -652,11 +749,17 @@ impl MiniDaw {
             MenuBarAction::ProjectOpen => self.handle_ui_load_action(),
             MenuBarAction::ProjectSave => self.handle_ui_save_action(),
             MenuBarAction::ProjectExportToWav => self.handle_ui_export_action(),
+            MenuBarAction::ProjectExportToSmf => self.handle_ui_export_smf_action(),
+            MenuBarAction::EditUndo => self.send_to_project(ProjectServiceInput::ProjectUndo),
+            MenuBarAction::EditRedo => self.send_to_project(ProjectServiceInput::ProjectRedo),
             MenuBarAction::TrackNewMidi => self.send_to_project(ProjectServiceInput::TrackNewMidi),
             MenuBarAction::TrackNewAudio => {
                 self.send_to_project(ProjectServiceInput::TrackNewAudio)
             }
             MenuBarAction::TrackNewAux => self.send_to_project(ProjectServiceInput::TrackNewAux),
+            MenuBarAction::TrackNewGroup => {
+                self.send_to_project(ProjectServiceInput::TrackNewGroup)
+            }
             MenuBarAction::TrackDuplicate => todo!(),
             MenuBarAction::TrackDelete => todo!(),
             MenuBarAction::TrackRemoveSelectedPatterns => todo!(),
@@ -434,8 +434,14 @@ impl Controls for Composer {
         self.e.is_performing = false;
     }
 
-    // TODO: this doesn't fit. Ignore here? Or problem with trait?
-    fn skip_to_start(&mut self) {}
+    fn skip_to_start(&mut self) {
+        self.e
+            .tracks_to_sequencers
+            .values_mut()
+            .for_each(|s| s.skip_to_start());
+        self.e.time_range = TimeRange::default();
+        self.update_is_finished();
+    }
 
     fn is_performing(&self) -> bool {
         self.e.is_performing
@@ -5,8 +5,9 @@ use ensnare::prelude::*;
 use serde::{Deserialize, Serialize};
 
 /// A [Note] is a single played note. It knows which key it's playing (which
-/// is more or less assumed to be a MIDI key value), and when (start/end) it's
-/// supposed to play, relative to time zero.
+/// is more or less assumed to be a MIDI key value), how hard it's struck and
+/// released, and when (start/end) it's supposed to play, relative to time
+/// zero.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Note {
@@ -14,6 +15,12 @@ pub struct Note {
     pub key: u8,
     /// The range of time when this note should play.
     pub extent: TimeRange,
+    /// The MIDI velocity (1-127) of the note-on.
+    #[serde(default = "Note::default_velocity")]
+    pub velocity: u8,
+    /// The MIDI velocity (0-127) of the note-off.
+    #[serde(default = "Note::default_release_velocity")]
+    pub release_velocity: u8,
 }
 impl HasExtent for Note {
     fn extent(&self) -> TimeRange {
@@ -25,11 +32,19 @@ impl HasExtent for Note {
     }
 }
 impl Note {
+    /// The velocity of a [Note] that doesn't specify one.
+    pub const DEFAULT_VELOCITY: u8 = 127;
+    /// The release velocity of a [Note] that doesn't specify one. 64 is what
+    /// the MIDI spec suggests for devices that don't sense release velocity.
+    pub const DEFAULT_RELEASE_VELOCITY: u8 = 64;
+
     /// Creates a [Note] from a u8 and a start/end (inclusive start, exclusive end).
     pub const fn new_with_start_and_end(key: u8, start: MusicalTime, end: MusicalTime) -> Self {
         Self {
             key,
             extent: TimeRange(start..end),
+            velocity: Self::DEFAULT_VELOCITY,
+            release_velocity: Self::DEFAULT_RELEASE_VELOCITY,
         }
     }
 
@@ -44,10 +59,47 @@ impl Note {
         Self::new_with(key as u8, start, duration)
     }
 
+    /// Returns a copy of this note with the given velocity, clamped to 1-127.
+    /// (A note-on with velocity zero means note-off in MIDI.)
+    pub fn with_velocity(&self, velocity: u8) -> Self {
+        let mut r = self.clone();
+        r.velocity = velocity.clamp(1, 127);
+        r
+    }
+
+    /// Returns a copy of this note with the given release velocity, clamped to
+    /// 0-127.
+    pub fn with_release_velocity(&self, release_velocity: u8) -> Self {
+        let mut r = self.clone();
+        r.release_velocity = release_velocity.min(127);
+        r
+    }
+
+    /// The velocity to send with this note's note-on. The field isn't checked
+    /// when a project is loaded, so this clamps it to 1-127.
+    pub fn note_on_velocity(&self) -> u8 {
+        self.velocity.clamp(1, 127)
+    }
+
+    /// The velocity to send with this note's note-off, clamped to 0-127.
+    pub fn note_off_velocity(&self) -> u8 {
+        self.release_velocity.min(127)
+    }
+
     /// Moves the note's start/end by the specified amount without changing its
     /// duration.
     pub fn shift_right(&self, rhs: MusicalTime) -> Self {
-        Self::new_with_start_and_end(self.key, self.extent.0.start + rhs, self.extent.0.end + rhs)
+        let mut r = self.clone();
+        r.extent = TimeRange(self.extent.0.start + rhs..self.extent.0.end + rhs);
+        r
+    }
+
+    fn default_velocity() -> u8 {
+        Self::DEFAULT_VELOCITY
+    }
+
+    fn default_release_velocity() -> u8 {
+        Self::DEFAULT_RELEASE_VELOCITY
     }
 }
 // TODO: I don't think this is the best choice to expose this idea. If there's a
@@ -59,17 +111,94 @@ impl Into<Vec<MidiEvent>> for Note {
             MidiEvent {
                 message: MidiMessage::NoteOn {
                     key: u7::from(self.key),
-                    vel: u7::from(127),
+                    vel: u7::from(self.note_on_velocity()),
                 },
                 time: self.extent.0.start,
             },
             MidiEvent {
                 message: MidiMessage::NoteOff {
                     key: u7::from(self.key),
-                    vel: u7::from(127),
+                    vel: u7::from(self.note_off_velocity()),
                 },
                 time: self.extent.0.end,
             },
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_velocity_reaches_midi_events() {
+        let note = Note::new_with(
+            MidiNote::C4 as u8,
+            MusicalTime::START,
+            MusicalTime::DURATION_HALF,
+        )
+        .with_velocity(90)
+        .with_release_velocity(20);
+        let events: Vec<MidiEvent> = note.clone().into();
+        assert_eq!(
+            events[0].message,
+            MidiMessage::NoteOn {
+                key: u7::from(MidiNote::C4 as u8),
+                vel: u7::from(90)
+            }
+        );
+        assert_eq!(
+            events[1].message,
+            MidiMessage::NoteOff {
+                key: u7::from(MidiNote::C4 as u8),
+                vel: u7::from(20)
+            }
+        );
+
+        assert_eq!(
+            note.with_velocity(0).velocity,
+            1,
+            "a zero note-on velocity would be a note-off, so it should be clamped"
+        );
+
+        let mut note = note;
+        note.velocity = 0;
+        note.release_velocity = 200;
+        let events: Vec<MidiEvent> = note.into();
+        assert_eq!(
+            events[0].message,
+            MidiMessage::NoteOn {
+                key: u7::from(MidiNote::C4 as u8),
+                vel: u7::from(1)
+            },
+            "out-of-range velocities set directly should still be clamped"
+        );
+        assert_eq!(
+            events[1].message,
+            MidiMessage::NoteOff {
+                key: u7::from(MidiNote::C4 as u8),
+                vel: u7::from(127)
+            }
+        );
+    }
+
+    #[test]
+    fn note_without_velocity_deserializes_with_defaults() {
+        let original = Note::new_with(
+            MidiNote::D4 as u8,
+            MusicalTime::START,
+            MusicalTime::DURATION_WHOLE,
+        );
+        let note = original.with_velocity(33);
+        let mut value = serde_json::to_value(&note).unwrap();
+        assert_eq!(value["velocity"], 33);
+        let map = value.as_object_mut().unwrap();
+        map.remove("velocity");
+        map.remove("release-velocity");
+
+        let note: Note = serde_json::from_value(value).unwrap();
+        assert_eq!(note.velocity, Note::DEFAULT_VELOCITY);
+        assert_eq!(note.release_velocity, Note::DEFAULT_RELEASE_VELOCITY);
+        assert_eq!(note.extent, original.extent);
+    }
+}
@@ -126,6 +126,20 @@ impl PatternBuilder {
         }
         self
     }
+
+    /// Sets the velocity of every note added so far, cycling through the given
+    /// velocities in the order the notes were added. This is a quick way to
+    /// give a [PatternBuilder::note_sequence()] an accent pattern.
+    pub fn velocities(&mut self, velocities: Vec<u8>) -> &mut Self {
+        if !velocities.is_empty() {
+            if let Some(notes) = self.notes.as_mut() {
+                for (note, velocity) in notes.iter_mut().zip(velocities.iter().cycle()) {
+                    *note = note.with_velocity(*velocity);
+                }
+            }
+        }
+        self
+    }
 }
 impl Default for Pattern {
     fn default() -> Self {
@@ -218,6 +232,15 @@ impl Pattern {
         self.refresh_internals();
     }
 
+    /// Removes the first note matching this one, leaving any other identical
+    /// notes in place.
+    pub fn remove_one_note(&mut self, note: &Note) {
+        if let Some(index) = self.notes.iter().position(|v| v == note) {
+            self.notes.remove(index);
+            self.refresh_internals();
+        }
+    }
+
     /// Adds a note if it doesn't already exist; removes it if it does.
     pub fn toggle_note(&mut self, note: Note) {
         if self.notes.contains(&note) {
@@ -263,6 +286,13 @@ impl Pattern {
         self.replace_note(note, new_note)
     }
 
+    /// Sets a new velocity for all notes in the Pattern matching the given
+    /// [Note]. If any are found, returns the new version.
+    pub fn change_note_velocity(&mut self, note: &Note, new_velocity: u8) -> anyhow::Result<Note> {
+        let new_note = note.with_velocity(new_velocity);
+        self.replace_note(note, new_note)
+    }
+
     /// Replaces all notes in the Pattern matching the given [Note] with a new
     /// [Note]. If any are found, returns the new version.
     pub fn replace_note(&mut self, note: &Note, new_note: Note) -> anyhow::Result<Note> {
@@ -817,4 +847,25 @@ mod tests {
             .unwrap();
         assert_eq!(p.duration(), MusicalTime::new_with_beats(2));
     }
+
+    #[test]
+    fn pattern_builder_velocities() {
+        let p = PatternBuilder::default()
+            .note_sequence(vec![60, 61, 62, 63, 64], None)
+            .velocities(vec![127, 64])
+            .build()
+            .unwrap();
+        let velocities: Vec<u8> = p.notes().iter().map(|n| n.velocity).collect();
+        assert_eq!(
+            velocities,
+            vec![127, 64, 127, 64, 127],
+            "velocities should cycle across the notes"
+        );
+
+        let mut p = p;
+        let note = p.notes()[1].clone();
+        let new_note = p.change_note_velocity(&note, 100).unwrap();
+        assert_eq!(new_note.velocity, 100);
+        assert_eq!(p.notes()[1].velocity, 100);
+    }
 }
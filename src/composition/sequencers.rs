@@ -188,22 +188,10 @@ impl Sequences for NoteSequencer {
         position: MusicalTime,
     ) -> anyhow::Result<()> {
         let note = note.shift_right(position);
-        let _ = self.e.inner.remove_midi_message(
-            channel,
-            MidiMessage::NoteOn {
-                key: u7::from(note.key),
-                vel: u7::from(127),
-            },
-            note.extent.0.start,
-        );
-        let _ = self.e.inner.remove_midi_message(
-            channel,
-            MidiMessage::NoteOff {
-                key: u7::from(note.key),
-                vel: u7::from(127),
-            },
-            note.extent.0.end,
-        );
+        let events: Vec<MidiEvent> = note.clone().into();
+        events.iter().for_each(|&e| {
+            let _ = self.e.inner.remove_midi_event(channel, e);
+        });
         self.notes.retain(|n| *n != note);
         self.recalculate_extent();
         Ok(())
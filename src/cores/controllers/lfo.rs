@@ -28,6 +28,7 @@ pub struct LfoControllerCoreEphemerals {
     is_performing: bool,
     time_range: TimeRange,
     last_frame: usize,
+    last_value: BipolarNormal,
     pub osc_buffer: GenerationBuffer<BipolarNormal>,
 }
 impl Serializable for LfoControllerCore {}
@@ -56,7 +57,6 @@ impl Controls for LfoControllerCore {
             .start
             .as_frames(Tempo::from(120), self.oscillator.sample_rate());
 
-        let mut last_value = BipolarNormal::default();
         if frames != self.e.last_frame {
             let tick_count = if frames >= self.e.last_frame {
                 // normal case; oscillator should advance the calculated number
@@ -70,6 +70,9 @@ impl Controls for LfoControllerCore {
                 // it.
                 frames - self.e.last_frame
             } else {
+                // Time jumped backward, probably because playback looped.
+                // The LFO is free-running, so rather than restart it, we pick
+                // up where it left off.
                 self.e.last_frame = frames;
                 0
             };
@@ -78,10 +81,10 @@ impl Controls for LfoControllerCore {
             self.e.osc_buffer.resize(tick_count);
             self.oscillator.generate(self.e.osc_buffer.buffer_mut());
             if tick_count != 0 {
-                last_value = *self.e.osc_buffer.buffer().last().unwrap();
+                self.e.last_value = *self.e.osc_buffer.buffer().last().unwrap();
             }
         }
-        control_events_fn(WorkEvent::Control(last_value.into()));
+        control_events_fn(WorkEvent::Control(self.e.last_value.into()));
     }
 
     fn is_finished(&self) -> bool {
@@ -98,6 +101,7 @@ impl Controls for LfoControllerCore {
 
     fn skip_to_start(&mut self) {
         // TODO: think how important it is for LFO oscillator to start at zero
+        self.e.last_frame = 0;
     }
 
     fn is_performing(&self) -> bool {
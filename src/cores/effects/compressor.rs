@@ -1,154 +1,619 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+use super::{
+    dynamics::{
+        control_value_to_range, db_to_linear, linear_to_db, EnvelopeFollower, SidechainKey,
+    },
+    SIDECHAIN_KEY_CHANNELS,
+};
 use crate::prelude::*;
 use delegate::delegate;
+use derivative::Derivative;
 use derive_builder::Builder;
-use ensnare_proc_macros::Control;
 use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
 
-#[derive(Debug, Default, Builder, Control, Serialize, Deserialize)]
-#[builder(default)]
+/// How a [CompressorCore] measures the level of its input.
+#[derive(
+    Clone, Copy, Debug, Default, Display, EnumIter, IntoStaticStr, PartialEq, Serialize, Deserialize,
+)]
 #[serde(rename_all = "kebab-case")]
+pub enum CompressorDetector {
+    /// Follows the instantaneous amplitude. Catches transients.
+    #[default]
+    Peak,
+    /// Follows the average power. Closer to perceived loudness.
+    Rms,
+}
+
+/// A feed-forward dynamic range compressor. An envelope follower tracks the
+/// input level, and whatever part of that level is above the threshold is
+/// reduced by the ratio. The attack and release times control how quickly the
+/// follower responds to rising and falling levels.
+///
+/// The follower normally listens to the compressor's own input, but it can
+/// listen to a sidechain key instead, which is how one track ducks another.
+/// See [TransformsAudioWithKey].
+///
+/// Automation moves each parameter across the range that makes sense for it,
+/// such as [CompressorCore::THRESHOLD_RANGE], rather than across 0..=1.
+#[derive(Debug, Builder, Derivative, Serialize, Deserialize)]
+#[derivative(Default)]
+#[builder(default, build_fn(private, name = "build_from_builder"))]
+#[serde(default, rename_all = "kebab-case")]
 pub struct CompressorCore {
-    /// The level above which compression takes effect. Range is 0.0..=1.0, 0.0
-    /// corresponds to quietest, and 1.0 corresponds to 0dB.
-    #[control]
-    threshold: Normal,
-
-    /// How much to compress the audio above the threshold. For example, 2:1
-    /// means that a 2dB input increase leads to a 1dB output increase. Note
-    /// that this value is actually the inverted ratio, so that 2:1 is 0.5 (1
-    /// divided by 2), and 1:4 is 0.25 (1 divided by 4). Thus, 1.0 means no
-    /// compression, and 0.0 is infinite compression (the output remains a
-    /// constant amplitude no matter what).
-    #[control]
-    ratio: Ratio,
-
-    /// How soon the compressor activates after the level exceeds the threshold.
-    /// Expressed as a [Normal] that is scaled to an amount of time.
-    #[control]
-    attack: Normal,
-
-    /// How soon the compressor deactivates after the level drops below the
-    /// Expressed as a [Normal] that is scaled to an amount of time.
-    #[control]
-    release: Normal,
+    /// The level, in dBFS, above which compression takes effect.
+    #[derivative(Default(value = "-20.0"))]
+    #[serde(rename = "threshold-db")]
+    threshold: ParameterType,
+
+    /// How much to compress the audio above the threshold. For example, 4.0
+    /// (4:1) means that a 4dB input increase above the threshold leads to a
+    /// 1dB output increase. 1.0 means no compression.
+    #[derivative(Default(value = "4.0"))]
+    #[serde(rename = "ratio-to-one")]
+    ratio: ParameterType,
+
+    /// The width, in dB, of the region around the threshold where compression
+    /// eases in. Zero is a hard knee.
+    #[derivative(Default(value = "6.0"))]
+    knee: ParameterType,
+
+    /// How long the compressor takes to respond to a rising level.
+    #[derivative(Default(value = "0.01.into()"))]
+    attack: Seconds,
+
+    /// How long the compressor takes to recover after the level falls.
+    #[derivative(Default(value = "0.1.into()"))]
+    release: Seconds,
+
+    /// The gain, in dB, applied after compression to make up for the lost
+    /// level.
+    makeup_gain: ParameterType,
+
+    /// How the input level is measured.
+    detector: CompressorDetector,
+
+    // Older projects stored the threshold as a linear amplitude (1.0 = 0dB)
+    // and the ratio inverted (0.5 = 2:1) under these names. They're read only
+    // so that after_deser() can convert them.
+    #[serde(rename = "threshold", skip_serializing)]
+    #[builder(setter(skip))]
+    legacy_threshold: Option<Normal>,
+    #[serde(rename = "ratio", skip_serializing)]
+    #[builder(setter(skip))]
+    legacy_ratio: Option<Ratio>,
 
     #[serde(skip)]
     #[builder(setter(skip))]
+    e: CompressorCoreEphemerals,
+}
+#[derive(Debug, Default)]
+pub struct CompressorCoreEphemerals {
+    followers: [EnvelopeFollower; 2],
+    key: SidechainKey,
+    gain_reduction: [ParameterType; 2],
+
     c: Configurables,
 }
-impl Serializable for CompressorCore {}
+impl CompressorCoreBuilder {
+    /// The overridden Builder build() method.
+    pub fn build(&self) -> Result<CompressorCore, CompressorCoreBuilderError> {
+        match self.build_from_builder() {
+            Ok(mut s) => {
+                s.after_deser();
+                Ok(s)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl Serializable for CompressorCore {
+    fn after_deser(&mut self) {
+        self.convert_legacy_fields();
+        self.update_coefficients();
+    }
+}
 impl TransformsAudio for CompressorCore {
-    fn transform_channel(&mut self, _channel: usize, input_sample: Sample) -> Sample {
-        let input_sample_positive = input_sample.0.abs();
-        let threshold = self.threshold.0;
-        if input_sample_positive > threshold {
-            // TODO: this expression is (a + b - a) * c * d, which is just b * c
-            // * d, which is clearly wrong. Fix it. (Too tired right now to look
-            //   into how compression should work)
-            Sample::from(
-                (threshold + (input_sample_positive - threshold) * self.ratio)
-                    * input_sample.0.signum(),
-            )
-        } else {
-            input_sample
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        match channel {
+            0 | 1 => {
+                let detector_sample = self.e.key.take_or(channel, input_sample);
+                self.compress(channel, detector_sample, input_sample)
+            }
+            _ if SIDECHAIN_KEY_CHANNELS.contains(&channel) => {
+                self.e.key.set(channel, input_sample);
+                input_sample
+            }
+            _ => input_sample,
+        }
+    }
+}
+impl TransformsAudioWithKey for CompressorCore {
+    fn transform_with_key(&mut self, key: &[StereoSample], samples: &mut [StereoSample]) {
+        for (sample, key) in samples.iter_mut().zip(key.iter()) {
+            *sample = StereoSample(
+                self.compress(0, key.0, sample.0),
+                self.compress(1, key.1, sample.1),
+            );
+        }
+    }
+}
+impl Controllable for CompressorCore {
+    fn control_index_count(&self) -> usize {
+        Self::CONTROL_NAMES.len()
+    }
+
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        Self::CONTROL_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(ControlIndex)
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        Self::CONTROL_NAMES.get(index.0).map(|n| n.to_string())
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        match index.0 {
+            0 => self.set_threshold(control_value_to_range(value, &Self::THRESHOLD_RANGE)),
+            1 => self.set_ratio(control_value_to_range(value, &Self::RATIO_RANGE)),
+            2 => self.set_knee(control_value_to_range(value, &Self::KNEE_RANGE)),
+            3 => self.set_attack(Seconds::from(value.0)),
+            4 => self.set_release(Seconds::from(value.0)),
+            5 => self.set_makeup_gain(control_value_to_range(value, &Self::MAKEUP_GAIN_RANGE)),
+            _ => {}
         }
     }
 }
 impl Configurable for CompressorCore {
     delegate! {
-        to self.c {
+        to self.e.c {
             fn sample_rate(&self) -> SampleRate;
-            fn update_sample_rate(&mut self, sample_rate: SampleRate);
             fn tempo(&self) -> Tempo;
             fn update_tempo(&mut self, tempo: Tempo);
             fn time_signature(&self) -> TimeSignature;
             fn update_time_signature(&mut self, time_signature: TimeSignature);
         }
     }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.c.update_sample_rate(sample_rate);
+        self.update_coefficients();
+    }
 }
 impl CompressorCore {
-    pub fn threshold(&self) -> Normal {
+    /// The thresholds, in dBFS, that automation covers.
+    pub const THRESHOLD_RANGE: RangeInclusive<ParameterType> = -60.0..=0.0;
+    /// The ratios that automation covers.
+    pub const RATIO_RANGE: RangeInclusive<ParameterType> = 1.0..=20.0;
+    /// The knee widths, in dB, that automation covers.
+    pub const KNEE_RANGE: RangeInclusive<ParameterType> = 0.0..=24.0;
+    /// The makeup gains, in dB, that automation covers.
+    pub const MAKEUP_GAIN_RANGE: RangeInclusive<ParameterType> = 0.0..=24.0;
+
+    // In control index order. Attack and release are in seconds, so they're
+    // already in 0..=1.
+    const CONTROL_NAMES: [&'static str; 6] = [
+        "threshold",
+        "ratio",
+        "knee",
+        "attack",
+        "release",
+        "makeup-gain",
+    ];
+
+    // Converts the threshold and ratio of a project saved before they were
+    // expressed in dB and N:1. Those projects also stored attack and release
+    // as unitless values that nothing used, so they go back to the defaults.
+    fn convert_legacy_fields(&mut self) {
+        if self.legacy_threshold.is_none() && self.legacy_ratio.is_none() {
+            return;
+        }
+        let defaults = Self::default();
+        if let Some(threshold) = self.legacy_threshold.take() {
+            self.threshold = linear_to_db(threshold.0)
+                .clamp(*Self::THRESHOLD_RANGE.start(), *Self::THRESHOLD_RANGE.end());
+        }
+        if let Some(ratio) = self.legacy_ratio.take() {
+            // An inverted ratio of zero meant infinite compression.
+            self.ratio = if ratio.0 > 0.0 {
+                (1.0 / ratio.0).clamp(*Self::RATIO_RANGE.start(), *Self::RATIO_RANGE.end())
+            } else {
+                *Self::RATIO_RANGE.end()
+            };
+        }
+        self.attack = defaults.attack;
+        self.release = defaults.release;
+    }
+
+    fn update_coefficients(&mut self) {
+        let sample_rate = self.e.c.sample_rate();
+        self.e
+            .followers
+            .iter_mut()
+            .for_each(|f| f.update_times(self.attack, self.release, sample_rate));
+    }
+
+    // Compresses one channel's input sample according to the level of the
+    // detector sample, which is the input itself unless there's a key.
+    fn compress(
+        &mut self,
+        channel: usize,
+        detector_sample: Sample,
+        input_sample: Sample,
+    ) -> Sample {
+        let detector_sample = detector_sample.0;
+        let level = match self.detector {
+            CompressorDetector::Peak => self.e.followers[channel].follow(detector_sample.abs()),
+            CompressorDetector::Rms => self.e.followers[channel]
+                .follow(detector_sample * detector_sample)
+                .sqrt(),
+        };
+        let gain_reduction = self.gain_reduction_for_level(linear_to_db(level));
+        self.e.gain_reduction[channel] = gain_reduction;
+        input_sample * db_to_linear(self.makeup_gain - gain_reduction)
+    }
+
+    // The static gain curve: how many dB to reduce the given input level.
+    fn gain_reduction_for_level(&self, level_db: ParameterType) -> ParameterType {
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+        let overshoot = level_db - self.threshold;
+        if self.knee > 0.0 && overshoot.abs() * 2.0 <= self.knee {
+            let knee_overshoot = overshoot + self.knee / 2.0;
+            slope * knee_overshoot * knee_overshoot / (2.0 * self.knee)
+        } else if overshoot > 0.0 {
+            slope * overshoot
+        } else {
+            0.0
+        }
+    }
+
+    /// The current gain reduction in dB, as a non-negative number. If the
+    /// channels differ, this is the larger of the two.
+    pub fn gain_reduction(&self) -> ParameterType {
+        self.e.gain_reduction.iter().copied().fold(0.0, f64::max)
+    }
+
+    pub fn threshold(&self) -> ParameterType {
         self.threshold
     }
 
-    pub fn set_threshold(&mut self, threshold: Normal) {
+    pub fn set_threshold(&mut self, threshold: ParameterType) {
         self.threshold = threshold;
     }
 
-    pub fn ratio(&self) -> Ratio {
+    pub fn ratio(&self) -> ParameterType {
         self.ratio
     }
 
-    pub fn set_ratio(&mut self, ratio: Ratio) {
+    pub fn set_ratio(&mut self, ratio: ParameterType) {
         self.ratio = ratio;
     }
 
-    pub fn attack(&self) -> Normal {
+    pub fn knee(&self) -> ParameterType {
+        self.knee
+    }
+
+    pub fn set_knee(&mut self, knee: ParameterType) {
+        self.knee = knee;
+    }
+
+    pub fn attack(&self) -> Seconds {
         self.attack
     }
 
-    pub fn set_attack(&mut self, attack: Normal) {
-        self.attack = attack;
+    pub fn set_attack(&mut self, attack: Seconds) {
+        if self.attack != attack {
+            self.attack = attack;
+            self.update_coefficients();
+        }
     }
 
-    pub fn release(&self) -> Normal {
+    pub fn release(&self) -> Seconds {
         self.release
     }
 
-    pub fn set_release(&mut self, release: Normal) {
-        self.release = release;
+    pub fn set_release(&mut self, release: Seconds) {
+        if self.release != release {
+            self.release = release;
+            self.update_coefficients();
+        }
+    }
+
+    pub fn makeup_gain(&self) -> ParameterType {
+        self.makeup_gain
+    }
+
+    pub fn set_makeup_gain(&mut self, makeup_gain: ParameterType) {
+        self.makeup_gain = makeup_gain;
+    }
+
+    pub fn detector(&self) -> CompressorDetector {
+        self.detector
+    }
+
+    pub fn set_detector(&mut self, detector: CompressorDetector) {
+        if self.detector != detector {
+            self.detector = detector;
+
+            // The peak and RMS followers track different quantities.
+            self.e.followers.iter_mut().for_each(|f| f.reset());
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use float_cmp::approx_eq;
 
-    #[test]
-    fn basic_compressor() {
-        const THRESHOLD: SampleType = 0.25;
-        let mut fx = CompressorCoreBuilder::default()
-            .threshold(THRESHOLD.into())
-            .ratio(0.5.into())
+    // A compressor whose detector follows its input instantly, so that the
+    // static gain curve is easy to test.
+    fn instant_compressor(threshold: ParameterType, ratio: ParameterType) -> CompressorCore {
+        CompressorCoreBuilder::default()
+            .threshold(threshold)
+            .ratio(ratio)
+            .knee(0.0)
             .attack(0.0.into())
             .release(0.0.into())
             .build()
-            .unwrap();
-        assert_eq!(
-            fx.transform_channel(0, Sample::from(0.35)),
-            Sample::from((0.35 - THRESHOLD) * 0.5 + THRESHOLD)
+            .unwrap()
+    }
+
+    #[test]
+    fn basic_compressor() {
+        let mut fx = instant_compressor(-12.0, 2.0);
+        let output = fx.transform_channel(0, Sample::from(1.0));
+        assert!(
+            approx_eq!(f64, output.0, db_to_linear(-6.0), epsilon = 0.000001),
+            "12dB over the threshold at 2:1 should come out 6dB over it, but got {}",
+            linear_to_db(output.0)
+        );
+        assert!(approx_eq!(
+            f64,
+            fx.gain_reduction(),
+            6.0,
+            epsilon = 0.000001
+        ));
+
+        let output = fx.transform_channel(0, Sample::from(-1.0));
+        assert!(
+            approx_eq!(f64, output.0, -db_to_linear(-6.0), epsilon = 0.000001),
+            "negative samples should be compressed the same way"
         );
     }
 
     #[test]
     fn nothing_compressor() {
+        let mut fx = instant_compressor(-12.0, 1.0);
+        assert_eq!(
+            fx.transform_channel(0, Sample::from(0.9)),
+            Sample::from(0.9)
+        );
+
+        let mut fx = instant_compressor(-6.0, 4.0);
+        assert_eq!(
+            fx.transform_channel(0, Sample::from(0.25)),
+            Sample::from(0.25),
+            "levels below the threshold should pass through unchanged"
+        );
+        assert_eq!(fx.gain_reduction(), 0.0);
+    }
+
+    #[test]
+    fn infinite_compressor() {
+        let mut fx = instant_compressor(-12.0, f64::INFINITY);
+        let output = fx.transform_channel(0, Sample::from(0.9));
+        assert!(approx_eq!(
+            f64,
+            output.0,
+            db_to_linear(-12.0),
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn compressor_knee_and_makeup_gain() {
         let mut fx = CompressorCoreBuilder::default()
-            .threshold(0.25.into())
-            .ratio(1.0.into())
+            .threshold(-12.0)
+            .ratio(2.0)
+            .knee(6.0)
+            .makeup_gain(3.0)
             .attack(0.0.into())
             .release(0.0.into())
             .build()
             .unwrap();
+
+        // Right at the threshold, a soft knee is already compressing a little
+        // (a quarter of the way into the knee, at half the slope).
+        let _ = fx.transform_channel(0, Sample::from(db_to_linear(-12.0)));
+        assert!(approx_eq!(
+            f64,
+            fx.gain_reduction(),
+            0.375,
+            epsilon = 0.000001
+        ));
+
+        // Well above the knee, it's the same as a hard knee.
+        let output = fx.transform_channel(0, Sample::from(1.0));
+        assert!(approx_eq!(
+            f64,
+            fx.gain_reduction(),
+            6.0,
+            epsilon = 0.000001
+        ));
+        assert!(approx_eq!(
+            f64,
+            output.0,
+            db_to_linear(-6.0 + 3.0),
+            epsilon = 0.000001
+        ));
+
+        // Below the knee, only the makeup gain applies.
+        let output = fx.transform_channel(0, Sample::from(db_to_linear(-30.0)));
+        assert_eq!(fx.gain_reduction(), 0.0);
+        assert!(approx_eq!(
+            f64,
+            output.0,
+            db_to_linear(-27.0),
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn compressor_controls_cover_parameter_ranges() {
+        let mut fx = CompressorCore::default();
+        let set = |fx: &mut CompressorCore, name: &str, value: f64| {
+            let index = fx.control_index_for_name(name).unwrap();
+            assert_eq!(fx.control_name_for_index(index).unwrap(), name);
+            fx.control_set_param_by_index(index, ControlValue(value));
+        };
+
+        set(&mut fx, "threshold", 0.0);
+        assert_eq!(fx.threshold(), -60.0);
+        set(&mut fx, "threshold", 1.0);
+        assert_eq!(fx.threshold(), 0.0);
+        set(&mut fx, "ratio", 0.0);
         assert_eq!(
-            fx.transform_channel(0, Sample::from(0.35f32)),
-            Sample::from(0.35f32)
+            fx.ratio(),
+            1.0,
+            "the lowest ratio should mean no compression"
         );
+        set(&mut fx, "ratio", 1.0);
+        assert_eq!(fx.ratio(), 20.0);
+        set(&mut fx, "knee", 0.5);
+        assert_eq!(fx.knee(), 12.0);
+        set(&mut fx, "makeup-gain", 0.25);
+        assert_eq!(fx.makeup_gain(), 6.0);
+        set(&mut fx, "release", 0.5);
+        assert_eq!(fx.release(), Seconds(0.5));
+        assert_eq!(fx.control_index_count(), 6);
     }
 
     #[test]
-    fn infinite_compressor() {
+    fn compressor_attack_and_release() {
         let mut fx = CompressorCoreBuilder::default()
-            .threshold(0.25.into())
-            .ratio(0.0.into())
-            .attack(0.0.into())
-            .release(0.0.into())
+            .threshold(-12.0)
+            .ratio(4.0)
+            .knee(0.0)
+            .attack(0.01.into())
+            .release(0.1.into())
+            .detector(CompressorDetector::Rms)
             .build()
             .unwrap();
+        let sample_rate = fx.sample_rate().0;
+        let steady_state_reduction = 9.0;
+
+        let _ = fx.transform_channel(0, Sample::from(1.0));
+        let first_reduction = fx.gain_reduction();
+        assert!(
+            first_reduction < steady_state_reduction,
+            "a loud signal shouldn't be fully compressed right away"
+        );
+
+        // Ten time constants is plenty to settle.
+        for _ in 0..(sample_rate / 10) {
+            let _ = fx.transform_channel(0, Sample::from(1.0));
+        }
+        assert!(first_reduction < fx.gain_reduction());
+        assert!(approx_eq!(
+            f64,
+            fx.gain_reduction(),
+            steady_state_reduction,
+            epsilon = 0.01
+        ));
+
+        // Release is slower than attack, so after the same short time, the
+        // compressor should still be holding down the gain.
+        for _ in 0..(sample_rate / 100) {
+            let _ = fx.transform_channel(0, Sample::from(0.0));
+        }
+        assert!(fx.gain_reduction() > 0.0);
+        for _ in 0..sample_rate * 2 {
+            let _ = fx.transform_channel(0, Sample::from(0.0));
+        }
+        assert_eq!(fx.gain_reduction(), 0.0);
+    }
+
+    #[test]
+    fn compressor_listens_to_sidechain_key() {
+        let mut fx = instant_compressor(-12.0, 2.0);
+
+        // A quiet input with a loud key is turned down as if it were loud.
+        let _ = fx.transform_channel(SIDECHAIN_KEY_CHANNELS[0], Sample::from(1.0));
+        let output = fx.transform_channel(0, Sample::from(0.1));
+        assert!(approx_eq!(
+            f64,
+            fx.gain_reduction(),
+            6.0,
+            epsilon = 0.000001
+        ));
+        assert!(approx_eq!(
+            f64,
+            output.0,
+            0.1 * db_to_linear(-6.0),
+            epsilon = 0.000001
+        ));
+
+        // A key sample is good for only one frame.
+        let output = fx.transform_channel(0, Sample::from(0.1));
+        assert_eq!(output, Sample::from(0.1));
+
+        // The same, a buffer at a time.
+        let mut samples = [StereoSample::from(0.1); 2];
+        fx.transform_with_key(
+            &[StereoSample::from(1.0), StereoSample::SILENCE],
+            &mut samples,
+        );
+        assert!(approx_eq!(
+            f64,
+            samples[0].0 .0,
+            0.1 * db_to_linear(-6.0),
+            epsilon = 0.000001
+        ));
+        assert_eq!(samples[1], StereoSample::from(0.1));
+
         assert_eq!(
-            fx.transform_channel(0, Sample::from(0.35)),
-            Sample::from(0.25)
+            fx.transform_channel(7, Sample::from(0.1)),
+            Sample::from(0.1),
+            "A channel that the compressor doesn't know should pass through"
         );
     }
+
+    #[test]
+    fn legacy_compressor_settings_are_converted() {
+        let mut fx: CompressorCore = serde_json::from_str(
+            r#"{"threshold": 0.5, "ratio": 0.25, "attack": 0.9, "release": 0.9}"#,
+        )
+        .unwrap();
+        fx.after_deser();
+        assert!(
+            approx_eq!(f64, fx.threshold(), linear_to_db(0.5), epsilon = 0.000001),
+            "a linear threshold of 0.5 should become about -6dB, but got {}",
+            fx.threshold()
+        );
+        assert_eq!(fx.ratio(), 4.0, "an inverted ratio of 0.25 is 4:1");
+        assert_eq!(fx.attack(), CompressorCore::default().attack());
+
+        let mut fx: CompressorCore =
+            serde_json::from_str(r#"{"threshold": 1.0, "ratio": 0.0}"#).unwrap();
+        fx.after_deser();
+        assert_eq!(fx.threshold(), 0.0);
+        assert_eq!(fx.ratio(), *CompressorCore::RATIO_RANGE.end());
+
+        let json = serde_json::to_string(&fx).unwrap();
+        let mut reloaded: CompressorCore = serde_json::from_str(&json).unwrap();
+        reloaded.after_deser();
+        assert_eq!(reloaded.threshold(), fx.threshold());
+        assert_eq!(reloaded.ratio(), fx.ratio());
+    }
 }
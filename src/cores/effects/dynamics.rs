@@ -0,0 +1,98 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Building blocks shared by effects that change their gain according to a
+//! signal's level, such as compressors and gates.
+
+use crate::prelude::*;
+use std::ops::RangeInclusive;
+
+/// A one-pole smoother that follows a rectified signal, rising at the attack
+/// rate and falling at the release rate.
+#[derive(Debug, Default)]
+pub(crate) struct EnvelopeFollower {
+    envelope: f64,
+    attack_coefficient: f64,
+    release_coefficient: f64,
+}
+impl EnvelopeFollower {
+    /// Sets how quickly the follower responds. A zero time means it responds
+    /// instantly.
+    pub(crate) fn update_times(
+        &mut self,
+        attack: Seconds,
+        release: Seconds,
+        sample_rate: SampleRate,
+    ) {
+        self.attack_coefficient = Self::smoothing_coefficient(attack, sample_rate);
+        self.release_coefficient = Self::smoothing_coefficient(release, sample_rate);
+    }
+
+    // The coefficient that gets a follower about 63% of the way to a new level
+    // in the given time.
+    fn smoothing_coefficient(time: Seconds, sample_rate: SampleRate) -> f64 {
+        if time.0 <= 0.0 {
+            0.0
+        } else {
+            (-1.0 / (time.0 * sample_rate.0 as f64)).exp()
+        }
+    }
+
+    /// Takes in the next value and returns the new envelope.
+    pub(crate) fn follow(&mut self, input: f64) -> f64 {
+        let coefficient = if input > self.envelope {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.envelope = coefficient * self.envelope + (1.0 - coefficient) * input;
+        self.envelope
+    }
+
+    /// Forgets the current envelope.
+    pub(crate) fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+/// Holds the most recent frame of a sidechain key signal until the main input
+/// for the same frame arrives. See
+/// [SIDECHAIN_KEY_CHANNELS](super::SIDECHAIN_KEY_CHANNELS).
+#[derive(Debug, Default)]
+pub(crate) struct SidechainKey {
+    samples: [Option<Sample>; 2],
+}
+impl SidechainKey {
+    /// Remembers a key sample that arrived on one of the key channels.
+    pub(crate) fn set(&mut self, key_channel: usize, sample: Sample) {
+        self.samples[key_channel - super::SIDECHAIN_KEY_CHANNELS[0]] = Some(sample);
+    }
+
+    /// Returns the sample that should drive the detector for the given main
+    /// channel: the key if there is one for this frame, or else the input
+    /// itself.
+    pub(crate) fn take_or(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        self.samples[channel].take().unwrap_or(input_sample)
+    }
+}
+
+/// Converts a linear amplitude to dBFS.
+pub(crate) fn linear_to_db(level: f64) -> ParameterType {
+    // Anything quieter than this is silence for our purposes, and it keeps
+    // log10() away from zero.
+    const FLOOR: f64 = 1.0e-10;
+    20.0 * level.max(FLOOR).log10()
+}
+
+/// Converts dBFS to a linear amplitude.
+pub(crate) fn db_to_linear(db: ParameterType) -> f64 {
+    10.0f64.powf(db / 20.0)
+}
+
+/// Maps an automation value, which is always 0..=1, onto a parameter's own
+/// range, such as -60..=0 dB for a threshold.
+pub(crate) fn control_value_to_range(
+    value: ControlValue,
+    range: &RangeInclusive<ParameterType>,
+) -> ParameterType {
+    range.start() + value.0.clamp(0.0, 1.0) * (range.end() - range.start())
+}
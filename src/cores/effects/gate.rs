@@ -0,0 +1,300 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+use super::{
+    dynamics::{
+        control_value_to_range, db_to_linear, linear_to_db, EnvelopeFollower, SidechainKey,
+    },
+    SIDECHAIN_KEY_CHANNELS,
+};
+use crate::prelude::*;
+use delegate::delegate;
+use derivative::Derivative;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A noise gate. It lets audio through while the level is above the threshold,
+/// and turns it down by the range while it's below. Like [CompressorCore], it
+/// can listen to a sidechain key rather than its own input. See
+/// [TransformsAudioWithKey].
+///
+/// Automation moves the threshold and range across
+/// [GateCore::THRESHOLD_RANGE] and [GateCore::RANGE_RANGE].
+#[derive(Debug, Builder, Derivative, Serialize, Deserialize)]
+#[derivative(Default)]
+#[builder(default, build_fn(private, name = "build_from_builder"))]
+#[serde(rename_all = "kebab-case")]
+pub struct GateCore {
+    /// The level, in dBFS, above which the gate opens.
+    #[derivative(Default(value = "-40.0"))]
+    threshold: ParameterType,
+
+    /// How far, in dB, the gate turns down the audio while it's closed.
+    #[derivative(Default(value = "80.0"))]
+    range: ParameterType,
+
+    /// How long the gate takes to open.
+    #[derivative(Default(value = "0.001.into()"))]
+    attack: Seconds,
+
+    /// How long the gate takes to close.
+    #[derivative(Default(value = "0.1.into()"))]
+    release: Seconds,
+
+    #[serde(skip)]
+    #[builder(setter(skip))]
+    e: GateCoreEphemerals,
+}
+#[derive(Debug, Default)]
+pub struct GateCoreEphemerals {
+    // Peak level of whatever the gate is listening to. Rises instantly so that
+    // the gate doesn't miss transients, and falls at the release rate so that
+    // it doesn't chatter.
+    level_followers: [EnvelopeFollower; 2],
+    // Smooths the gate's gain so that opening and closing don't click.
+    gain_followers: [EnvelopeFollower; 2],
+    key: SidechainKey,
+
+    c: Configurables,
+}
+impl GateCoreBuilder {
+    /// The overridden Builder build() method.
+    pub fn build(&self) -> Result<GateCore, GateCoreBuilderError> {
+        match self.build_from_builder() {
+            Ok(mut s) => {
+                s.after_deser();
+                Ok(s)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl Serializable for GateCore {
+    fn after_deser(&mut self) {
+        self.update_coefficients();
+    }
+}
+impl TransformsAudio for GateCore {
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        match channel {
+            0 | 1 => {
+                let detector_sample = self.e.key.take_or(channel, input_sample);
+                self.gate(channel, detector_sample, input_sample)
+            }
+            _ if SIDECHAIN_KEY_CHANNELS.contains(&channel) => {
+                self.e.key.set(channel, input_sample);
+                input_sample
+            }
+            _ => input_sample,
+        }
+    }
+}
+impl TransformsAudioWithKey for GateCore {
+    fn transform_with_key(&mut self, key: &[StereoSample], samples: &mut [StereoSample]) {
+        for (sample, key) in samples.iter_mut().zip(key.iter()) {
+            *sample = StereoSample(self.gate(0, key.0, sample.0), self.gate(1, key.1, sample.1));
+        }
+    }
+}
+impl Controllable for GateCore {
+    fn control_index_count(&self) -> usize {
+        Self::CONTROL_NAMES.len()
+    }
+
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        Self::CONTROL_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(ControlIndex)
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        Self::CONTROL_NAMES.get(index.0).map(|n| n.to_string())
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        match index.0 {
+            0 => self.set_threshold(control_value_to_range(value, &Self::THRESHOLD_RANGE)),
+            1 => self.set_range(control_value_to_range(value, &Self::RANGE_RANGE)),
+            2 => self.set_attack(Seconds::from(value.0)),
+            3 => self.set_release(Seconds::from(value.0)),
+            _ => {}
+        }
+    }
+}
+impl Configurable for GateCore {
+    delegate! {
+        to self.e.c {
+            fn sample_rate(&self) -> SampleRate;
+            fn tempo(&self) -> Tempo;
+            fn update_tempo(&mut self, tempo: Tempo);
+            fn time_signature(&self) -> TimeSignature;
+            fn update_time_signature(&mut self, time_signature: TimeSignature);
+        }
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.c.update_sample_rate(sample_rate);
+        self.update_coefficients();
+    }
+}
+impl GateCore {
+    /// The thresholds, in dBFS, that automation covers.
+    pub const THRESHOLD_RANGE: RangeInclusive<ParameterType> = -80.0..=0.0;
+    /// The ranges, in dB, that automation covers.
+    pub const RANGE_RANGE: RangeInclusive<ParameterType> = 0.0..=80.0;
+
+    // In control index order. Attack and release are in seconds, so they're
+    // already in 0..=1.
+    const CONTROL_NAMES: [&'static str; 4] = ["threshold", "range", "attack", "release"];
+
+    fn update_coefficients(&mut self) {
+        let sample_rate = self.e.c.sample_rate();
+        self.e
+            .level_followers
+            .iter_mut()
+            .for_each(|f| f.update_times(Seconds(0.0), self.release, sample_rate));
+        self.e
+            .gain_followers
+            .iter_mut()
+            .for_each(|f| f.update_times(self.attack, self.release, sample_rate));
+    }
+
+    // Gates one channel's input sample according to the level of the detector
+    // sample, which is the input itself unless there's a key.
+    fn gate(&mut self, channel: usize, detector_sample: Sample, input_sample: Sample) -> Sample {
+        let level = self.e.level_followers[channel].follow(detector_sample.0.abs());
+        let target_gain = if linear_to_db(level) >= self.threshold {
+            1.0
+        } else {
+            db_to_linear(-self.range)
+        };
+        input_sample * self.e.gain_followers[channel].follow(target_gain)
+    }
+
+    pub fn threshold(&self) -> ParameterType {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: ParameterType) {
+        self.threshold = threshold;
+    }
+
+    pub fn range(&self) -> ParameterType {
+        self.range
+    }
+
+    pub fn set_range(&mut self, range: ParameterType) {
+        self.range = range;
+    }
+
+    pub fn attack(&self) -> Seconds {
+        self.attack
+    }
+
+    pub fn set_attack(&mut self, attack: Seconds) {
+        if self.attack != attack {
+            self.attack = attack;
+            self.update_coefficients();
+        }
+    }
+
+    pub fn release(&self) -> Seconds {
+        self.release
+    }
+
+    pub fn set_release(&mut self, release: Seconds) {
+        if self.release != release {
+            self.release = release;
+            self.update_coefficients();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn gate_opens_and_closes() {
+        let mut fx = GateCoreBuilder::default()
+            .threshold(-20.0)
+            .range(60.0)
+            .attack(0.0.into())
+            .release(0.0.into())
+            .build()
+            .unwrap();
+        assert_eq!(
+            fx.transform_channel(0, Sample::from(0.5)),
+            Sample::from(0.5),
+            "a loud signal should pass through"
+        );
+        let output = fx.transform_channel(0, Sample::from(0.01));
+        assert!(approx_eq!(
+            f64,
+            output.0,
+            0.01 * db_to_linear(-60.0),
+            epsilon = 0.000001
+        ));
+
+        // Keyed by a loud signal, the same quiet input passes through.
+        let _ = fx.transform_channel(SIDECHAIN_KEY_CHANNELS[1], Sample::from(0.5));
+        assert_eq!(
+            fx.transform_channel(1, Sample::from(0.01)),
+            Sample::from(0.01)
+        );
+        let mut samples = [StereoSample::from(0.01)];
+        fx.transform_with_key(&[StereoSample::from(0.5)], &mut samples);
+        assert_eq!(samples[0], StereoSample::from(0.01));
+
+        assert_eq!(
+            fx.transform_channel(7, Sample::from(0.01)),
+            Sample::from(0.01),
+            "A channel that the gate doesn't know should pass through"
+        );
+    }
+
+    #[test]
+    fn gate_controls_cover_parameter_ranges() {
+        let mut fx = GateCore::default();
+        let threshold = fx.control_index_for_name("threshold").unwrap();
+        let range = fx.control_index_for_name("range").unwrap();
+        fx.control_set_param_by_index(threshold, ControlValue(0.5));
+        assert_eq!(fx.threshold(), -40.0);
+        fx.control_set_param_by_index(range, ControlValue::MAX);
+        assert_eq!(fx.range(), 80.0);
+        fx.control_set_param_by_name("release", ControlValue(0.25));
+        assert_eq!(fx.release(), Seconds(0.25));
+    }
+
+    #[test]
+    fn gate_closes_smoothly() {
+        let mut fx = GateCoreBuilder::default()
+            .threshold(-20.0)
+            .attack(0.0.into())
+            .release(0.05.into())
+            .build()
+            .unwrap();
+        let _ = fx.transform_channel(0, Sample::from(0.5));
+
+        // The level follower has to decay below the threshold first, and then
+        // the gain eases down rather than dropping all at once.
+        let mut previous = 0.05;
+        let mut saw_partial_gain = false;
+        for _ in 0..fx.sample_rate().0 {
+            let output = fx.transform_channel(0, Sample::from(0.05)).0;
+            assert!(output <= previous);
+            saw_partial_gain |= output > 0.0001 && output < 0.049;
+            previous = output;
+        }
+        assert!(saw_partial_gain);
+        assert!(previous < 0.0001);
+    }
+}
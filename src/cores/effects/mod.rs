@@ -4,10 +4,11 @@
 //! [TransformsAudio](crate::traits::TransformsAudio) trait. Examples are
 //! [Reverb] and filters.
 
+pub(crate) use dynamics::{db_to_linear, linear_to_db};
 pub use {
     bitcrusher::{BitcrusherCore, BitcrusherCoreBuilder},
     chorus::{ChorusCore, ChorusCoreBuilder},
-    compressor::{CompressorCore, CompressorCoreBuilder},
+    compressor::{CompressorCore, CompressorCoreBuilder, CompressorDetector},
     filter::{
         BiQuadFilterAllPassCore, BiQuadFilterAllPassCoreBuilder, BiQuadFilterBandPassCore,
         BiQuadFilterBandPassCoreBuilder, BiQuadFilterBandStopCore, BiQuadFilterBandStopCoreBuilder,
@@ -17,6 +18,7 @@ pub use {
         BiQuadFilterLowShelfCoreBuilder, BiQuadFilterNoneCoreBuilder, BiQuadFilterPeakingEqCore,
         BiQuadFilterPeakingEqCoreBuilder,
     },
+    gate::{GateCore, GateCoreBuilder},
     limiter::{LimiterCore, LimiterCoreBuilder},
     test::*,
 };
@@ -24,6 +26,18 @@ pub use {
 mod bitcrusher;
 mod chorus;
 mod compressor;
+mod dynamics;
 mod filter;
+mod gate;
 mod limiter;
 mod test;
+
+// Effects that take a sidechain key implement
+// [TransformsAudioWithKey](crate::traits::TransformsAudioWithKey), but the
+// orchestrator holds them as [Entity](crate::traits::Entity)s, which live in
+// the ensnare crate and can't be asked for that trait. So the orchestrator
+// hands them the key through
+// [transform_channel()](crate::traits::TransformsAudio::transform_channel) on
+// these extra channel numbers (left, then right), one frame at a time, just
+// before the main input for the same frame.
+pub(crate) const SIDECHAIN_KEY_CHANNELS: [usize; 2] = [2, 3];
@@ -112,13 +112,12 @@ impl DrumkitCore {
                         if let Ok(file) = Paths::global().search_and_open(path.as_path()) {
                             if let Ok(samples) = SamplerCore::read_samples_from_file(&file) {
                                 let note = item.note as u8;
-                                Ok((
-                                    u7::from(note),
-                                    SamplerVoice::new_with_samples(
-                                        Arc::new(samples),
-                                        MidiNote::from_repr(note as usize).unwrap().into(),
-                                    ),
-                                ))
+                                let mut voice = SamplerVoice::new_with_samples(
+                                    Arc::new(samples),
+                                    MidiNote::from_repr(note as usize).unwrap().into(),
+                                );
+                                voice.amp_envelope_mut().set_release(Self::drum_release());
+                                Ok((u7::from(note), voice))
                             } else {
                                 Err(anyhow!("Unable to load sample from file {:?}.", path))
                             }
@@ -138,6 +137,12 @@ impl DrumkitCore {
         }
     }
 
+    // Drum notes are usually short, so a long release lets each hit ring out
+    // rather than choking it at note-off.
+    fn drum_release() -> Normal {
+        Envelope::from_seconds_to_normal(Seconds(1.0))
+    }
+
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
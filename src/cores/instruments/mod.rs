@@ -6,7 +6,10 @@
 pub use {
     drumkit::DrumkitCore,
     fm::{FmSynthCore, FmSynthCoreBuilder},
-    sampler::{SamplerCore, SamplerVoice},
+    sampler::{
+        Interpolation, LoopMode, SampleLoop, SampleMetadata, SampleZone, SamplerCore, SamplerVoice,
+    },
+    sfz::SfzInstrument,
     subtractive::{
         LfoRouting, SubtractiveSynthCore, SubtractiveSynthCoreBuilder, SubtractiveSynthVoice,
         PATCH_DIR as SUBTRACTIVE_PATCH_DIR,
@@ -19,5 +22,6 @@ pub use {
 mod drumkit;
 mod fm;
 mod sampler;
+mod sfz;
 mod subtractive;
 mod test;
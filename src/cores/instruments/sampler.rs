@@ -1,17 +1,247 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
 use crate::{
+    cores::effects::{BiQuadFilterLowPass24dbCore, BiQuadFilterLowPass24dbCoreBuilder},
     prelude::*,
-    util::{
-        library::{SampleLibrary, SampleSource},
-        Paths,
-    },
+    util::library::SampleSource,
 };
 use anyhow::{anyhow, Result};
+use derivative::Derivative;
 use ensnare_proc_macros::Control;
 use hound::WavReader;
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Seek},
+    sync::{Arc, OnceLock},
+};
+use strum_macros::{Display, EnumIter};
+
+/// How a [SamplerVoice] reads between samples, which it has to do whenever it
+/// plays at a pitch other than the sample's root.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display, EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interpolation {
+    /// Uses the sample at or before the read position. It's the cheapest, but
+    /// anything off the root pitch sounds harsh and aliased.
+    Truncate,
+    /// Draws a straight line between the two nearest samples.
+    #[default]
+    Linear,
+    /// Fits a smooth Catmull-Rom curve through the four nearest samples.
+    #[strum(serialize = "Cubic Hermite")]
+    CubicHermite,
+    /// Convolves the samples with a windowed sinc kernel. It's the most
+    /// expensive, and the only one that also filters out frequencies that
+    /// would alias when the pitch goes up.
+    #[strum(serialize = "Windowed Sinc")]
+    WindowedSinc,
+}
+impl Interpolation {
+    // How many zero crossings the windowed sinc kernel reaches on either side
+    // of its center, at the root pitch.
+    const SINC_ZERO_CROSSINGS: usize = 16;
+    // Kernel lookup table entries per zero crossing.
+    const SINC_TABLE_RESOLUTION: usize = 512;
+    // The lowest cutoff the windowed sinc uses when the pitch goes up. It keeps
+    // the kernel from growing without bound at extreme pitches.
+    const SINC_MIN_CUTOFF: f64 = 1.0 / 8.0;
+
+    /// The value of the samples at a fractional position. `step` is how far
+    /// the position advances for each frame of output. Positions outside the
+    /// samples are silent.
+    pub fn interpolate(&self, samples: &[StereoSample], position: f64, step: f64) -> StereoSample {
+        let index = position.floor() as isize;
+        let fraction = position - position.floor();
+        let sample_at = |i: isize| {
+            if i >= 0 && (i as usize) < samples.len() {
+                samples[i as usize]
+            } else {
+                StereoSample::SILENCE
+            }
+        };
+        match self {
+            Interpolation::Truncate => sample_at(index),
+            Interpolation::Linear => {
+                sample_at(index) * (1.0 - fraction) + sample_at(index + 1) * fraction
+            }
+            Interpolation::CubicHermite => {
+                let f = fraction;
+                let weights = [
+                    ((-0.5 * f + 1.0) * f - 0.5) * f,
+                    (1.5 * f - 2.5) * f * f + 1.0,
+                    ((-1.5 * f + 2.0) * f + 0.5) * f,
+                    (0.5 * f - 0.5) * f * f,
+                ];
+                (-1..=2)
+                    .zip(weights)
+                    .fold(StereoSample::SILENCE, |sum, (offset, weight)| {
+                        sum + sample_at(index + offset) * weight
+                    })
+            }
+            Interpolation::WindowedSinc => {
+                // Stretching the kernel lowers its cutoff along with the new
+                // Nyquist frequency when the pitch goes up.
+                let cutoff = (1.0 / step.abs()).clamp(Self::SINC_MIN_CUTOFF, 1.0);
+                let half_width = (Self::SINC_ZERO_CROSSINGS as f64 / cutoff).ceil() as isize;
+                let mut sum = StereoSample::SILENCE;
+                for i in (index - half_width + 1)..=(index + half_width) {
+                    let weight = cutoff * Self::windowed_sinc((position - i as f64) * cutoff);
+                    sum += sample_at(i) * weight;
+                }
+                sum
+            }
+        }
+    }
+
+    // The windowed sinc kernel, looked up in a table that's built on first use.
+    fn windowed_sinc(x: f64) -> f64 {
+        static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+        let table = TABLE.get_or_init(|| {
+            let len = Self::SINC_ZERO_CROSSINGS * Self::SINC_TABLE_RESOLUTION;
+            (0..=len)
+                .map(|i| {
+                    Self::exact_windowed_sinc(
+                        i as f64 / Self::SINC_TABLE_RESOLUTION as f64,
+                        Self::SINC_ZERO_CROSSINGS as f64,
+                    )
+                })
+                .collect()
+        });
+        let x = x.abs() * Self::SINC_TABLE_RESOLUTION as f64;
+        let i = x as usize;
+        if i + 1 >= table.len() {
+            return 0.0;
+        }
+        table[i] + (table[i + 1] - table[i]) * (x - i as f64)
+    }
+
+    // A sinc function tapered to zero at +/- half_width by a Blackman window.
+    fn exact_windowed_sinc(x: f64, half_width: f64) -> f64 {
+        if x.abs() >= half_width {
+            return 0.0;
+        }
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        };
+        let w = x / half_width;
+        sinc * (0.42 + 0.5 * (PI * w).cos() + 0.08 * (2.0 * PI * w).cos())
+    }
+}
+
+/// How a [SamplerVoice] repeats the loop in its sample.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display, EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoopMode {
+    /// Plays the sample once, ignoring the loop.
+    #[default]
+    Off,
+    /// Jumps from the end of the loop back to its start.
+    Forward,
+    /// Plays the loop forward, then backward, then forward again.
+    #[strum(serialize = "Ping-Pong")]
+    PingPong,
+}
+
+/// A span of a sample that a [SamplerVoice] repeats, so that a short sample
+/// can sustain for as long as a key is held.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SampleLoop {
+    pub mode: LoopMode,
+    /// The first frame of the loop.
+    pub start: usize,
+    /// The frame just past the end of the loop.
+    pub end: usize,
+    /// If true, the loop repeats only while the note is held, and the rest of
+    /// the sample plays after note-off. Otherwise the note stops at note-off.
+    pub is_sustain_only: bool,
+}
+impl SampleLoop {
+    // Whether the loop can be played in a sample of the given length.
+    fn is_playable(&self, sample_count: usize) -> bool {
+        let min_len = if self.mode == LoopMode::PingPong {
+            2
+        } else {
+            1
+        };
+        self.mode != LoopMode::Off && self.end <= sample_count && self.start + min_len <= self.end
+    }
+}
+
+/// What a WAV file says about how it should be played, beyond its samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SampleMetadata {
+    /// The pitch at which the sample plays back unchanged.
+    pub root: Option<FrequencyHz>,
+    pub sample_loop: Option<SampleLoop>,
+}
+
+/// Maps a sample onto a range of keys and velocities, so that an instrument can
+/// use a different recording for each part of the keyboard, and for soft and
+/// hard playing.
+#[derive(Debug, Clone, PartialEq, Derivative, Serialize, Deserialize)]
+#[derivative(Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SampleZone {
+    pub source: SampleSource,
+    /// The pitch at which the sample plays back unchanged. If None, it comes
+    /// from the sample file, or else it's 440Hz.
+    pub root: Option<FrequencyHz>,
+    /// Overrides the loop in the sample file's metadata, if any.
+    pub sample_loop: Option<SampleLoop>,
+    /// If there's no [SampleZone::sample_loop], overrides whether the sample
+    /// file's loop repeats only while the note is held.
+    pub is_file_loop_sustain_only: Option<bool>,
+
+    /// The lowest MIDI key that plays this zone.
+    pub lowest_key: u8,
+    /// The highest MIDI key that plays this zone.
+    #[derivative(Default(value = "127"))]
+    pub highest_key: u8,
+    /// The lowest note-on velocity that plays this zone.
+    pub lowest_velocity: u8,
+    /// The highest note-on velocity that plays this zone.
+    #[derivative(Default(value = "127"))]
+    pub highest_velocity: u8,
+
+    /// Zones in the same group that cover the same note take turns playing
+    /// it, so that repeated notes don't all sound identical.
+    pub round_robin_group: Option<usize>,
+}
+impl SampleZone {
+    /// Whether a note with this key and velocity plays this zone.
+    pub fn contains(&self, key: u7, velocity: u7) -> bool {
+        (self.lowest_key..=self.highest_key).contains(&key.as_int())
+            && (self.lowest_velocity..=self.highest_velocity).contains(&velocity.as_int())
+    }
+
+    // The loop that this zone plays, given the one in its sample file.
+    fn sample_loop_with(&self, file_loop: Option<SampleLoop>) -> SampleLoop {
+        self.sample_loop
+            .or_else(|| {
+                file_loop.map(|file_loop| SampleLoop {
+                    is_sustain_only: self
+                        .is_file_loop_sustain_only
+                        .unwrap_or(file_loop.is_sustain_only),
+                    ..file_loop
+                })
+            })
+            .unwrap_or_default()
+    }
+}
+
+// A zone's sample, ready to play.
+#[derive(Debug, Clone)]
+struct LoadedZone {
+    samples: Arc<Vec<StereoSample>>,
+    root: FrequencyHz,
+    sample_loop: SampleLoop,
+}
 
 /// One sampler voice. Combine multiple of these to make a sampling synth.
 #[derive(Debug, Default)]
@@ -21,76 +251,113 @@ pub struct SamplerVoice {
 
     root_frequency: FrequencyHz,
     frequency: FrequencyHz,
+    interpolation: Interpolation,
+    sample_loop: SampleLoop,
+
+    amp_envelope: Envelope,
+    // How much softer notes are quieter, and how much darker.
+    velocity_to_gain: Normal,
+    velocity_to_filter: Normal,
+    filter: BiQuadFilterLowPass24dbCore,
+
+    note_on_key: u7,
+    note_on_velocity: u7,
+    steal_is_underway: bool,
+    // The zone that the next note plays, if it's different from the last one.
+    next_zone: Option<LoadedZone>,
+    velocity_gain: f64,
 
     was_reset: bool,
     is_playing: bool,
+    // Set at note-off. A sustain loop then lets the rest of the sample play.
+    is_released: bool,
+    // Whether a ping-pong loop is on its way backward.
+    is_reversed: bool,
     sample_pointer: ParameterType,
     sample_pointer_delta: ParameterType,
+
+    amp_envelope_buffer: GenerationBuffer<Normal>,
 }
 impl IsVoice<StereoSample> for SamplerVoice {}
 impl IsStereoSampleVoice for SamplerVoice {}
 impl PlaysNotes for SamplerVoice {
     fn is_playing(&self) -> bool {
-        self.is_playing
+        self.is_playing && !self.amp_envelope.is_idle()
     }
 
-    #[allow(unused_variables)]
     fn note_on(&mut self, key: u7, velocity: u7) {
-        self.is_playing = true;
-        self.sample_pointer = 0.0;
-        self.frequency = MidiNote::from_repr(key.as_int() as usize).unwrap().into();
-        self.sample_pointer_delta = (self.frequency / self.root_frequency).into();
+        if self.is_playing() {
+            // Fade out the current note before jumping back to the start of the
+            // sample, which would otherwise click.
+            self.steal_is_underway = true;
+            self.note_on_key = key;
+            self.note_on_velocity = velocity;
+            self.amp_envelope.trigger_shutdown();
+        } else {
+            self.start_note(key, velocity);
+        }
     }
 
-    #[allow(unused_variables)]
-    fn aftertouch(&mut self, velocity: u7) {
-        todo!()
+    fn aftertouch(&mut self, _velocity: u7) {
+        // TODO: do something
     }
 
-    #[allow(unused_variables)]
-    fn note_off(&mut self, velocity: u7) {
-        self.is_playing = false;
-        self.sample_pointer = 0.0;
+    fn note_off(&mut self, _velocity: u7) {
+        // A note that ends before a steal finishes never starts.
+        self.steal_is_underway = false;
+        if self.is_playing() {
+            self.amp_envelope.trigger_release();
+        }
+        self.is_released = true;
+        if self.sample_loop.is_sustain_only {
+            self.is_reversed = false;
+        }
     }
 }
 impl Generates<StereoSample> for SamplerVoice {
     fn generate(&mut self, values: &mut [StereoSample]) -> bool {
         let mut generated_signal = false;
+        self.amp_envelope_buffer.resize(values.len());
+        let is_playing = self.is_playing();
+        if is_playing {
+            self.amp_envelope
+                .generate(self.amp_envelope_buffer.buffer_mut());
+        }
 
-        for value in values {
-            *value = {
-                if self.is_playing {
-                    if let Some(samples) = self.samples.as_ref() {
-                        if samples.len() != 0 {
-                            generated_signal = true;
-                            samples[self.sample_pointer as usize]
-                        } else {
-                            StereoSample::SILENCE
-                        }
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = match self.samples.as_ref() {
+                Some(samples) if is_playing && self.is_playing => {
+                    generated_signal = true;
+                    let sample = self.interpolation.interpolate(
+                        samples,
+                        self.sample_pointer,
+                        self.sample_pointer_delta,
+                    );
+                    let sample = if self.velocity_to_filter.0 > 0.0 {
+                        StereoSample(
+                            self.filter.transform_channel(0, sample.0),
+                            self.filter.transform_channel(1, sample.1),
+                        )
                     } else {
-                        StereoSample::SILENCE
-                    }
-                } else {
-                    StereoSample::SILENCE
+                        sample
+                    };
+                    sample * (self.amp_envelope_buffer.buffer()[i].0 * self.velocity_gain)
                 }
+                _ => StereoSample::SILENCE,
             };
 
-            if self.is_playing {
-                if !self.was_reset {
-                    self.sample_pointer += self.sample_pointer_delta;
-                }
-                if let Some(samples) = self.samples.as_ref() {
-                    debug_assert_ne!(samples.len(), 0);
-                    while self.sample_pointer as usize >= samples.len() {
-                        self.is_playing = false;
-                        self.sample_pointer -= samples.len() as f64;
-                    }
-                }
+            if self.is_playing() && !self.was_reset {
+                self.advance();
             }
             if self.was_reset {
                 self.was_reset = false;
             }
         }
+
+        if self.steal_is_underway && self.amp_envelope.is_idle() {
+            self.steal_is_underway = false;
+            self.start_note(self.note_on_key, self.note_on_velocity);
+        }
         generated_signal
     }
 }
@@ -98,10 +365,19 @@ impl Serializable for SamplerVoice {}
 impl Configurable for SamplerVoice {
     fn update_sample_rate(&mut self, sample_rate: SampleRate) {
         self.sample_rate = sample_rate;
+        self.amp_envelope.update_sample_rate(sample_rate);
+        self.filter.update_sample_rate(sample_rate);
         self.was_reset = true;
     }
 }
 impl SamplerVoice {
+    /// The filter cutoff for a note at full velocity, which leaves the filter
+    /// all but open.
+    const OPEN_CUTOFF: ParameterType = 20000.0;
+    /// How far a note at zero velocity closes the filter when
+    /// velocity-to-filter is at its maximum.
+    const VELOCITY_FILTER_OCTAVES: ParameterType = 8.0;
+
     pub fn new_with_samples(samples: Arc<Vec<StereoSample>>, root_frequency: FrequencyHz) -> Self {
         if !root_frequency.0.is_normal() {
             panic!("strange number given for root frequency: {root_frequency}");
@@ -116,20 +392,178 @@ impl SamplerVoice {
             samples,
             root_frequency,
             frequency: Default::default(),
+            interpolation: Default::default(),
+            sample_loop: Default::default(),
+            amp_envelope: Self::default_amp_envelope(),
+            velocity_to_gain: Normal::maximum(),
+            velocity_to_filter: Normal::minimum(),
+            filter: BiQuadFilterLowPass24dbCoreBuilder::default()
+                .build()
+                .unwrap(),
+            note_on_key: Default::default(),
+            note_on_velocity: Default::default(),
+            steal_is_underway: Default::default(),
+            next_zone: Default::default(),
+            velocity_gain: 1.0,
             was_reset: true,
             is_playing: Default::default(),
+            is_released: Default::default(),
+            is_reversed: Default::default(),
             sample_pointer: Default::default(),
             sample_pointer_delta: Default::default(),
+            amp_envelope_buffer: Default::default(),
         }
     }
 
+    /// An envelope that's quick enough not to blur the sample, but that fades
+    /// in and out rather than clicking.
+    pub fn default_amp_envelope() -> Envelope {
+        EnvelopeBuilder::default()
+            .attack(Envelope::from_seconds_to_normal(Seconds(0.001)))
+            .sustain(Normal::maximum())
+            .release(Envelope::from_seconds_to_normal(Seconds(0.05)))
+            .build()
+            .unwrap()
+    }
+
+    fn start_note(&mut self, key: u7, velocity: u7) {
+        if let Some(zone) = self.next_zone.take() {
+            self.samples = (!zone.samples.is_empty()).then_some(zone.samples);
+            self.root_frequency = zone.root;
+            self.set_sample_loop(zone.sample_loop);
+        }
+        self.is_playing = true;
+        self.is_released = false;
+        self.is_reversed = false;
+        self.sample_pointer = 0.0;
+        self.frequency = MidiNote::from_repr(key.as_int() as usize).unwrap().into();
+        self.sample_pointer_delta = (self.frequency / self.root_frequency).into();
+
+        let velocity = velocity.as_int() as f64 / 127.0;
+        // A square law sounds more even across the range than a straight line.
+        self.velocity_gain = 1.0 - self.velocity_to_gain.0 * (1.0 - velocity * velocity);
+        if self.velocity_to_filter.0 > 0.0 {
+            let octaves =
+                self.velocity_to_filter.0 * (1.0 - velocity) * Self::VELOCITY_FILTER_OCTAVES;
+            let open_cutoff = Self::OPEN_CUTOFF.min(self.sample_rate.0 as f64 * 0.45);
+            self.filter
+                .set_cutoff(FrequencyHz::from(open_cutoff * 2.0f64.powf(-octaves)));
+        }
+        self.amp_envelope.trigger_attack();
+    }
+
     pub fn set_root_frequency(&mut self, root_frequency: FrequencyHz) {
         self.root_frequency = root_frequency;
     }
+
+    // Switches to another zone's sample when the next note starts. A note that
+    // is still fading out keeps its own sample until it's done.
+    fn set_next_zone(&mut self, zone: LoadedZone) {
+        self.next_zone = Some(zone);
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn sample_loop(&self) -> SampleLoop {
+        self.sample_loop
+    }
+
+    pub fn set_sample_loop(&mut self, sample_loop: SampleLoop) {
+        self.sample_loop = sample_loop;
+        self.is_reversed = false;
+    }
+
+    pub fn amp_envelope(&self) -> &Envelope {
+        &self.amp_envelope
+    }
+
+    pub fn amp_envelope_mut(&mut self) -> &mut Envelope {
+        &mut self.amp_envelope
+    }
+
+    pub fn velocity_to_gain(&self) -> Normal {
+        self.velocity_to_gain
+    }
+
+    /// Sets how much quieter soft notes are. At zero, velocity doesn't change
+    /// the gain; at the maximum, a note at zero velocity is silent.
+    pub fn set_velocity_to_gain(&mut self, velocity_to_gain: Normal) {
+        self.velocity_to_gain = velocity_to_gain;
+    }
+
+    pub fn velocity_to_filter(&self) -> Normal {
+        self.velocity_to_filter
+    }
+
+    /// Sets how much darker soft notes are. At zero, the low-pass filter is
+    /// bypassed.
+    pub fn set_velocity_to_filter(&mut self, velocity_to_filter: Normal) {
+        self.velocity_to_filter = velocity_to_filter;
+    }
+
+    fn has_playable_loop(&self) -> bool {
+        self.samples
+            .as_ref()
+            .is_some_and(|samples| self.sample_loop.is_playable(samples.len()))
+    }
+
+    // Moves the read position along by one frame, following the loop while it
+    // applies, and stops the voice when it runs off the end of the sample.
+    fn advance(&mut self) {
+        let Some(sample_count) = self.samples.as_ref().map(|samples| samples.len()) else {
+            return;
+        };
+        debug_assert_ne!(sample_count, 0);
+        if self.is_reversed {
+            self.sample_pointer -= self.sample_pointer_delta;
+        } else {
+            self.sample_pointer += self.sample_pointer_delta;
+        }
+
+        let loop_has_ended = self.is_released && self.sample_loop.is_sustain_only;
+        if !loop_has_ended && self.has_playable_loop() {
+            let start = self.sample_loop.start as f64;
+            let end = self.sample_loop.end as f64;
+            match self.sample_loop.mode {
+                LoopMode::Off => {}
+                LoopMode::Forward => {
+                    if self.sample_pointer >= end {
+                        self.sample_pointer = start + (self.sample_pointer - end) % (end - start);
+                    }
+                }
+                LoopMode::PingPong => {
+                    // Bounce off the loop's last frame and its first one.
+                    let last = end - 1.0;
+                    loop {
+                        if self.sample_pointer > last {
+                            self.sample_pointer = 2.0 * last - self.sample_pointer;
+                            self.is_reversed = true;
+                        } else if self.is_reversed && self.sample_pointer < start {
+                            self.sample_pointer = 2.0 * start - self.sample_pointer;
+                            self.is_reversed = false;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.sample_pointer as usize >= sample_count {
+            self.is_playing = false;
+        }
+    }
 }
 
 /// A sampling synthesizer.
-#[derive(Debug, Control, Default, Serialize, Deserialize)]
+#[derive(Debug, Control, Derivative, Serialize, Deserialize)]
+#[derivative(Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct SamplerCore {
     source: SampleSource,
@@ -137,12 +571,43 @@ pub struct SamplerCore {
     #[control]
     root: FrequencyHz,
 
+    #[control]
+    #[serde(default = "SamplerVoice::default_amp_envelope")]
+    #[derivative(Default(value = "SamplerVoice::default_amp_envelope()"))]
+    amp_envelope: Envelope,
+
+    #[control]
+    #[serde(default = "Normal::maximum")]
+    #[derivative(Default(value = "Normal::maximum()"))]
+    velocity_to_gain: Normal,
+
+    #[control]
+    #[serde(default)]
+    velocity_to_filter: Normal,
+
+    #[serde(default)]
+    interpolation: Interpolation,
+
+    /// Overrides the loop in the sample file's metadata, if any.
+    #[serde(default)]
+    sample_loop: Option<SampleLoop>,
+
+    /// If there are any zones, they replace the single sample in `source`.
+    #[serde(default)]
+    zones: Vec<SampleZone>,
+
     #[serde(skip)]
     e: SamplerEphemerals,
 }
 #[derive(Debug, Default)]
 pub struct SamplerEphemerals {
     calculated_root: FrequencyHz,
+    metadata: SampleMetadata,
+
+    // One for each of the zones, in the same order.
+    loaded_zones: Vec<LoadedZone>,
+    // The next turn of each round-robin group.
+    round_robin_turns: FxHashMap<usize, usize>,
 
     inner: Synthesizer<SamplerVoice>,
 
@@ -155,6 +620,18 @@ impl HandlesMidi for SamplerCore {
         message: MidiMessage,
         midi_messages_fn: &mut MidiMessagesFn,
     ) {
+        if let MidiMessage::NoteOn { key, vel } = message {
+            if !self.zones.is_empty() {
+                let Some(index) = self.select_zone(key, vel) else {
+                    // No zone covers this note, so there's nothing to play.
+                    return;
+                };
+                let zone = self.e.loaded_zones[index].clone();
+                if let Some(voice) = self.e.inner.voice_for_key(&key) {
+                    voice.set_next_zone(zone);
+                }
+            }
+        }
         self.e
             .inner
             .handle_midi_message(channel, message, midi_messages_fn)
@@ -165,7 +642,13 @@ impl Generates<StereoSample> for SamplerCore {
         self.e.inner.generate(values)
     }
 }
-impl Serializable for SamplerCore {}
+impl Serializable for SamplerCore {
+    fn after_deser(&mut self) {
+        if let Err(e) = self.load() {
+            eprintln!("Couldn't load sampler: {e:?}");
+        }
+    }
+}
 impl Configurable for SamplerCore {
     fn sample_rate(&self) -> SampleRate {
         self.e.inner.sample_rate()
@@ -193,24 +676,19 @@ impl Configurable for SamplerCore {
 }
 impl SamplerCore {
     pub fn load(&mut self) -> anyhow::Result<()> {
-        let path = match &self.source {
-            SampleSource::SampleLibrary(index) => {
-                if let Some(path) = SampleLibrary::global().path(*index) {
-                    Paths::global().build_sample(&Vec::default(), Path::new(&path))
-                } else {
-                    return Err(anyhow!("Couldn't find sample {index} in library"));
-                }
-            }
-            SampleSource::Path(path_buf) => {
-                Paths::global().build_sample(&Vec::default(), Path::new(&path_buf))
-            }
-        };
-        let file = Paths::global().search_and_open(path.as_path())?;
+        if !self.zones.is_empty() {
+            return self.load_zones();
+        }
+        let file = self.source.open()?;
         let samples = Self::read_samples_from_file(&file)?;
         let samples = Arc::new(samples);
+        // Metadata is a nicety, so a file with a broken smpl chunk still loads.
+        self.e.metadata = Self::read_sample_metadata(&file).unwrap_or_default();
 
         self.e.calculated_root = if self.root.0 > 0.0 {
             self.root
+        } else if let Some(embedded_root) = self.e.metadata.root {
+            embedded_root
         } else
         // if let Ok(embedded_root_note) = Self::read_riff_metadata(&mut f2) {
         //  FrequencyHz::from(u7::from(embedded_root_note))
@@ -219,15 +697,90 @@ impl SamplerCore {
             FrequencyHz::from(440.0)
         };
 
+        let sample_loop = self.calculated_sample_loop();
         self.e.inner = Synthesizer::<SamplerVoice>::new_with(Box::new(
             VoiceStore::<SamplerVoice>::new_with_voice(VoiceCount::from(8), || {
-                SamplerVoice::new_with_samples(Arc::clone(&samples), self.e.calculated_root)
+                let mut voice =
+                    SamplerVoice::new_with_samples(Arc::clone(&samples), self.e.calculated_root);
+                self.configure_voice(&mut voice);
+                voice.set_sample_loop(sample_loop);
+                voice
             }),
         ));
 
         Ok(())
     }
 
+    // Loads every zone's sample. The voices start out empty, and each note
+    // gives its voice the sample of the zone that it plays.
+    fn load_zones(&mut self) -> anyhow::Result<()> {
+        self.e.loaded_zones = self
+            .zones
+            .iter()
+            .map(|zone| {
+                let file = zone.source.open()?;
+                let samples = Self::read_samples_from_file(&file)?;
+                let metadata = Self::read_sample_metadata(&file).unwrap_or_default();
+                Ok(LoadedZone {
+                    samples: Arc::new(samples),
+                    root: zone
+                        .root
+                        .or(metadata.root)
+                        .unwrap_or(FrequencyHz::from(440.0)),
+                    sample_loop: zone.sample_loop_with(metadata.sample_loop),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.e.round_robin_turns.clear();
+
+        let samples = Arc::new(Vec::default());
+        self.e.inner = Synthesizer::<SamplerVoice>::new_with(Box::new(
+            VoiceStore::<SamplerVoice>::new_with_voice(VoiceCount::from(8), || {
+                let mut voice =
+                    SamplerVoice::new_with_samples(Arc::clone(&samples), FrequencyHz::from(440.0));
+                self.configure_voice(&mut voice);
+                voice
+            }),
+        ));
+
+        Ok(())
+    }
+
+    // Picks the zone that plays a note: the first one that covers it, or if
+    // that one is in a round-robin group, whichever of the group's zones
+    // covering the note has the next turn.
+    fn select_zone(&mut self, key: u7, velocity: u7) -> Option<usize> {
+        let first = self
+            .zones
+            .iter()
+            .position(|zone| zone.contains(key, velocity))?;
+        let Some(group) = self.zones[first].round_robin_group else {
+            return Some(first);
+        };
+        let candidates: Vec<usize> = self
+            .zones
+            .iter()
+            .enumerate()
+            .filter(|(_, zone)| {
+                zone.round_robin_group == Some(group) && zone.contains(key, velocity)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let turn = self.e.round_robin_turns.entry(group).or_default();
+        let index = candidates[*turn % candidates.len()];
+        *turn += 1;
+        Some(index)
+    }
+
+    /// Creates a sampler that plays a set of [SampleZone]s. Call
+    /// [SamplerCore::load()] before playing it.
+    pub fn new_with_zones(zones: Vec<SampleZone>) -> Self {
+        Self {
+            zones,
+            ..Default::default()
+        }
+    }
+
     pub fn new_with(source: SampleSource, root: Option<FrequencyHz>) -> Self {
         let samples = Arc::new(Vec::default());
         let calculated_root = root.unwrap_or_default();
@@ -245,9 +798,25 @@ impl SamplerCore {
             e,
             source,
             root: calculated_root,
+            amp_envelope: SamplerVoice::default_amp_envelope(),
+            velocity_to_gain: Normal::maximum(),
+            velocity_to_filter: Default::default(),
+            interpolation: Default::default(),
+            sample_loop: Default::default(),
+            zones: Default::default(),
         }
     }
 
+    // Gives a new voice the settings that every voice shares.
+    fn configure_voice(&self, voice: &mut SamplerVoice) {
+        voice.set_interpolation(self.interpolation);
+        voice
+            .amp_envelope_mut()
+            .update_from_prototype(&self.amp_envelope);
+        voice.set_velocity_to_gain(self.velocity_to_gain);
+        voice.set_velocity_to_filter(self.velocity_to_filter);
+    }
+
     // https://forums.cockos.com/showthread.php?t=227118
     //
     // ** The acid chunk goes a little something like this:
@@ -309,6 +878,95 @@ impl SamplerCore {
         // Err(anyhow!("Couldn't find root note in acid RIFF chunk"))
     }
 
+    // Returns the contents of the first chunk in a RIFF file with the given ID.
+    fn read_riff_chunk(file: &File, chunk_id: &[u8; 4]) -> Result<Option<Vec<u8>>> {
+        let mut reader = BufReader::new(file);
+        reader.rewind()?;
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(anyhow!("Not a RIFF WAVE file"));
+        }
+        loop {
+            let mut chunk_header = [0; 8];
+            match reader.read_exact(&mut chunk_header) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            let size = u32::from_le_bytes(chunk_header[4..8].try_into()?) as usize;
+            if &chunk_header[0..4] == chunk_id {
+                let mut bytes = vec![0; size];
+                reader.read_exact(&mut bytes)?;
+                return Ok(Some(bytes));
+            }
+            // Chunks are padded to an even length.
+            reader.seek_relative((size + size % 2) as i64)?;
+        }
+    }
+
+    // https://www.recordingblogs.com/wiki/sample-chunk-of-a-wave-file
+    //
+    // The smpl chunk is a list of little-endian u32s:
+    //
+    //  0 manufacturer
+    //  1 product
+    //  2 sample period, in nanoseconds
+    //  3 MIDI unity note, the key that plays the sample at its recorded pitch
+    //  4 MIDI pitch fraction, in 1/2^32 of a semitone above the unity note
+    //  5 SMPTE format
+    //  6 SMPTE offset
+    //  7 number of sample loops
+    //  8 size of sampler-specific data after the loops
+    //
+    // Then each loop is six more:
+    //
+    //  0 cue point ID
+    //  1 type: 0 forward, 1 alternating (ping-pong), 2 backward
+    //  2 start, in frames
+    //  3 end, in frames, inclusive
+    //  4 fraction
+    //  5 play count, where 0 means forever
+    //
+    // Only the first loop is used, and backward loops play forward.
+    /// Reads the root note and loop points from a WAV file's smpl chunk.
+    pub fn read_sample_metadata(file: &File) -> Result<SampleMetadata> {
+        let Some(bytes) = Self::read_riff_chunk(file, b"smpl")? else {
+            return Ok(SampleMetadata::default());
+        };
+        let field = |index: usize| -> Result<u32> {
+            let offset = index * 4;
+            let Some(field) = bytes.get(offset..offset + 4) else {
+                return Err(anyhow!("smpl chunk is too short"));
+            };
+            Ok(u32::from_le_bytes(field.try_into()?))
+        };
+
+        let unity_note = field(3)?;
+        let root = (unity_note <= 127).then(|| {
+            let semitones =
+                unity_note as f64 + field(4).unwrap_or_default() as f64 / 2.0f64.powi(32);
+            FrequencyHz::from(440.0 * 2.0f64.powf((semitones - 69.0) / 12.0))
+        });
+        let sample_loop = if field(7)? > 0 {
+            let start = field(9 + 2)? as usize;
+            let end = field(9 + 3)? as usize + 1;
+            Some(SampleLoop {
+                mode: if field(9 + 1)? == 1 {
+                    LoopMode::PingPong
+                } else {
+                    LoopMode::Forward
+                },
+                start,
+                end,
+                is_sustain_only: false,
+            })
+        } else {
+            None
+        };
+        Ok(SampleMetadata { root, sample_loop })
+    }
+
     fn read_samples<T>(
         reader: &mut WavReader<BufReader<&File>>,
         channels: u16,
@@ -346,32 +1004,127 @@ impl SamplerCore {
     }
 
     pub fn read_samples_from_file(file: &File) -> anyhow::Result<Vec<StereoSample>> {
-        let mut reader = hound::WavReader::new(BufReader::new(file))?;
+        Ok(Self::read_samples_and_sample_rate_from_file(file)?.0)
+    }
+
+    /// Like [SamplerCore::read_samples_from_file()], but also returns the
+    /// file's sample rate.
+    pub fn read_samples_and_sample_rate_from_file(
+        file: &File,
+    ) -> anyhow::Result<(Vec<StereoSample>, SampleRate)> {
+        let mut reader = BufReader::new(file);
+        reader.rewind()?;
+        let mut reader = hound::WavReader::new(reader)?;
         let spec = reader.spec();
         let itype_max: SampleType = 2.0f64.powi(spec.bits_per_sample as i32 - 1);
 
-        match spec.sample_format {
+        let samples = match spec.sample_format {
             hound::SampleFormat::Float => {
                 Self::read_samples::<f32>(&mut reader, spec.channels, itype_max)
             }
             hound::SampleFormat::Int => {
                 Self::read_samples::<i32>(&mut reader, spec.channels, itype_max)
             }
-        }
+        }?;
+        Ok((samples, SampleRate::from(spec.sample_rate as usize)))
     }
 
     pub fn root(&self) -> FrequencyHz {
         self.root
     }
 
+    /// Sets the root of the sample in `source`. Zones have roots of their own.
     pub fn set_root(&mut self, root: FrequencyHz) {
         self.root = root;
+        if !self.zones.is_empty() {
+            return;
+        }
         self.e
             .inner
             .voices_mut()
             .for_each(|v| v.set_root_frequency(root));
     }
 
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+        self.e
+            .inner
+            .voices_mut()
+            .for_each(|v| v.set_interpolation(interpolation));
+    }
+
+    pub fn amp_envelope(&self) -> &Envelope {
+        &self.amp_envelope
+    }
+
+    /// After changing the envelope, call
+    /// [SamplerCore::notify_change_amp_envelope()] to pass it on to the
+    /// voices.
+    pub fn amp_envelope_mut(&mut self) -> &mut Envelope {
+        &mut self.amp_envelope
+    }
+
+    pub fn notify_change_amp_envelope(&mut self) {
+        self.e.inner.voices_mut().for_each(|v| {
+            v.amp_envelope_mut()
+                .update_from_prototype(&self.amp_envelope);
+        });
+    }
+
+    pub fn velocity_to_gain(&self) -> Normal {
+        self.velocity_to_gain
+    }
+
+    pub fn set_velocity_to_gain(&mut self, velocity_to_gain: Normal) {
+        self.velocity_to_gain = velocity_to_gain;
+        self.e
+            .inner
+            .voices_mut()
+            .for_each(|v| v.set_velocity_to_gain(velocity_to_gain));
+    }
+
+    pub fn velocity_to_filter(&self) -> Normal {
+        self.velocity_to_filter
+    }
+
+    pub fn set_velocity_to_filter(&mut self, velocity_to_filter: Normal) {
+        self.velocity_to_filter = velocity_to_filter;
+        self.e
+            .inner
+            .voices_mut()
+            .for_each(|v| v.set_velocity_to_filter(velocity_to_filter));
+    }
+
+    pub fn sample_loop(&self) -> Option<SampleLoop> {
+        self.sample_loop
+    }
+
+    /// Sets the loop, or with None, goes back to the one in the sample file.
+    /// Like the root, it applies only to the sample in `source`.
+    pub fn set_sample_loop(&mut self, sample_loop: Option<SampleLoop>) {
+        self.sample_loop = sample_loop;
+        if !self.zones.is_empty() {
+            return;
+        }
+        let sample_loop = self.calculated_sample_loop();
+        self.e
+            .inner
+            .voices_mut()
+            .for_each(|v| v.set_sample_loop(sample_loop));
+    }
+
+    /// The loop that the voices play: the one that was set, or else the one in
+    /// the sample file.
+    pub fn calculated_sample_loop(&self) -> SampleLoop {
+        self.sample_loop
+            .or(self.e.metadata.sample_loop)
+            .unwrap_or_default()
+    }
+
     pub fn calculated_root(&self) -> FrequencyHz {
         self.e.calculated_root
     }
@@ -380,6 +1133,17 @@ impl SamplerCore {
         self.e.calculated_root = calculated_root;
     }
 
+    pub fn zones(&self) -> &[SampleZone] {
+        &self.zones
+    }
+
+    /// Replaces the zones and loads their samples. With no zones, the sampler
+    /// goes back to playing the sample in its source.
+    pub fn set_zones(&mut self, zones: Vec<SampleZone>) -> anyhow::Result<()> {
+        self.zones = zones;
+        self.load()
+    }
+
     pub fn source(&self) -> &SampleSource {
         &self.source
     }
@@ -395,8 +1159,9 @@ impl SamplerCore {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::FileType;
-    use std::path::PathBuf;
+    use crate::util::{FileType, Paths};
+    use std::path::{Path, PathBuf};
+    use strum::IntoEnumIterator;
 
     fn paths_with_test_data_dir() -> Paths {
         let mut paths = Paths::default();
@@ -484,6 +1249,519 @@ mod tests {
         );
     }
 
+    fn square_wave_samples() -> Arc<Vec<StereoSample>> {
+        let file = paths_with_test_data_dir()
+            .search_and_open_with_file_type(
+                FileType::Sample,
+                Path::new("square-440Hz-1-second-mono-24-bit-PCM.wav"),
+            )
+            .unwrap();
+        Arc::new(SamplerCore::read_samples_from_file(&file).unwrap())
+    }
+
+    // Plays the samples at the given MIDI key, with a root of A4.
+    fn render_pitched(
+        samples: &Arc<Vec<StereoSample>>,
+        key: u8,
+        interpolation: Interpolation,
+        len: usize,
+    ) -> Vec<StereoSample> {
+        let mut voice =
+            SamplerVoice::new_with_samples(Arc::clone(samples), FrequencyHz::from(440.0));
+        voice.set_interpolation(interpolation);
+
+        // The first frame after a reset doesn't advance, so get it out of the
+        // way before the note starts.
+        voice.generate(&mut [StereoSample::SILENCE]);
+        voice.note_on(key.into(), 127.into());
+        let mut buffer = vec![StereoSample::SILENCE; len];
+        voice.generate(&mut buffer);
+        buffer
+    }
+
+    // Ideal band-limited resampling, approximated by a windowed sinc four
+    // times as long as the sampler's, computed exactly rather than from a
+    // table.
+    fn reference_render(samples: &[StereoSample], step: f64, len: usize) -> Vec<StereoSample> {
+        const HALF_WIDTH: f64 = 64.0;
+        let cutoff = (1.0 / step).min(1.0);
+        let half_width = (HALF_WIDTH / cutoff).ceil() as isize;
+        (0..len)
+            .map(|frame| {
+                let position = frame as f64 * step;
+                let index = position.floor() as isize;
+                ((index - half_width + 1)..=(index + half_width))
+                    .filter(|i| *i >= 0 && (*i as usize) < samples.len())
+                    .fold(StereoSample::SILENCE, |sum, i| {
+                        let x = (position - i as f64) * cutoff;
+                        sum + samples[i as usize]
+                            * (cutoff * Interpolation::exact_windowed_sinc(x, HALF_WIDTH))
+                    })
+            })
+            .collect()
+    }
+
+    fn rms_error(a: &[StereoSample], b: &[StereoSample]) -> f64 {
+        let sum: f64 = a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| (a.0 .0 - b.0 .0).powi(2) + (a.1 .0 - b.1 .0).powi(2))
+            .sum();
+        (sum / (2 * a.len()) as f64).sqrt()
+    }
+
+    #[test]
+    fn interpolation_mainline() {
+        let samples = [0.0f64, 1.0, 0.0, -1.0].map(StereoSample::from);
+        assert_eq!(
+            Interpolation::Truncate.interpolate(&samples, 1.75, 1.0),
+            StereoSample::from(1.0)
+        );
+        assert_eq!(
+            Interpolation::Linear.interpolate(&samples, 1.25, 1.0),
+            StereoSample::from(0.75)
+        );
+        for interpolation in Interpolation::iter() {
+            for (i, sample) in samples.iter().enumerate() {
+                let value = interpolation.interpolate(&samples, i as f64, 1.0);
+                assert!(
+                    (value.0 .0 - sample.0 .0).abs() < 0.000001,
+                    "{interpolation} should pass through the samples themselves, but at #{i} got {value:?}"
+                );
+            }
+            assert_eq!(
+                interpolation.interpolate(&samples, -100.0, 1.0),
+                StereoSample::SILENCE,
+                "{interpolation} should be silent far outside the samples"
+            );
+        }
+    }
+
+    #[test]
+    fn pitched_playback_matches_reference_renders() {
+        const LEN: usize = 1280;
+        // Skip the start, where the reference and the sampler treat the silence
+        // before the first sample a little differently.
+        const SKIP: usize = 256;
+        let samples = square_wave_samples();
+
+        // A little down, a little up, and a fifth up.
+        for key in [67, 71, 76] {
+            let frequency: FrequencyHz = MidiNote::from_repr(key as usize).unwrap().into();
+            let step: ParameterType = (frequency / FrequencyHz::from(440.0)).into();
+            let reference = reference_render(&samples, step, LEN);
+            let error = |interpolation| {
+                let rendered = render_pitched(&samples, key, interpolation, LEN);
+                rms_error(&rendered[SKIP..], &reference[SKIP..])
+            };
+            let truncate = error(Interpolation::Truncate);
+            let linear = error(Interpolation::Linear);
+            let cubic = error(Interpolation::CubicHermite);
+            let sinc = error(Interpolation::WindowedSinc);
+
+            assert!(
+                sinc < 0.02,
+                "Windowed sinc at key {key} should be close to the reference, but RMS error was {sinc}"
+            );
+            assert!(
+                linear < 0.08 && cubic < 0.08,
+                "Linear ({linear}) and cubic ({cubic}) at key {key} should be near the reference"
+            );
+            assert!(
+                truncate > 0.12,
+                "Truncation at key {key} should alias audibly, but RMS error was only {truncate}"
+            );
+            assert!(
+                sinc < linear.min(cubic) && linear.max(cubic) < truncate,
+                "At key {key}, quality should improve from truncation ({truncate}) to linear ({linear}) and cubic ({cubic}) to sinc ({sinc})"
+            );
+        }
+    }
+
+    #[test]
+    fn sampler_core_interpolation_reaches_voices() {
+        let mut sampler =
+            SamplerCore::new_with(SampleSource::Path("stereo-pluck.wav".into()), None);
+        assert_eq!(sampler.interpolation(), Interpolation::Linear);
+        sampler.set_interpolation(Interpolation::WindowedSinc);
+        assert!(sampler
+            .e
+            .inner
+            .voices_mut()
+            .all(|v| v.interpolation() == Interpolation::WindowedSinc));
+    }
+
+    // A voice over a ramp, where each sample's value is its index, so that the
+    // output shows where the voice is reading. Its envelope neither fades in nor
+    // out, so as not to disturb the values.
+    fn ramp_voice(sample_loop: SampleLoop) -> SamplerVoice {
+        let samples = Arc::new(
+            (0..8)
+                .map(|i| StereoSample::from(i as f64))
+                .collect::<Vec<_>>(),
+        );
+        let mut voice = SamplerVoice::new_with_samples(samples, MidiNote::A4.into());
+        voice.set_interpolation(Interpolation::Truncate);
+        voice.set_sample_loop(sample_loop);
+        voice.amp_envelope_mut().set_attack(Normal::minimum());
+        voice.amp_envelope_mut().set_release(Normal::minimum());
+        voice.generate(&mut [StereoSample::SILENCE]);
+        voice.note_on((MidiNote::A4 as u8).into(), 127.into());
+        voice
+    }
+
+    fn positions(voice: &mut SamplerVoice, len: usize) -> Vec<f64> {
+        let mut buffer = vec![StereoSample::SILENCE; len];
+        voice.generate(&mut buffer);
+        buffer.iter().map(|s| s.0 .0).collect()
+    }
+
+    #[test]
+    fn sample_loops() {
+        let mut voice = ramp_voice(SampleLoop {
+            mode: LoopMode::Forward,
+            start: 2,
+            end: 5,
+            is_sustain_only: false,
+        });
+        assert_eq!(
+            positions(&mut voice, 10),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 2.0, 3.0, 4.0, 2.0, 3.0]
+        );
+        voice.note_off(0.into());
+        assert!(
+            !voice.is_playing(),
+            "Without a release, a loop that isn't sustain-only should stop at note-off"
+        );
+
+        let mut voice = ramp_voice(SampleLoop {
+            mode: LoopMode::PingPong,
+            start: 2,
+            end: 5,
+            is_sustain_only: false,
+        });
+        assert_eq!(
+            positions(&mut voice, 10),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 2.0, 3.0, 4.0, 3.0]
+        );
+
+        let mut voice = ramp_voice(SampleLoop {
+            mode: LoopMode::Forward,
+            start: 2,
+            end: 5,
+            is_sustain_only: true,
+        });
+        assert_eq!(
+            positions(&mut voice, 7),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 2.0, 3.0]
+        );
+        // A release long enough to keep the tail at nearly full volume.
+        voice.amp_envelope_mut().set_release(Normal::from(0.5));
+        voice.note_off(0.into());
+        assert!(voice.is_playing());
+        let tail = positions(&mut voice, 6);
+        for (value, expected) in tail.iter().zip([4.0, 5.0, 6.0, 7.0, 0.0, 0.0]) {
+            assert!(
+                (value - expected).abs() < 0.01,
+                "After note-off, a sustain loop should let the rest of the sample play, but got {tail:?}"
+            );
+        }
+        assert!(!voice.is_playing());
+
+        let mut voice = ramp_voice(SampleLoop {
+            mode: LoopMode::Forward,
+            start: 6,
+            end: 100,
+            is_sustain_only: false,
+        });
+        assert_eq!(
+            positions(&mut voice, 10),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 0.0, 0.0],
+            "A loop that runs past the end of the sample should be ignored"
+        );
+    }
+
+    // A second of a constant level, apart from a short fade-in at the start.
+    fn steady_voice() -> SamplerVoice {
+        let samples = Arc::new(
+            (0..44100)
+                .map(|i| StereoSample::from((i as f64 / 100.0).min(1.0)))
+                .collect::<Vec<_>>(),
+        );
+        let mut voice = SamplerVoice::new_with_samples(samples, MidiNote::A4.into());
+        voice.generate(&mut [StereoSample::SILENCE]);
+        voice
+    }
+
+    fn render(voice: &mut SamplerVoice, len: usize) -> Vec<f64> {
+        let mut values = Vec::default();
+        let mut buffer = [StereoSample::SILENCE; 64];
+        while values.len() < len {
+            voice.generate(&mut buffer);
+            values.extend(buffer.iter().map(|s| s.0 .0));
+        }
+        values
+    }
+
+    #[test]
+    fn velocity_response() {
+        let level_at_velocity = |velocity: u8, velocity_to_gain: Normal| {
+            let mut voice = steady_voice();
+            voice.set_velocity_to_gain(velocity_to_gain);
+            voice.note_on((MidiNote::A4 as u8).into(), velocity.into());
+            *render(&mut voice, 1000).last().unwrap()
+        };
+        assert!((level_at_velocity(127, Normal::maximum()) - 1.0).abs() < 0.000001);
+        assert!(
+            (level_at_velocity(64, Normal::maximum()) - (64.0f64 / 127.0).powi(2)).abs() < 0.000001,
+            "Soft notes should be quieter"
+        );
+        assert!(
+            (level_at_velocity(64, Normal::minimum()) - 1.0).abs() < 0.000001,
+            "Without velocity-to-gain, velocity shouldn't matter"
+        );
+
+        // A 5 kHz sine, which the filter passes when it's open.
+        let brightness_at_velocity = |velocity: u8| {
+            let samples = Arc::new(
+                (0..44100)
+                    .map(|i| {
+                        StereoSample::from(0.5 * (2.0 * PI * 5000.0 * i as f64 / 44100.0).sin())
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            let mut voice = SamplerVoice::new_with_samples(samples, MidiNote::A4.into());
+            voice.set_velocity_to_filter(Normal::maximum());
+            voice.note_on((MidiNote::A4 as u8).into(), velocity.into());
+            let values = render(&mut voice, 4096);
+            values[2048..].iter().map(|v| v.abs()).sum::<f64>()
+        };
+        assert!(
+            brightness_at_velocity(20) < brightness_at_velocity(127) / 10.0,
+            "With velocity-to-filter, soft notes should be darker"
+        );
+    }
+
+    #[test]
+    fn note_off_fades_out() {
+        let mut voice = steady_voice();
+        voice.note_on((MidiNote::A4 as u8).into(), 127.into());
+        let held = render(&mut voice, 1024);
+        assert!((held.last().unwrap() - 1.0).abs() < 0.000001);
+
+        voice.note_off(0.into());
+        assert!(
+            voice.is_playing(),
+            "The release should keep the voice going"
+        );
+        let released = render(&mut voice, 4096);
+        assert!(
+            released[0] > 0.9,
+            "Note-off shouldn't cut the sound abruptly"
+        );
+        assert!(released.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(*released.last().unwrap(), 0.0);
+        assert!(!voice.is_playing());
+
+        // This used to panic.
+        voice.aftertouch(64.into());
+    }
+
+    #[test]
+    fn retriggering_doesnt_click() {
+        let mut voice = steady_voice();
+        voice.note_on((MidiNote::A4 as u8).into(), 127.into());
+        let mut values = render(&mut voice, 1024);
+        voice.note_on((MidiNote::A4 as u8).into(), 127.into());
+        values.extend(render(&mut voice, 1024));
+        assert!(voice.is_playing());
+        assert!(
+            (values.last().unwrap() - 1.0).abs() < 0.000001,
+            "The new note should be playing"
+        );
+        let largest_step = values[1..]
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f64::max);
+        assert!(
+            largest_step < 0.05,
+            "The old note should fade out before the sample starts over, but output jumped by {largest_step}"
+        );
+    }
+
+    #[test]
+    fn reading_smpl_chunk() {
+        // A 1,000-frame sine rooted at C4, with a ping-pong loop over frames
+        // 100 through 899.
+        let file = paths_with_test_data_dir()
+            .search_and_open_with_file_type(FileType::Sample, Path::new("riff-with-smpl.wav"))
+            .unwrap();
+        let metadata = SamplerCore::read_sample_metadata(&file).unwrap();
+        let root = metadata.root.unwrap();
+        assert!(
+            (root.0 - 261.6256).abs() < 0.001,
+            "Sample should be rooted at C4, but got {root}"
+        );
+        assert_eq!(
+            metadata.sample_loop,
+            Some(SampleLoop {
+                mode: LoopMode::PingPong,
+                start: 100,
+                end: 900,
+                is_sustain_only: false,
+            })
+        );
+
+        // Reading the metadata shouldn't disturb reading the samples, in
+        // either order.
+        let samples = SamplerCore::read_samples_from_file(&file).unwrap();
+        assert_eq!(samples.len(), 1000);
+
+        let file = paths_with_test_data_dir()
+            .search_and_open_with_file_type(
+                FileType::Sample,
+                Path::new("square-440Hz-1-second-mono-24-bit-PCM.wav"),
+            )
+            .unwrap();
+        assert_eq!(
+            SamplerCore::read_sample_metadata(&file).unwrap(),
+            SampleMetadata::default(),
+            "A file without a smpl chunk should have no metadata"
+        );
+    }
+
+    #[test]
+    fn zone_selection() {
+        let zone = |lowest_key, highest_key, lowest_velocity, round_robin_group| SampleZone {
+            lowest_key,
+            highest_key,
+            lowest_velocity,
+            round_robin_group,
+            ..Default::default()
+        };
+        let mut sampler = SamplerCore::new_with_zones(vec![
+            zone(21, 59, 0, None),
+            SampleZone {
+                highest_velocity: 63,
+                ..zone(60, 127, 0, None)
+            },
+            zone(60, 127, 64, Some(1)),
+            zone(60, 127, 64, Some(1)),
+        ]);
+        assert_eq!(sampler.select_zone(40.into(), 100.into()), Some(0));
+        assert_eq!(sampler.select_zone(60.into(), 30.into()), Some(1));
+        assert_eq!(
+            sampler.select_zone(10.into(), 100.into()),
+            None,
+            "A note that no zone covers should play nothing"
+        );
+
+        let turns: Vec<_> = (0..3)
+            .filter_map(|_| sampler.select_zone(72.into(), 100.into()))
+            .collect();
+        assert_eq!(
+            turns,
+            vec![2, 3, 2],
+            "Zones in a round-robin group should take turns"
+        );
+    }
+
+    #[test]
+    fn zone_loops() {
+        let file_loop = SampleLoop {
+            mode: LoopMode::Forward,
+            start: 10,
+            end: 20,
+            is_sustain_only: false,
+        };
+        let zone_loop = SampleLoop {
+            start: 5,
+            ..file_loop
+        };
+        let zone = SampleZone::default();
+        assert_eq!(zone.sample_loop_with(Some(file_loop)), file_loop);
+        assert_eq!(zone.sample_loop_with(None), SampleLoop::default());
+
+        let zone = SampleZone {
+            is_file_loop_sustain_only: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            zone.sample_loop_with(Some(file_loop)),
+            SampleLoop {
+                is_sustain_only: true,
+                ..file_loop
+            },
+            "The zone's loop mode should apply to the file's loop points"
+        );
+
+        let zone = SampleZone {
+            sample_loop: Some(zone_loop),
+            ..zone
+        };
+        assert_eq!(zone.sample_loop_with(Some(file_loop)), zone_loop);
+    }
+
+    #[test]
+    fn zones_play_their_own_samples() {
+        Paths::set_instance(paths_with_test_data_dir());
+        let square = SampleSource::Path("square-440Hz-1-second-mono-24-bit-PCM.wav".into());
+        let pluck = SampleSource::Path("stereo-pluck.wav".into());
+        let square_len = SamplerCore::read_samples_from_file(&square.open().unwrap())
+            .unwrap()
+            .len();
+        let pluck_len = SamplerCore::read_samples_from_file(&pluck.open().unwrap())
+            .unwrap()
+            .len();
+        assert_ne!(square_len, pluck_len);
+
+        let mut sampler = SamplerCore::new_with_zones(vec![
+            SampleZone {
+                source: square,
+                highest_key: 63,
+                ..Default::default()
+            },
+            SampleZone {
+                source: pluck,
+                root: Some(FrequencyHz::from(220.0)),
+                lowest_key: 64,
+                ..Default::default()
+            },
+        ]);
+        assert!(sampler.load().is_ok());
+
+        // Round-trip it the way a project would, which should load it again.
+        let json = serde_json::to_string(&sampler).unwrap();
+        let mut sampler: SamplerCore = serde_json::from_str(&json).unwrap();
+        sampler.after_deser();
+        assert_eq!(sampler.zones().len(), 2);
+
+        let mut play = |key: u8| {
+            sampler.handle_midi_message(
+                MidiChannel::default(),
+                MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: 127.into(),
+                },
+                &mut |_, _| {},
+            );
+            let mut buffer = [StereoSample::default(); 64];
+            sampler.generate(&mut buffer);
+            sampler
+                .e
+                .inner
+                .voices()
+                .filter(|v| v.is_playing())
+                .map(|v| (v.samples.as_ref().unwrap().len(), v.root_frequency))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(play(60), vec![(square_len, FrequencyHz::from(440.0))]);
+        assert!(
+            play(69).contains(&(pluck_len, FrequencyHz::from(220.0))),
+            "A note above the first zone should play the second zone's sample"
+        );
+    }
+
     #[test]
     fn sampler_makes_any_sound_at_all() {
         let paths = paths_with_test_data_dir();
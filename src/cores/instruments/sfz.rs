@@ -0,0 +1,665 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Imports instruments in the SFZ format, a text file that maps a set of WAV
+//! files across the keyboard. See <https://sfzformat.com/>.
+
+use super::sampler::{LoopMode, SampleLoop, SampleZone, SamplerCore, SamplerVoice};
+use crate::{
+    prelude::*,
+    util::{library::SampleSource, Paths},
+};
+use anyhow::{anyhow, Result};
+use rustc_hash::FxHashMap;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+type Opcodes = FxHashMap<String, String>;
+
+// The part of an SFZ file that opcodes apply to. Each level's opcodes are the
+// defaults for the levels below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Header {
+    Control,
+    Global,
+    Master,
+    Group,
+    Region,
+    // Headers that don't describe samples, like <curve> and <effect>.
+    Other,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Header(String),
+    Opcode(String, String),
+}
+
+/// An SFZ file, translated into what a [SamplerCore] can play.
+///
+/// It understands regions and the <global>, <master> and <group> headers that
+/// hold their defaults; key and velocity ranges; pitch_keycenter and tuning;
+/// loops; the amp envelope; round robin with seq_length and seq_position; and
+/// #define and #include. Other opcodes are ignored, and a region with a value
+/// that can't be understood is left out rather than failing the whole file.
+#[derive(Debug, Default, Clone)]
+pub struct SfzInstrument {
+    pub zones: Vec<SampleZone>,
+    /// Why each region that was left out couldn't be understood.
+    pub skipped_regions: Vec<String>,
+    /// The amplitude envelope, if the file sets one. SFZ lets each region have
+    /// its own, but a [SamplerCore] has one for the whole instrument, so it
+    /// comes from the first region that sets one.
+    pub amp_envelope: Option<Envelope>,
+}
+impl SfzInstrument {
+    const MAX_INCLUDE_DEPTH: usize = 8;
+
+    /// Reads an SFZ file, and any files it includes, from the [Paths] hives.
+    /// Like a [SampleSource::Path], `path` is relative to the hives' samples
+    /// directories. Sample paths in the file are relative to the file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let text = Self::read_with_includes(path, &dir, 0)?;
+        Self::parse(&text, &dir)
+    }
+
+    /// Parses the text of an SFZ file whose sample paths are relative to
+    /// `dir`.
+    pub fn parse(text: &str, dir: &Path) -> Result<Self> {
+        let mut defines = Vec::<(String, String)>::default();
+        let mut header = Header::Other;
+        let mut control = Opcodes::default();
+        let mut global = Opcodes::default();
+        let mut master = Opcodes::default();
+        let mut group = Opcodes::default();
+        let mut region = Opcodes::default();
+        // Each <global>, <master> and <group> starts a new set of round-robin
+        // regions.
+        let mut group_number = 0;
+        let mut regions = Vec::default();
+
+        let text = Self::strip_comments(text);
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(define) = line.strip_prefix("#define") {
+                let mut parts = define.split_whitespace();
+                if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                    defines.push((name.to_string(), value.to_string()));
+                    // Longer names first, so that $A doesn't clobber $AB.
+                    defines.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+                }
+                continue;
+            }
+            let line = defines
+                .iter()
+                .fold(line.to_string(), |line, (name, value)| {
+                    line.replace(name, value)
+                });
+
+            for token in Self::tokenize(&line) {
+                match token {
+                    Token::Header(name) => {
+                        if header == Header::Region {
+                            regions.push((
+                                Self::merge(&[&global, &master, &group, &region]),
+                                group_number,
+                            ));
+                        }
+                        header = match name.as_str() {
+                            "control" => Header::Control,
+                            "global" => {
+                                global.clear();
+                                master.clear();
+                                group.clear();
+                                group_number += 1;
+                                Header::Global
+                            }
+                            "master" => {
+                                master.clear();
+                                group.clear();
+                                group_number += 1;
+                                Header::Master
+                            }
+                            "group" => {
+                                group.clear();
+                                group_number += 1;
+                                Header::Group
+                            }
+                            "region" => {
+                                region.clear();
+                                Header::Region
+                            }
+                            _ => Header::Other,
+                        };
+                    }
+                    Token::Opcode(name, value) => {
+                        let opcodes = match header {
+                            Header::Control => &mut control,
+                            Header::Global => &mut global,
+                            Header::Master => &mut master,
+                            Header::Group => &mut group,
+                            Header::Region => &mut region,
+                            Header::Other => continue,
+                        };
+                        opcodes.insert(name, value);
+                    }
+                }
+            }
+        }
+        if header == Header::Region {
+            regions.push((
+                Self::merge(&[&global, &master, &group, &region]),
+                group_number,
+            ));
+        }
+
+        let mut dir = dir.to_path_buf();
+        if let Some(default_path) = control.get("default_path") {
+            dir.push(Self::normalize_path(default_path));
+        }
+        let mut instrument = Self::default();
+        let mut seq_positions = Vec::default();
+        for (opcodes, group_number) in regions.iter() {
+            match Self::zone_from(opcodes, &dir, *group_number) {
+                Ok(Some((zone, seq_position))) => {
+                    if instrument.amp_envelope.is_none() {
+                        instrument.amp_envelope = Self::amp_envelope_from(opcodes)?;
+                    }
+                    instrument.zones.push(zone);
+                    seq_positions.push(seq_position);
+                }
+                Ok(None) => {}
+                Err(e) => instrument.skipped_regions.push(e.to_string()),
+            }
+        }
+        instrument.sort_round_robins(&seq_positions);
+        Ok(instrument)
+    }
+
+    fn read_text(path: &Path) -> Result<String> {
+        let paths = Paths::global();
+        paths
+            .search_and_read_to_string(&paths.build_sample(&Vec::default(), path))
+            .map_err(|e| anyhow!("Couldn't read SFZ file {path:?}: {e}"))
+    }
+
+    // Replaces each #include line with the text of the file it names, which is
+    // relative to the top-level file.
+    fn read_with_includes(path: &Path, dir: &Path, depth: usize) -> Result<String> {
+        if depth > Self::MAX_INCLUDE_DEPTH {
+            return Err(anyhow!("SFZ includes are nested too deeply at {path:?}"));
+        }
+        let text = Self::read_text(path)?;
+        let mut expanded = String::with_capacity(text.len());
+        for line in text.lines() {
+            if let Some(include) = line.trim_start().strip_prefix("#include") {
+                let included = include
+                    .split('"')
+                    .nth(1)
+                    .ok_or_else(|| anyhow!("Malformed #include in {path:?}: {line}"))?;
+                expanded.push_str(&Self::read_with_includes(
+                    &dir.join(Self::normalize_path(included)),
+                    dir,
+                    depth + 1,
+                )?);
+            } else {
+                expanded.push_str(line);
+            }
+            expanded.push('\n');
+        }
+        Ok(expanded)
+    }
+
+    // Removes // and /* */ comments, keeping line breaks.
+    fn strip_comments(text: &str) -> String {
+        let mut stripped = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("//") {
+                rest = after.find('\n').map_or("", |end| &after[end..]);
+            } else if let Some(after) = rest.strip_prefix("/*") {
+                let end = after.find("*/").map_or(after.len(), |end| end + 2);
+                stripped.extend(after[..end].chars().filter(|c| *c == '\n'));
+                rest = &after[end..];
+            } else {
+                let c = rest.chars().next().unwrap();
+                stripped.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        stripped
+    }
+
+    // Splits a line into headers and opcodes. An opcode's value runs until the
+    // next opcode or header, because sample names can have spaces in them.
+    fn tokenize(line: &str) -> Vec<Token> {
+        let mut tokens = Vec::default();
+        let mut rest = line;
+        loop {
+            let (chunk, header) = match rest.find('<') {
+                Some(start) => match rest[start..].find('>') {
+                    Some(len) => (
+                        &rest[..start],
+                        Some((&rest[start + 1..start + len], &rest[start + len + 1..])),
+                    ),
+                    None => (rest, None),
+                },
+                None => (rest, None),
+            };
+            for word in chunk.split_whitespace() {
+                match word.split_once('=') {
+                    Some((name, value))
+                        if !name.is_empty()
+                            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+                    {
+                        tokens.push(Token::Opcode(name.to_string(), value.to_string()));
+                    }
+                    _ => {
+                        if let Some(Token::Opcode(_, value)) = tokens.last_mut() {
+                            if !value.is_empty() {
+                                value.push(' ');
+                            }
+                            value.push_str(word);
+                        }
+                    }
+                }
+            }
+            match header {
+                Some((name, after)) => {
+                    tokens.push(Token::Header(name.trim().to_string()));
+                    rest = after;
+                }
+                None => break,
+            }
+        }
+        tokens
+    }
+
+    // Combines levels of opcodes, later ones overriding earlier ones.
+    fn merge(levels: &[&Opcodes]) -> Opcodes {
+        levels.iter().fold(Opcodes::default(), |mut merged, level| {
+            merged.extend(level.iter().map(|(k, v)| (k.clone(), v.clone())));
+            merged
+        })
+    }
+
+    // SFZ files written on Windows use backslashes.
+    fn normalize_path(path: &str) -> PathBuf {
+        PathBuf::from(path.trim().replace('\\', "/"))
+    }
+
+    fn number<T: FromStr>(opcodes: &Opcodes, name: &str) -> Result<Option<T>> {
+        opcodes
+            .get(name)
+            .map(|value| {
+                value
+                    .parse::<T>()
+                    .map_err(|_| anyhow!("Invalid value {value:?} for SFZ opcode {name}"))
+            })
+            .transpose()
+    }
+
+    // A key is either a MIDI note number or a note name like c#4, where c4 is
+    // middle C (60). -1 means no key, as if the opcode weren't there.
+    fn key(opcodes: &Opcodes, name: &str) -> Result<Option<u8>> {
+        let Some(value) = opcodes.get(name).filter(|value| *value != "-1") else {
+            return Ok(None);
+        };
+        let invalid = || anyhow!("Invalid key {value:?} for SFZ opcode {name}");
+        let key = if let Ok(key) = value.parse::<i32>() {
+            key
+        } else {
+            let mut chars = value.chars();
+            let pitch_class = match chars.next().map(|c| c.to_ascii_lowercase()) {
+                Some('c') => 0,
+                Some('d') => 2,
+                Some('e') => 4,
+                Some('f') => 5,
+                Some('g') => 7,
+                Some('a') => 9,
+                Some('b') => 11,
+                _ => return Err(invalid()),
+            };
+            let rest = chars.as_str();
+            let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+                (1, octave)
+            } else if let Some(octave) = rest.strip_prefix('b') {
+                (-1, octave)
+            } else {
+                (0, rest)
+            };
+            let octave = octave.parse::<i32>().map_err(|_| invalid())?;
+            (octave + 1) * 12 + pitch_class + accidental
+        };
+        u8::try_from(key)
+            .ok()
+            .filter(|key| *key <= 127)
+            .map(Some)
+            .ok_or_else(invalid)
+    }
+
+    // Builds a zone from a region's opcodes, along with its place in its
+    // round-robin sequence. Regions without a sample file are skipped.
+    fn zone_from(
+        opcodes: &Opcodes,
+        dir: &Path,
+        group_number: usize,
+    ) -> Result<Option<(SampleZone, usize)>> {
+        let Some(sample) = opcodes.get("sample") else {
+            return Ok(None);
+        };
+        // Names starting with * are built-in waveforms, not files.
+        if sample.starts_with('*') {
+            return Ok(None);
+        }
+        // A key or hikey of -1 means that no key triggers the region. Such
+        // regions play on note-off or by controller, which the sampler can't
+        // do.
+        if ["key", "hikey"]
+            .iter()
+            .any(|name| opcodes.get(*name).is_some_and(|value| value == "-1"))
+        {
+            return Ok(None);
+        }
+        let mut zone = SampleZone {
+            source: SampleSource::Path(dir.join(Self::normalize_path(sample))),
+            ..Default::default()
+        };
+
+        let key = Self::key(opcodes, "key")?;
+        if let Some(key) = key {
+            zone.lowest_key = key;
+            zone.highest_key = key;
+        }
+        if let Some(lowest_key) = Self::key(opcodes, "lokey")? {
+            zone.lowest_key = lowest_key;
+        }
+        if let Some(highest_key) = Self::key(opcodes, "hikey")? {
+            zone.highest_key = highest_key;
+        }
+        if let Some(lowest_velocity) = Self::number::<u8>(opcodes, "lovel")? {
+            zone.lowest_velocity = lowest_velocity.min(127);
+        }
+        if let Some(highest_velocity) = Self::number::<u8>(opcodes, "hivel")? {
+            zone.highest_velocity = highest_velocity.min(127);
+        }
+
+        // pitch_keycenter=sample means to use the root in the sample file.
+        if opcodes.get("pitch_keycenter").map(String::as_str) != Some("sample") {
+            let keycenter = Self::key(opcodes, "pitch_keycenter")?.or(key).unwrap_or(60);
+            let semitones = Self::number::<f64>(opcodes, "transpose")?.unwrap_or_default()
+                + Self::number::<f64>(opcodes, "tune")?.unwrap_or_default() / 100.0;
+            let root: FrequencyHz = MidiNote::from_repr(keycenter as usize).unwrap().into();
+            zone.root = Some(FrequencyHz::from(root.0 / 2.0f64.powf(semitones / 12.0)));
+        }
+
+        Self::set_sample_loop(&mut zone, opcodes)?;
+
+        let seq_length = Self::number::<usize>(opcodes, "seq_length")?.unwrap_or(1);
+        let seq_position = Self::number::<usize>(opcodes, "seq_position")?.unwrap_or(1);
+        if seq_length > 1 {
+            zone.round_robin_group = Some(group_number);
+        }
+        Ok(Some((zone, seq_position)))
+    }
+
+    // SFZ 1 spells some loop opcodes without underscores.
+    fn set_sample_loop(zone: &mut SampleZone, opcodes: &Opcodes) -> Result<()> {
+        let either = |name: &str, alias: &str| {
+            Self::number::<usize>(opcodes, name)
+                .transpose()
+                .or_else(|| Self::number::<usize>(opcodes, alias).transpose())
+                .transpose()
+        };
+        let start = either("loop_start", "loopstart")?;
+        let end = either("loop_end", "loopend")?;
+        let mode = opcodes
+            .get("loop_mode")
+            .or_else(|| opcodes.get("loopmode"))
+            .map(String::as_str);
+        let is_sustain_only = match mode {
+            None => {
+                // Without a loop mode, a region loops if it gives loop points,
+                // and otherwise does whatever the sample file says.
+                if start.is_none() && end.is_none() {
+                    return Ok(());
+                }
+                false
+            }
+            Some("no_loop") | Some("one_shot") => {
+                zone.sample_loop = Some(SampleLoop::default());
+                return Ok(());
+            }
+            Some("loop_continuous") => false,
+            Some("loop_sustain") => true,
+            Some(mode) => return Err(anyhow!("Unknown SFZ loop mode {mode:?}")),
+        };
+        let (Some(start), Some(end)) = (start, end) else {
+            // The loop points are in the sample file, but the mode still
+            // applies to them.
+            zone.is_file_loop_sustain_only = Some(is_sustain_only);
+            return Ok(());
+        };
+        zone.sample_loop = Some(SampleLoop {
+            mode: if opcodes.get("loop_type").map(String::as_str) == Some("alternate") {
+                LoopMode::PingPong
+            } else {
+                LoopMode::Forward
+            },
+            start,
+            // SFZ's loop end is the loop's last frame.
+            end: end + 1,
+            is_sustain_only,
+        });
+        Ok(())
+    }
+
+    fn amp_envelope_from(opcodes: &Opcodes) -> Result<Option<Envelope>> {
+        let attack = Self::number::<f64>(opcodes, "ampeg_attack")?;
+        let decay = Self::number::<f64>(opcodes, "ampeg_decay")?;
+        let sustain = Self::number::<f64>(opcodes, "ampeg_sustain")?;
+        let release = Self::number::<f64>(opcodes, "ampeg_release")?;
+        if attack.is_none() && decay.is_none() && sustain.is_none() && release.is_none() {
+            return Ok(None);
+        }
+        let seconds_to_normal = |seconds: f64| {
+            Normal::from(
+                Envelope::from_seconds_to_normal(Seconds(seconds.max(0.0)))
+                    .0
+                    .min(1.0),
+            )
+        };
+        let mut envelope = SamplerVoice::default_amp_envelope();
+        if let Some(attack) = attack {
+            envelope.set_attack(seconds_to_normal(attack));
+        }
+        envelope.set_decay(seconds_to_normal(decay.unwrap_or_default()));
+        if let Some(sustain) = sustain {
+            envelope.set_sustain(Normal::from((sustain / 100.0).clamp(0.0, 1.0)));
+        }
+        if let Some(release) = release {
+            envelope.set_release(seconds_to_normal(release));
+        }
+        Ok(Some(envelope))
+    }
+
+    // Puts each round-robin group's zones in the order of their seq_position,
+    // which is the order in which they take turns. Other zones stay put.
+    fn sort_round_robins(&mut self, seq_positions: &[usize]) {
+        let mut groups = FxHashMap::<usize, Vec<usize>>::default();
+        self.zones.iter().enumerate().for_each(|(index, zone)| {
+            if let Some(group) = zone.round_robin_group {
+                groups.entry(group).or_default().push(index);
+            }
+        });
+        for indexes in groups.values() {
+            let mut members: Vec<(usize, SampleZone)> = indexes
+                .iter()
+                .map(|index| (seq_positions[*index], self.zones[*index].clone()))
+                .collect();
+            members.sort_by_key(|(seq_position, _)| *seq_position);
+            indexes
+                .iter()
+                .zip(members)
+                .for_each(|(index, (_, zone))| self.zones[*index] = zone);
+        }
+    }
+}
+
+impl SamplerCore {
+    /// Creates a sampler from an SFZ file in the [Paths] hives, and loads its
+    /// samples. See [SfzInstrument::read()].
+    pub fn new_from_sfz(path: &Path) -> Result<Self> {
+        let instrument = SfzInstrument::read(path)?;
+        for reason in instrument.skipped_regions.iter() {
+            eprintln!("Skipped a region of SFZ file {path:?}: {reason}");
+        }
+        if instrument.zones.is_empty() {
+            return Err(anyhow!("SFZ file {path:?} has no regions with samples"));
+        }
+        let mut sampler = SamplerCore::new_with_zones(instrument.zones);
+        if let Some(amp_envelope) = instrument.amp_envelope {
+            *sampler.amp_envelope_mut() = amp_envelope;
+        }
+        sampler.load()?;
+        Ok(sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizing() {
+        assert_eq!(
+            SfzInstrument::tokenize("<region>sample=Grand Piano C4.wav lokey=c4 <group> hikey=60"),
+            vec![
+                Token::Header("region".into()),
+                Token::Opcode("sample".into(), "Grand Piano C4.wav".into()),
+                Token::Opcode("lokey".into(), "c4".into()),
+                Token::Header("group".into()),
+                Token::Opcode("hikey".into(), "60".into()),
+            ]
+        );
+        assert_eq!(
+            SfzInstrument::strip_comments("a // b\nc /* d\ne */ f"),
+            "a \nc \n f"
+        );
+    }
+
+    #[test]
+    fn parsing() {
+        let text = r#"
+            // A piano with two velocity layers and round robin up top.
+            #define $LOOP_END 199
+            <control> default_path=samples\piano\
+            <global> ampeg_attack=0.01 ampeg_sustain=50 ampeg_release=0.6
+            <curve> sample=not-a-region.wav
+            <group> lovel=1 hivel=63
+            <region> sample=soft c3.wav key=48 tune=-50
+            <region> sample=soft c4.wav lokey=c#4 hikey=d5 pitch_keycenter=sample
+            <group> lovel=64 seq_length=2 loop_mode=loop_sustain
+            <region> sample=loud-2.wav seq_position=2 loop_start=100 loop_end=$LOOP_END
+            /* The first of the pair. */
+            <region> sample=loud-1.wav seq_position=1
+            <region> sample=*sine
+        "#;
+        let instrument = SfzInstrument::parse(text, Path::new("sfz")).unwrap();
+        let zones = &instrument.zones;
+        assert_eq!(zones.len(), 4, "Only regions with sample files count");
+
+        assert_eq!(
+            zones[0].source,
+            SampleSource::Path("sfz/samples/piano/soft c3.wav".into())
+        );
+        assert_eq!((zones[0].lowest_key, zones[0].highest_key), (48, 48));
+        assert_eq!(
+            (zones[0].lowest_velocity, zones[0].highest_velocity),
+            (1, 63)
+        );
+        let root = zones[0].root.unwrap();
+        assert!(
+            (root.0 - 130.8128 * 2.0f64.powf(0.5 / 12.0)).abs() < 0.001,
+            "Tuning down should raise the root, but got {root}"
+        );
+        assert_eq!(zones[0].sample_loop, None);
+        assert_eq!(zones[0].is_file_loop_sustain_only, None);
+        assert_eq!(zones[0].round_robin_group, None);
+
+        assert_eq!((zones[1].lowest_key, zones[1].highest_key), (61, 74));
+        assert_eq!(zones[1].root, None, "The root should come from the file");
+
+        assert_eq!(
+            zones[2].source,
+            SampleSource::Path("sfz/samples/piano/loud-1.wav".into()),
+            "Round-robin zones should be in seq_position order"
+        );
+        assert_eq!(
+            zones[2].sample_loop, None,
+            "It has no loop points of its own"
+        );
+        assert_eq!(
+            zones[2].is_file_loop_sustain_only,
+            Some(true),
+            "Its group's loop mode should apply to the file's loop points"
+        );
+        assert_eq!(
+            zones[3].sample_loop,
+            Some(SampleLoop {
+                mode: LoopMode::Forward,
+                start: 100,
+                end: 200,
+                is_sustain_only: true,
+            })
+        );
+        assert_eq!(zones[3].lowest_velocity, 64);
+        assert_eq!(zones[3].highest_velocity, 127);
+        assert!(zones[2].round_robin_group.is_some());
+        assert_eq!(zones[2].round_robin_group, zones[3].round_robin_group);
+
+        let amp_envelope = instrument.amp_envelope.unwrap();
+        assert_eq!(amp_envelope.sustain(), Normal::from(0.5));
+        assert!(
+            (Envelope::from_normal_to_seconds(amp_envelope.release()).0 - 0.6).abs() < 0.000001
+        );
+
+        assert!(instrument.skipped_regions.is_empty());
+
+        // Regions that no key triggers are left out quietly, and regions that
+        // can't be understood are left out with a reason. Either way, the
+        // rest of the file still loads.
+        let text = r#"
+            <region> sample=a.wav lokey=-1 hikey=c4
+            <region> sample=release.wav key=-1 trigger=release
+            <region> sample=cc.wav hikey=-1 on_locc64=127
+            <region> sample=b.wav lokey=h4
+            <region> sample=c.wav lovel=loud
+            <region> sample=d.wav loop_mode=loop_forever
+        "#;
+        let instrument = SfzInstrument::parse(text, Path::new("")).unwrap();
+        assert_eq!(instrument.zones.len(), 1);
+        let zone = &instrument.zones[0];
+        assert_eq!((zone.lowest_key, zone.highest_key), (0, 60));
+        assert_eq!(instrument.skipped_regions.len(), 3);
+    }
+
+    #[test]
+    fn loading_from_hives() {
+        let mut paths = Paths::default();
+        paths.push_hive(Paths::test_data_rel());
+        Paths::set_instance(paths);
+
+        let sampler = SamplerCore::new_from_sfz(Path::new("sfz/test-instrument.sfz")).unwrap();
+        assert_eq!(sampler.zones().len(), 3, "The included region should count");
+        assert_eq!(
+            sampler.amp_envelope().sustain(),
+            Normal::from(0.8),
+            "The envelope should come from the file"
+        );
+
+        assert!(SamplerCore::new_from_sfz(Path::new("sfz/nonexistent.sfz")).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
-use crate::egui::{activity_indicator, analyze_spectrum, FrequencyDomainWidget, TimeDomainWidget};
-use crate::types::VisualizationQueue;
+use crate::egui::{
+    activity_indicator, analyze_spectrum, level_meter, FrequencyDomainWidget, TimeDomainWidget,
+};
+use crate::types::{MeterReadingsQueue, VisualizationQueue};
 use eframe::{
     egui::{Image, ImageButton, Layout, Widget},
     epaint::vec2,
@@ -25,6 +27,8 @@ pub struct ControlBar {
     /// An owned VecDeque that acts as a ring buffer of the most recent
     /// generated audio frames.
     pub visualization_queue: VisualizationQueue,
+    /// The latest meter readings, for the master meter.
+    pub meter_readings_queue: MeterReadingsQueue,
     pub display_mode: ControlBarDisplayMode,
     pub fft_buffer: Vec<f32>,
 }
@@ -199,6 +203,20 @@ impl<'a> eframe::egui::Widget for ControlBarWidget<'a> {
                     }
                 });
             }
+            if let Ok(readings) = self.control_bar.meter_readings_queue.0.read() {
+                ui.add(level_meter(readings.master, 32.0));
+                let format_lufs = |lufs: Option<f64>| {
+                    lufs.filter(|lufs| lufs.is_finite())
+                        .map_or("--".to_string(), |lufs| format!("{lufs:.1}"))
+                };
+                ui.label(format!(
+                    "M {}\nS {}\nI {}",
+                    format_lufs(readings.loudness.momentary),
+                    format_lufs(readings.loudness.short_term),
+                    format_lufs(readings.loudness.integrated)
+                ))
+                .on_hover_text("Momentary, short-term, and integrated loudness (LUFS)");
+            }
             ui.separator();
             if ui
                 .add(ImageButton::new(
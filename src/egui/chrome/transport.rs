@@ -7,19 +7,24 @@ use ensnare::elements::Transport;
 use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumIter, IntoStaticStr};
 
-/// Renders a [Transport].
+/// Renders a [Transport]. The current time is passed separately because the
+/// project's tempo map, not the [Transport], knows where playback really is.
 #[derive(Debug)]
 pub struct TransportWidget<'a> {
     transport: &'a mut Transport,
+    current_time: MusicalTime,
 }
 impl<'a> TransportWidget<'a> {
-    fn new_with(transport: &'a mut Transport) -> Self {
-        Self { transport }
+    fn new_with(transport: &'a mut Transport, current_time: MusicalTime) -> Self {
+        Self {
+            transport,
+            current_time,
+        }
     }
 
     /// Instantiates a widget suitable for adding to a [Ui](eframe::egui::Ui).
-    pub fn widget(transport: &'a mut Transport) -> impl Widget + 'a {
-        move |ui: &mut Ui| TransportWidget::new_with(transport).ui(ui)
+    pub fn widget(transport: &'a mut Transport, current_time: MusicalTime) -> impl Widget + 'a {
+        move |ui: &mut Ui| TransportWidget::new_with(transport, current_time).ui(ui)
     }
 }
 impl<'a> Widget for TransportWidget<'a> {
@@ -32,7 +37,7 @@ impl<'a> Widget for TransportWidget<'a> {
                     .speed(0.1)
                     .suffix(" BPM"),
             ) | ui.add(Label::new(
-                RichText::new(format!("{}", self.transport.current_time()))
+                RichText::new(format!("{}", self.current_time))
                     .text_style(eframe::egui::TextStyle::Monospace),
             )) | ui.add(TimeSignatureWidget::widget(
                 &mut self.transport.time_signature,
@@ -125,11 +125,7 @@ impl<'a> eframe::egui::Widget for ArrangementWidget<'a> {
                                     arrangement_extent,
                                 ));
                                 pattern.notes().iter().for_each(|note| {
-                                    let note = Note::new_with_start_and_end(
-                                        note.key,
-                                        note.extent.0.start + arrangement.position,
-                                        note.extent.0.end + arrangement.position,
-                                    );
+                                    let note = note.shift_right(arrangement.position);
                                     shape_v.push(Self::shape_for_note(
                                         &to_screen,
                                         &visuals,
@@ -86,6 +86,7 @@ struct PrototypeComposerWidgetMemory {
 pub struct ComposerWidget<'a> {
     time_signature: TimeSignature,
     notes: &'a mut Vec<Note>,
+    action: Option<&'a mut Option<ComposerWidgetAction>>,
     note_range: MidiNoteRange,
     time_labeler: TimeLabeler,
     note_labeler: NoteLabeler,
@@ -338,11 +339,25 @@ impl<'a> eframe::egui::Widget for ComposerWidget<'a> {
             let note = MidiNote::from_repr(hover_pos_data.y.floor() as usize).unwrap();
             let section = hover_pos_data.x.floor() as usize;
             if response.clicked() {
-                self.notes.push(Self::create_note(note, section));
+                let note = Self::create_note(note, section);
+                if let Some(action) = self.action {
+                    *action = Some(ComposerWidgetAction::AddNotes(vec![note]));
+                } else {
+                    self.notes.push(note);
+                }
                 response.mark_changed();
             } else if response.clicked_by(PointerButton::Secondary) {
+                // Velocity doesn't matter; the user is pointing at a key and time.
                 let note_to_remove = Self::create_note(note, section);
-                self.notes.retain(|n| *n != note_to_remove);
+                let is_match =
+                    |n: &Note| n.key == note_to_remove.key && n.extent == note_to_remove.extent;
+                if let Some(action) = self.action {
+                    *action = Some(ComposerWidgetAction::RemoveNotes(
+                        self.notes.iter().filter(|n| is_match(n)).cloned().collect(),
+                    ));
+                } else {
+                    self.notes.retain(|n| !is_match(n));
+                }
                 response.mark_changed();
             }
         }
@@ -380,12 +395,21 @@ impl<'a> eframe::egui::Widget for ComposerWidget<'a> {
     }
 }
 
+/// A note edit that a [ComposerWidget] asks its owner to make. See
+/// [ComposerWidget::action()].
+#[derive(Debug)]
+pub enum ComposerWidgetAction {
+    AddNotes(Vec<Note>),
+    RemoveNotes(Vec<Note>),
+}
+
 impl<'a> ComposerWidget<'a> {
     /// Creates a new widget.
     pub fn new(notes: &'a mut Vec<Note>) -> Self {
         Self {
             time_signature: Default::default(),
             notes,
+            action: Default::default(),
             note_range: Default::default(),
             time_labeler: Default::default(),
             note_labeler: Default::default(),
@@ -428,14 +452,19 @@ impl<'a> ComposerWidget<'a> {
         self
     }
 
+    /// Reports note edits here instead of making them, so that the owner can
+    /// make them in a way that can be undone.
+    pub fn action(mut self, action: &'a mut Option<ComposerWidgetAction>) -> Self {
+        self.action = Some(action);
+        self
+    }
+
     fn create_note(midi_note: MidiNote, section: usize) -> Note {
-        Note {
-            key: midi_note as u8,
-            extent: TimeRange::new_with_start_and_duration(
-                MusicalTime::new_with_fractional_beats(section as f64 / 4.0),
-                MusicalTime::DURATION_QUARTER,
-            ),
-        }
+        Note::new_with(
+            midi_note as u8,
+            MusicalTime::new_with_fractional_beats(section as f64 / 4.0),
+            MusicalTime::DURATION_QUARTER,
+        )
     }
 
     /// Instantiates a widget suitable for adding to a [Ui](eframe::egui::Ui).
@@ -1,7 +1,7 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
 pub use arrangement::{ArrangementWidget, ArrangementWidgetAction};
-pub use editor::{ComposerWidget, NoteLabeler, TimeLabeler};
+pub use editor::{ComposerWidget, ComposerWidgetAction, NoteLabeler, TimeLabeler};
 
 mod arrangement;
 mod carousel;
@@ -8,7 +8,7 @@ use eframe::{
     epaint::{pos2, RectShape, Shape},
 };
 
-/// An egui widget that draws a grid in the timeline view.
+/// An egui widget that draws a grid of bar lines in the timeline view.
 #[derive(Debug, Default)]
 pub struct GridWidget {
     /// The timeline's full time range.
@@ -16,6 +16,12 @@ pub struct GridWidget {
 
     /// The GUI view's time range.
     view_range: ViewRange,
+
+    /// The time signature in effect before any changes.
+    time_signature: TimeSignature,
+
+    /// Changes in time signature along the timeline.
+    time_signature_map: TimeSignatureMap,
 }
 impl GridWidget {
     fn range(mut self, range: ViewRange) -> Self {
@@ -26,13 +32,28 @@ impl GridWidget {
         self.view_range = view_range;
         self
     }
+    fn time_signature(mut self, time_signature: TimeSignature) -> Self {
+        self.time_signature = time_signature;
+        self
+    }
+    fn time_signature_map(mut self, time_signature_map: TimeSignatureMap) -> Self {
+        self.time_signature_map = time_signature_map;
+        self
+    }
 
     /// Instantiates a widget suitable for adding to a [Ui](eframe::egui::Ui).
-    pub fn widget(range: ViewRange, view_range: ViewRange) -> impl eframe::egui::Widget {
+    pub fn widget(
+        range: ViewRange,
+        view_range: ViewRange,
+        time_signature: TimeSignature,
+        time_signature_map: TimeSignatureMap,
+    ) -> impl eframe::egui::Widget {
         move |ui: &mut eframe::egui::Ui| {
             GridWidget::default()
                 .range(range)
                 .view_range(view_range)
+                .time_signature(time_signature)
+                .time_signature_map(time_signature_map)
                 .ui(ui)
         }
     }
@@ -44,8 +65,8 @@ impl eframe::egui::Widget for GridWidget {
         let (rect, response) = ui.allocate_exact_size(desired_size, eframe::egui::Sense::hover());
         let to_screen = RectTransform::from_to(
             eframe::epaint::Rect::from_x_y_ranges(
-                self.view_range.0.start.total_beats() as f32
-                    ..=self.view_range.0.end.total_beats() as f32,
+                self.view_range.0.start.total_units() as f32
+                    ..=self.view_range.0.end.total_units() as f32,
                 0.0..=1.0,
             ),
             rect,
@@ -58,7 +79,12 @@ impl eframe::egui::Widget for GridWidget {
             visuals.bg_fill,
         ))];
 
-        for x in LegendWidget::steps(&self.view_range) {
+        for (_, start) in LegendWidget::steps(
+            &self.view_range,
+            &self.time_signature,
+            &self.time_signature_map,
+        ) {
+            let x = start.total_units();
             shapes.push(Shape::LineSegment {
                 points: [
                     to_screen * pos2(x as f32, 0.0),
@@ -1,6 +1,10 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-use eframe::epaint::Rounding;
+use crate::orchestration::LevelReading;
+use eframe::{
+    egui::{pos2, vec2, Rect, Sense},
+    epaint::{Color32, Rounding, Stroke},
+};
 
 /// Draws an animated activity indicator that lights up immediately upon
 /// activity and then fades if the activity stops.
@@ -32,3 +36,69 @@ pub fn activity_indicator(is_active: bool) -> impl eframe::egui::Widget + 'stati
         response
     }
 }
+
+/// Draws a stereo level meter: a bar for each channel's RMS level, a brighter
+/// line at its peak, a thin line at its held peak, and a red cap if the signal
+/// has clipped.
+pub fn level_meter(reading: LevelReading, height: f32) -> impl eframe::egui::Widget + 'static {
+    // The quietest level that the meter shows.
+    const FLOOR_DB: f64 = -60.0;
+    let to_fraction = move |amplitude: f64| -> f32 {
+        let db = LevelReading::to_dbfs(amplitude).clamp(FLOOR_DB, 0.0);
+        (1.0 - db / FLOOR_DB) as f32
+    };
+
+    move |ui: &mut eframe::egui::Ui| {
+        let (rect, response) =
+            ui.allocate_exact_size(vec2(9.0, height), Sense::focusable_noninteractive());
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+        let painter = ui.painter();
+        painter.rect_filled(rect, Rounding::default(), ui.visuals().extreme_bg_color);
+
+        let clip_height = 3.0;
+        let bar_rect = Rect::from_min_max(pos2(rect.left(), rect.top() + clip_height), rect.max);
+        let level_y =
+            |amplitude: f64| bar_rect.bottom() - bar_rect.height() * to_fraction(amplitude);
+        for channel in 0..2 {
+            let left = rect.left() + channel as f32 * 5.0;
+            let right = left + 4.0;
+            painter.rect_filled(
+                Rect::from_min_max(
+                    pos2(left, level_y(reading.rms[channel])),
+                    pos2(right, rect.bottom()),
+                ),
+                Rounding::default(),
+                Color32::DARK_GREEN,
+            );
+            painter.line_segment(
+                [
+                    pos2(left, level_y(reading.peak[channel])),
+                    pos2(right, level_y(reading.peak[channel])),
+                ],
+                Stroke::new(2.0, Color32::GREEN),
+            );
+            painter.line_segment(
+                [
+                    pos2(left, level_y(reading.peak_hold[channel])),
+                    pos2(right, level_y(reading.peak_hold[channel])),
+                ],
+                Stroke::new(1.0, Color32::YELLOW),
+            );
+        }
+        if reading.has_clipped {
+            painter.rect_filled(
+                Rect::from_min_max(rect.min, pos2(rect.right(), rect.top() + clip_height)),
+                Rounding::default(),
+                Color32::RED,
+            );
+        }
+
+        response.on_hover_text(format!(
+            "Peak {:.1} / {:.1} dBFS",
+            LevelReading::to_dbfs(reading.peak_hold[0]),
+            LevelReading::to_dbfs(reading.peak_hold[1])
+        ))
+    }
+}
@@ -1,7 +1,11 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
-use crate::{cores::instruments::SamplerCore, prelude::*};
-use eframe::egui::{ComboBox, Widget};
+use crate::{
+    cores::instruments::{LoopMode, SamplerCore},
+    egui::{util::EnumComboBoxWidget, EnvelopeWidget},
+    prelude::*,
+};
+use eframe::egui::{Checkbox, CollapsingHeader, ComboBox, DragValue, Slider, Widget};
 use ensnare::prelude::*;
 use strum_macros::Display;
 
@@ -43,6 +47,60 @@ impl<'a> eframe::egui::Widget for SamplerWidget<'a> {
         if response.changed() {
             *self.action = Some(SamplerWidgetAction::Load(selected.into()));
         }
-        response
+
+        let mut interpolation = self.inner.interpolation();
+        let response = response | ui.add(EnumComboBoxWidget::new(&mut interpolation, "Quality"));
+        if interpolation != self.inner.interpolation() {
+            self.inner.set_interpolation(interpolation);
+        }
+
+        let mut sample_loop = self.inner.calculated_sample_loop();
+        let is_looping = sample_loop.mode != LoopMode::Off;
+        let response = response
+            | ui.add(EnumComboBoxWidget::new(&mut sample_loop.mode, "Loop"))
+            | ui.add_enabled(
+                is_looping,
+                DragValue::new(&mut sample_loop.start).prefix("Start "),
+            )
+            | ui.add_enabled(
+                is_looping,
+                DragValue::new(&mut sample_loop.end).prefix("End "),
+            )
+            | ui.add_enabled(
+                is_looping,
+                Checkbox::new(&mut sample_loop.is_sustain_only, "Sustain only"),
+            );
+        if sample_loop != self.inner.calculated_sample_loop() {
+            self.inner.set_sample_loop(Some(sample_loop));
+        }
+
+        let mut response = response
+            | CollapsingHeader::new("Amplitude")
+                .default_open(true)
+                .id_source(ui.next_auto_id())
+                .show_unindented(ui, |ui| {
+                    if ui
+                        .add(EnvelopeWidget::widget(self.inner.amp_envelope_mut()))
+                        .changed()
+                    {
+                        self.inner.notify_change_amp_envelope();
+                    }
+                })
+                .header_response;
+
+        let mut velocity_to_gain = self.inner.velocity_to_gain().0;
+        let slider_response =
+            ui.add(Slider::new(&mut velocity_to_gain, 0.0..=1.0).text("Velocity to Gain"));
+        if slider_response.changed() {
+            self.inner.set_velocity_to_gain(velocity_to_gain.into());
+        }
+        response |= slider_response;
+        let mut velocity_to_filter = self.inner.velocity_to_filter().0;
+        let slider_response =
+            ui.add(Slider::new(&mut velocity_to_filter, 0.0..=1.0).text("Velocity to Filter"));
+        if slider_response.changed() {
+            self.inner.set_velocity_to_filter(velocity_to_filter.into());
+        }
+        response | slider_response
     }
 }
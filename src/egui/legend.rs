@@ -8,26 +8,57 @@ use eframe::{
 };
 
 /// An egui widget that draws a legend on the horizontal axis of the timeline
-/// view.
+/// view. It labels bars, so it needs to know the meter.
 #[derive(Debug)]
 pub struct LegendWidget<'a> {
     /// The GUI view's time range.
     view_range: &'a mut ViewRange,
+
+    /// The time signature in effect before any changes.
+    time_signature: TimeSignature,
+
+    /// Changes in time signature along the timeline.
+    time_signature_map: &'a TimeSignatureMap,
 }
 impl<'a> LegendWidget<'a> {
-    fn new(view_range: &'a mut ViewRange) -> Self {
-        Self { view_range }
+    fn new(
+        view_range: &'a mut ViewRange,
+        time_signature: TimeSignature,
+        time_signature_map: &'a TimeSignatureMap,
+    ) -> Self {
+        Self {
+            view_range,
+            time_signature,
+            time_signature_map,
+        }
     }
 
     /// Instantiates a widget suitable for adding to a [Ui](eframe::egui::Ui).
-    pub fn widget(view_range: &mut ViewRange) -> impl eframe::egui::Widget + '_ {
-        move |ui: &mut eframe::egui::Ui| LegendWidget::new(view_range).ui(ui)
+    pub fn widget(
+        view_range: &'a mut ViewRange,
+        time_signature: TimeSignature,
+        time_signature_map: &'a TimeSignatureMap,
+    ) -> impl eframe::egui::Widget + 'a {
+        move |ui: &mut eframe::egui::Ui| {
+            LegendWidget::new(view_range, time_signature, time_signature_map).ui(ui)
+        }
     }
 
-    pub(super) fn steps(view_range: &ViewRange) -> std::iter::StepBy<core::ops::Range<usize>> {
-        let beat_count = view_range.0.end.total_beats() - view_range.0.start.total_beats();
-        let step = (beat_count as f32).log10().round() as usize;
-        (view_range.0.start.total_beats()..view_range.0.end.total_beats()).step_by(step * 2)
+    /// Returns the bars (and their start times) that should be marked in the
+    /// given view range. If there are too many to fit, only every second,
+    /// fourth, etc. bar is included.
+    pub(super) fn steps(
+        view_range: &ViewRange,
+        time_signature: &TimeSignature,
+        time_signature_map: &TimeSignatureMap,
+    ) -> Vec<(usize, MusicalTime)> {
+        let bar_starts =
+            time_signature_map.bar_starts(time_signature, &TimeRange(view_range.0.clone()));
+        let step = (bar_starts.len() / 16).next_power_of_two();
+        bar_starts
+            .into_iter()
+            .filter(|(bar, _)| bar % step == 0)
+            .collect()
     }
 }
 impl<'a> eframe::egui::Widget for LegendWidget<'a> {
@@ -36,21 +67,25 @@ impl<'a> eframe::egui::Widget for LegendWidget<'a> {
         let (rect, response) = ui.allocate_exact_size(desired_size, eframe::egui::Sense::click());
         let to_screen = RectTransform::from_to(
             eframe::epaint::Rect::from_x_y_ranges(
-                self.view_range.0.start.total_beats() as f32
-                    ..=self.view_range.0.end.total_beats() as f32,
+                self.view_range.0.start.total_units() as f32
+                    ..=self.view_range.0.end.total_units() as f32,
                 rect.top()..=rect.bottom(),
             ),
             rect,
         );
 
         let font_id = FontId::proportional(12.0);
-        for beat in Self::steps(self.view_range) {
-            let beat_plus_one = beat + 1;
-            let pos = to_screen * pos2(beat as f32, rect.top());
+        for (bar, start) in Self::steps(
+            self.view_range,
+            &self.time_signature,
+            self.time_signature_map,
+        ) {
+            let bar_plus_one = bar + 1;
+            let pos = to_screen * pos2(start.total_units() as f32, rect.top());
             ui.painter().text(
                 pos,
-                Align2::CENTER_TOP,
-                format!("{beat_plus_one}"),
+                Align2::LEFT_TOP,
+                format!("{bar_plus_one}"),
                 font_id.clone(),
                 ui.style().noninteractive().text_color(),
             );
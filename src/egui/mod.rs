@@ -6,7 +6,7 @@
 pub use {
     automation::TargetInstrument,
     chrome::{ControlBar, ControlBarAction, ControlBarWidget, TransportWidget},
-    composition::{ComposerWidget, NoteLabeler, TimeLabeler},
+    composition::{ComposerWidget, ComposerWidgetAction, NoteLabeler, TimeLabeler},
     entities::EntityPaletteWidget,
     generators::{EnvelopeWidget, OscillatorWidget},
     glue::DragNormalWidget,
@@ -49,7 +49,7 @@ pub(in crate::egui) use {
         analyze_spectrum, FrequencyDomainWidget, FrequencyWidget, TimeDomainWidget, WaveformWidget,
     },
     grid::GridWidget,
-    indicators::activity_indicator,
+    indicators::{activity_indicator, level_meter},
     legend::LegendWidget,
     util::fill_remaining_ui_space,
 };
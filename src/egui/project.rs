@@ -6,7 +6,10 @@ use super::{
     },
     LegendWidget,
 };
-use crate::{orchestration::TrackViewMode, prelude::*};
+use crate::{
+    orchestration::{TrackTitle, TrackViewMode},
+    prelude::*,
+};
 use eframe::{egui::Widget, epaint::Galley};
 use ensnare::prelude::*;
 use std::sync::Arc;
@@ -35,12 +38,15 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
         // The timeline needs to be aligned with the track content, so
         // we create an empty track title bar to match with the real
         // ones.
+        let time_signature = self.project.time_signature();
         let response = ui
             .horizontal(|ui| {
                 let mut action = None;
-                ui.add_enabled(false, TitleBarWidget::widget(None, &mut action));
+                ui.add_enabled(false, TitleBarWidget::widget(None, None, &mut action));
                 ui.add(LegendWidget::widget(
                     &mut self.project.view_state.view_range,
+                    time_signature,
+                    &self.project.time_signature_map,
                 ));
             })
             .response;
@@ -49,9 +55,20 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
         eframe::egui::ScrollArea::vertical()
             .id_source("orchestrator-scroller")
             .show(ui, |ui| {
-                let track_uids = self.project.orchestrator.track_uids().to_vec();
+                // The master track comes last, like the end of the signal
+                // flow.
+                let mut track_uids = self.project.orchestrator.track_uids().to_vec();
+                track_uids.push(Orchestrator::MASTER_TRACK_UID);
+                let master_track_title = TrackTitle("Master".to_string());
                 for track_uid in track_uids {
-                    let track_title = self.project.track_titles.get(&track_uid);
+                    if self.project.is_track_folded_away(track_uid) {
+                        continue;
+                    }
+                    let track_title = if track_uid == Orchestrator::MASTER_TRACK_UID {
+                        Some(&master_track_title)
+                    } else {
+                        self.project.track_titles.get(&track_uid)
+                    };
                     let font_galley: Option<Arc<Galley>> = if let Some(track_title) = track_title {
                         Some(make_title_bar_galley(ui, track_title))
                     } else {
@@ -77,6 +94,11 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
                         title_font_galley: font_galley,
                         color_scheme,
                         new_arrangement_to_select,
+                        is_folded: if self.project.is_group_track(track_uid) {
+                            Some(self.project.is_group_folded(track_uid))
+                        } else {
+                            None
+                        },
                     };
                     ui.add(TrackWidget::widget(&track_info, self.project, &mut action));
                     if let Some(action) = action {
@@ -104,11 +126,17 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
                             TrackWidgetAction::AdvanceTimelineView(track_uid) => {
                                 self.project.advance_track_view_mode(track_uid);
                             }
+                            TrackWidgetAction::ToggleFold(track_uid) => {
+                                let is_folded = self.project.is_group_folded(track_uid);
+                                self.project.set_group_folded(track_uid, !is_folded);
+                            }
                             TrackWidgetAction::CreateAutomationLane(track_uid) => {
-                                if let Ok(path_uid) = self.project.add_path(
-                                    track_uid,
-                                    SignalPathBuilder::default().build().unwrap(),
-                                ) {
+                                if let Ok(path_uid) = self.project.edit(|project| {
+                                    project.add_path(
+                                        track_uid,
+                                        SignalPathBuilder::default().build().unwrap(),
+                                    )
+                                }) {
                                     self.project.set_track_view_mode(
                                         track_uid,
                                         TrackViewMode::Control(path_uid),
@@ -116,12 +144,9 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
                                 }
                             }
                             TrackWidgetAction::ArrangePattern(pattern_uid, position) => {
-                                let _ = self.project.arrange_pattern(
-                                    track_uid,
-                                    pattern_uid,
-                                    None,
-                                    position,
-                                );
+                                let _ = self.project.edit(|project| {
+                                    project.arrange_pattern(track_uid, pattern_uid, None, position)
+                                });
                                 switch_to_composition = true;
                             }
                             TrackWidgetAction::MoveArrangement(
@@ -129,57 +154,67 @@ impl<'a> eframe::egui::Widget for ProjectWidget<'a> {
                                 position,
                                 is_shift_pressed,
                             ) => {
-                                let _ = self.project.move_arrangement(
-                                    track_uid,
-                                    arrangement_uid,
-                                    position,
-                                    is_shift_pressed,
-                                );
+                                let _ = self.project.edit(|project| {
+                                    project.move_arrangement(
+                                        track_uid,
+                                        arrangement_uid,
+                                        position,
+                                        is_shift_pressed,
+                                    )
+                                });
                                 switch_to_composition = true;
                             }
                             TrackWidgetAction::LinkPath(path_uid, uid, param) => {
-                                let _ = self.project.link_path(path_uid, uid, param);
+                                let _ = self
+                                    .project
+                                    .edit(|project| project.link_path(path_uid, uid, param));
                                 self.project.regenerate_signal_chain(track_uid);
                             }
                             TrackWidgetAction::UnlinkPath(path_uid, uid, param) => {
+                                self.project.checkpoint();
                                 self.project.unlink_path(path_uid, uid, param);
                                 self.project.regenerate_signal_chain(track_uid);
                             }
                             TrackWidgetAction::Unarrange(arrangement_uid) => {
+                                self.project.checkpoint();
                                 self.project.unarrange(track_uid, arrangement_uid);
                             }
                             TrackWidgetAction::Duplicate(arrangement_uid) => {
-                                if let Ok(new_uid) = self
-                                    .project
-                                    .duplicate_arrangement(track_uid, arrangement_uid)
-                                {
+                                if let Ok(new_uid) = self.project.edit(|project| {
+                                    project.duplicate_arrangement(track_uid, arrangement_uid)
+                                }) {
                                     self.project.set_new_arrangement_uid(track_uid, new_uid);
                                 }
                             }
                             TrackWidgetAction::AddPattern(position) => {
-                                if let Ok(pattern_uid) = self.project.add_pattern(
-                                    PatternBuilder::default()
-                                        .time_signature(self.project.time_signature())
-                                        .color_scheme(
-                                            self.project
-                                                .composer
-                                                .suggest_next_pattern_color_scheme(),
-                                        )
-                                        .build()
-                                        .unwrap(),
-                                    None,
-                                ) {
-                                    let quantized_position = position
-                                        .quantized_to_measure(&self.project.time_signature());
-                                    if let Ok(new_uid) = self.project.arrange_pattern(
+                                let quantized_position =
+                                    self.project.quantized_to_measure(position);
+                                // Adding and arranging the pattern is one step
+                                // as far as undo is concerned.
+                                if let Ok(new_uid) = self.project.edit(|project| {
+                                    let pattern_uid = project.add_pattern(
+                                        PatternBuilder::default()
+                                            .time_signature(
+                                                project.time_signature_at(quantized_position),
+                                            )
+                                            .color_scheme(
+                                                project
+                                                    .composer
+                                                    .suggest_next_pattern_color_scheme(),
+                                            )
+                                            .build()
+                                            .unwrap(),
+                                        None,
+                                    )?;
+                                    project.arrange_pattern(
                                         track_uid,
                                         pattern_uid,
                                         None,
                                         quantized_position,
-                                    ) {
-                                        self.project.composer.clear_edited_pattern();
-                                        self.project.set_new_arrangement_uid(track_uid, new_uid);
-                                    }
+                                    )
+                                }) {
+                                    self.project.composer.clear_edited_pattern();
+                                    self.project.set_new_arrangement_uid(track_uid, new_uid);
                                 }
                             }
                             TrackWidgetAction::ClearEditPattern => {
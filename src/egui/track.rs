@@ -4,6 +4,7 @@ use super::{
     automation::{SignalPathWidget, SignalPathWidgetAction},
     composition::{ArrangementWidget, ArrangementWidgetAction},
     cursor::CursorWidget,
+    level_meter,
     signal_chain::{SignalChainWidget, SignalChainWidgetAction},
     util::fill_remaining_ui_space,
     GridWidget,
@@ -14,7 +15,7 @@ use crate::{
     types::ColorScheme,
 };
 use eframe::{
-    egui::{Frame, Image, ImageButton, Margin, Sense, TextFormat, Widget},
+    egui::{Button, Frame, Image, ImageButton, Margin, Sense, TextFormat, Widget},
     emath::{Align, RectTransform},
     epaint::{
         text::LayoutJob, vec2, Color32, FontId, Galley, Rect, Shape, Stroke, TextShape, Vec2,
@@ -47,12 +48,15 @@ pub enum TitleBarWidgetAction {
     NextTimelineView,
     /// Add a new automation lane.
     NewAutomationLane,
+    /// Hide or show the tracks in a group.
+    ToggleFold,
 }
 
 /// An egui widget that draws a track's sideways title bar.
 #[derive(Debug)]
 pub struct TitleBarWidget<'a> {
     font_galley: Option<Arc<Galley>>,
+    is_folded: Option<bool>,
     action: &'a mut Option<TitleBarWidgetAction>,
 }
 impl<'a> eframe::egui::Widget for TitleBarWidget<'a> {
@@ -109,6 +113,20 @@ impl<'a> eframe::egui::Widget for TitleBarWidget<'a> {
                                 {
                                     *self.action = Some(TitleBarWidgetAction::NewAutomationLane);
                                 }
+                                if let Some(is_folded) = self.is_folded {
+                                    let (text, hover_text) = if is_folded {
+                                        ("▸", "Show the tracks in this group")
+                                    } else {
+                                        ("▾", "Hide the tracks in this group")
+                                    };
+                                    if ui
+                                        .add(Button::new(text).frame(false).small())
+                                        .on_hover_text(hover_text)
+                                        .clicked()
+                                    {
+                                        *self.action = Some(TitleBarWidgetAction::ToggleFold);
+                                    }
+                                }
                             });
                         }
                         let (response, painter) =
@@ -135,19 +153,26 @@ impl<'a> eframe::egui::Widget for TitleBarWidget<'a> {
     }
 }
 impl<'a> TitleBarWidget<'a> {
-    fn new(font_galley: Option<Arc<Galley>>, action: &'a mut Option<TitleBarWidgetAction>) -> Self {
+    fn new(
+        font_galley: Option<Arc<Galley>>,
+        is_folded: Option<bool>,
+        action: &'a mut Option<TitleBarWidgetAction>,
+    ) -> Self {
         Self {
             font_galley,
+            is_folded,
             action,
         }
     }
 
     /// Don't have a font_galley? Check out [make_title_bar_galley()].
+    /// `is_folded` is None unless the track is a group track.
     pub fn widget(
         font_galley: Option<Arc<Galley>>,
+        is_folded: Option<bool>,
         action: &'a mut Option<TitleBarWidgetAction>,
     ) -> impl eframe::egui::Widget + 'a {
-        move |ui: &mut eframe::egui::Ui| TitleBarWidget::new(font_galley, action).ui(ui)
+        move |ui: &mut eframe::egui::Ui| TitleBarWidget::new(font_galley, is_folded, action).ui(ui)
     }
 }
 
@@ -163,6 +188,8 @@ pub struct TrackWidgetInfo {
     pub title_font_galley: Option<Arc<Galley>>,
     pub color_scheme: ColorScheme,
     pub new_arrangement_to_select: Option<ArrangementUid>,
+    /// Whether a group track's tracks are hidden. None if it's not a group.
+    pub is_folded: Option<bool>,
 }
 
 #[derive(Debug, Display)]
@@ -177,6 +204,8 @@ pub enum TrackWidgetAction {
     NewDevice(EntityKey),
     /// Show the next timeline view.
     AdvanceTimelineView(TrackUid),
+    /// Hide or show the tracks in this group.
+    ToggleFold(TrackUid),
     CreateAutomationLane(TrackUid),
     ArrangePattern(PatternUid, MusicalTime),
     MoveArrangement(ArrangementUid, MusicalTime, bool),
@@ -225,6 +254,7 @@ impl<'a> Widget for TrackWidget<'a> {
     fn ui(self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         let track_uid = self.track_info.track_uid;
         let time_signature = self.project.time_signature().clone();
+        let time_signature_map = self.project.time_signature_map.clone();
         let track_info = self.project.e.track_info.entry(track_uid).or_default();
 
         // inner_margin() should be half of the Frame stroke width to leave room
@@ -259,7 +289,11 @@ impl<'a> Widget for TrackWidget<'a> {
                         .as_ref()
                         .map(|fg| Arc::clone(&fg));
                     let mut action = None;
-                    let response = ui.add(TitleBarWidget::widget(font_galley, &mut action));
+                    let response = ui.add(TitleBarWidget::widget(
+                        font_galley,
+                        self.track_info.is_folded,
+                        &mut action,
+                    ));
                     if let Some(action) = action {
                         match action {
                             TitleBarWidgetAction::NextTimelineView => {
@@ -270,12 +304,32 @@ impl<'a> Widget for TrackWidget<'a> {
                                 *self.action =
                                     Some(TrackWidgetAction::CreateAutomationLane(track_uid));
                             }
+                            TitleBarWidgetAction::ToggleFold => {
+                                *self.action = Some(TrackWidgetAction::ToggleFold(track_uid));
+                            }
                         }
                     }
                     if response.clicked() {
                         *self.action = Some(TrackWidgetAction::Clicked);
                     }
 
+                    let reading = self
+                        .project
+                        .e
+                        .meter_readings_queue
+                        .as_ref()
+                        .and_then(|queue| {
+                            queue.0.read().ok().map(|readings| {
+                                if track_uid == Orchestrator::MASTER_TRACK_UID {
+                                    readings.master
+                                } else {
+                                    readings.tracks.get(&track_uid).copied().unwrap_or_default()
+                                }
+                            })
+                        })
+                        .unwrap_or_default();
+                    ui.add(level_meter(reading, Self::TRACK_HEIGHT));
+
                     // Take up all the space we're given, even if we can't fill
                     // it with widget content.
                     ui.set_min_size(ui.available_size());
@@ -311,6 +365,8 @@ impl<'a> Widget for TrackWidget<'a> {
                                     ui.add(GridWidget::widget(
                                         temp_range.clone(),
                                         self.project.view_state.view_range.clone(),
+                                        time_signature,
+                                        time_signature_map.clone(),
                                     ))
                                 })
                                 .inner
@@ -459,7 +515,8 @@ impl<'a> Widget for TrackWidget<'a> {
                         });
                         if let Some(track_source) = payload {
                             if let Some(time) = time {
-                                let position = time.quantized_to_measure(&time_signature);
+                                let position =
+                                    time_signature_map.quantized_to_measure(&time_signature, time);
                                 match *track_source {
                                     TrackSource::PatternUid(pattern_uid) => {
                                         *self.action = Some(TrackWidgetAction::ArrangePattern(
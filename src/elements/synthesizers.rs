@@ -108,6 +108,14 @@ impl<V: IsStereoSampleVoice> Synthesizer<V> {
         }
     }
 
+    /// The voice that a note on this key would play, or None if the store is
+    /// out of voices.
+    pub fn voice_for_key(&mut self, key: &u7) -> Option<&mut Box<V>> {
+        self.voice_store
+            .as_mut()
+            .and_then(|vs| vs.get_voice(key).ok())
+    }
+
     pub fn voice_count(&self) -> usize {
         if let Some(vs) = self.voice_store.as_ref() {
             vs.voice_count()
@@ -180,8 +188,7 @@ impl<V: IsStereoSampleVoice> HandlesMidi for Synthesizer<V> {
                 },
                 #[allow(unused_variables)]
                 MidiMessage::ProgramChange { program } => todo!(),
-                #[allow(unused_variables)]
-                MidiMessage::ChannelAftertouch { vel } => todo!(),
+                MidiMessage::ChannelAftertouch { vel } => self.set_channel_aftertouch(vel.as_int()),
                 #[allow(unused_variables)]
                 MidiMessage::PitchBend { bend } => self.set_pitch_bend(bend.as_f32()),
             }
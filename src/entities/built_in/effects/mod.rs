@@ -2,7 +2,11 @@
 
 pub mod filter;
 
-use crate::cores::effects::{self, BitcrusherCore, ChorusCore, CompressorCore, LimiterCore};
+use crate::{
+    cores::effects::{self, BitcrusherCore, ChorusCore, CompressorCore, GateCore, LimiterCore},
+    traits::TransformsAudioWithKey,
+};
+use delegate::delegate;
 use ensnare::prelude::*;
 use ensnare_proc_macros::{
     InnerConfigurable, InnerControllable, InnerEffect, InnerSerializable, IsEntity, Metadata,
@@ -79,6 +83,44 @@ impl Compressor {
         Self { uid, inner }
     }
 }
+impl TransformsAudioWithKey for Compressor {
+    delegate! {
+        to self.inner {
+            fn transform_with_key(&mut self, key: &[StereoSample], samples: &mut [StereoSample]);
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Default,
+    InnerControllable,
+    InnerConfigurable,
+    InnerEffect,
+    InnerSerializable,
+    IsEntity,
+    Metadata,
+    Serialize,
+    Deserialize,
+)]
+#[entity(Controls, GeneratesStereoSample, HandlesMidi, SkipInner)]
+
+pub struct Gate {
+    uid: Uid,
+    inner: GateCore,
+}
+impl Gate {
+    pub fn new_with(uid: Uid, inner: GateCore) -> Self {
+        Self { uid, inner }
+    }
+}
+impl TransformsAudioWithKey for Gate {
+    delegate! {
+        to self.inner {
+            fn transform_with_key(&mut self, key: &[StereoSample], samples: &mut [StereoSample]);
+        }
+    }
+}
 
 #[derive(
     Debug,
@@ -104,11 +146,19 @@ impl Limiter {
     }
 }
 
+/// Whether the entity is one of the effects that implement
+/// [TransformsAudioWithKey]. [Entity] lives in the ensnare crate and can't be
+/// asked for that trait, so this goes by the entity's key.
+pub fn accepts_sidechain_key(entity: &dyn Entity) -> bool {
+    [Compressor::ENTITY_KEY, Gate::ENTITY_KEY].contains(&entity.key())
+}
+
 #[cfg(feature = "egui")]
 mod egui {
-    use self::effects::BitcrusherCore;
+    use self::effects::{BitcrusherCore, CompressorDetector};
     use super::*;
-    use eframe::egui::Slider;
+    use eframe::egui::{ProgressBar, Slider};
+    use strum::IntoEnumIterator;
 
     impl Displays for Bitcrusher {
         fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
@@ -130,43 +180,140 @@ mod egui {
 
     impl Displays for Compressor {
         fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-            let mut threshold = self.inner.threshold().0;
-            let mut ratio = self.inner.ratio().0;
-            let mut attack = self.inner.attack().0;
-            let mut release = self.inner.release().0;
+            let mut threshold = self.inner.threshold();
+            let mut ratio = self.inner.ratio();
+            let mut knee = self.inner.knee();
+            let mut attack = self.inner.attack().0 * 1000.0;
+            let mut release = self.inner.release().0 * 1000.0;
+            let mut makeup_gain = self.inner.makeup_gain();
+            let mut detector = self.inner.detector();
             let threshold_response = ui.add(
-                Slider::new(&mut threshold, Normal::range())
-                    .fixed_decimals(2)
+                Slider::new(&mut threshold, -60.0..=0.0)
+                    .fixed_decimals(1)
+                    .suffix(" dB")
                     .text("Threshold"),
             );
             if threshold_response.changed() {
-                self.inner.set_threshold(threshold.into());
+                self.inner.set_threshold(threshold);
             };
             let ratio_response = ui.add(
-                Slider::new(&mut ratio, 0.05..=2.0)
-                    .fixed_decimals(2)
+                Slider::new(&mut ratio, 1.0..=20.0)
+                    .logarithmic(true)
+                    .fixed_decimals(1)
+                    .suffix(":1")
                     .text("Ratio"),
             );
             if ratio_response.changed() {
-                self.inner.set_ratio(ratio.into());
+                self.inner.set_ratio(ratio);
+            };
+            let knee_response = ui.add(
+                Slider::new(&mut knee, 0.0..=24.0)
+                    .fixed_decimals(1)
+                    .suffix(" dB")
+                    .text("Knee"),
+            );
+            if knee_response.changed() {
+                self.inner.set_knee(knee);
+            };
+            let attack_response = ui.add(
+                Slider::new(&mut attack, 0.0..=500.0)
+                    .fixed_decimals(1)
+                    .suffix(" ms")
+                    .text("Attack"),
+            );
+            if attack_response.changed() {
+                self.inner.set_attack((attack / 1000.0).into());
+            };
+            let release_response = ui.add(
+                Slider::new(&mut release, 0.0..=2000.0)
+                    .fixed_decimals(0)
+                    .suffix(" ms")
+                    .text("Release"),
+            );
+            if release_response.changed() {
+                self.inner.set_release((release / 1000.0).into());
+            };
+            let makeup_gain_response = ui.add(
+                Slider::new(&mut makeup_gain, 0.0..=24.0)
+                    .fixed_decimals(1)
+                    .suffix(" dB")
+                    .text("Makeup gain"),
+            );
+            if makeup_gain_response.changed() {
+                self.inner.set_makeup_gain(makeup_gain);
+            };
+            let detector_response = ui
+                .horizontal(|ui| {
+                    CompressorDetector::iter()
+                        .map(|d| ui.radio_value(&mut detector, d, d.to_string()))
+                        .reduce(|acc, r| acc | r)
+                        .unwrap()
+                })
+                .inner;
+            if detector_response.changed() {
+                self.inner.set_detector(detector);
+            };
+
+            // This is how much the compressor was turning things down at the
+            // end of the last buffer, not an average, so it'll jitter.
+            let gain_reduction = self.inner.gain_reduction();
+            ui.add(
+                ProgressBar::new((gain_reduction / 24.0).min(1.0) as f32)
+                    .text(format!("Gain reduction: {gain_reduction:.1} dB")),
+            );
+            threshold_response
+                | ratio_response
+                | knee_response
+                | attack_response
+                | release_response
+                | makeup_gain_response
+                | detector_response
+        }
+    }
+
+    impl Displays for Gate {
+        fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+            let mut threshold = self.inner.threshold();
+            let mut range = self.inner.range();
+            let mut attack = self.inner.attack().0 * 1000.0;
+            let mut release = self.inner.release().0 * 1000.0;
+            let threshold_response = ui.add(
+                Slider::new(&mut threshold, -80.0..=0.0)
+                    .fixed_decimals(1)
+                    .suffix(" dB")
+                    .text("Threshold"),
+            );
+            if threshold_response.changed() {
+                self.inner.set_threshold(threshold);
+            };
+            let range_response = ui.add(
+                Slider::new(&mut range, 0.0..=80.0)
+                    .fixed_decimals(1)
+                    .suffix(" dB")
+                    .text("Range"),
+            );
+            if range_response.changed() {
+                self.inner.set_range(range);
             };
             let attack_response = ui.add(
-                Slider::new(&mut attack, Normal::range())
-                    .fixed_decimals(2)
+                Slider::new(&mut attack, 0.0..=100.0)
+                    .fixed_decimals(1)
+                    .suffix(" ms")
                     .text("Attack"),
             );
             if attack_response.changed() {
-                self.inner.set_attack(attack.into());
+                self.inner.set_attack((attack / 1000.0).into());
             };
             let release_response = ui.add(
-                Slider::new(&mut release, Normal::range())
-                    .fixed_decimals(2)
+                Slider::new(&mut release, 0.0..=2000.0)
+                    .fixed_decimals(0)
+                    .suffix(" ms")
                     .text("Release"),
             );
             if release_response.changed() {
-                self.inner.set_release(release.into());
+                self.inner.set_release((release / 1000.0).into());
             };
-            threshold_response | ratio_response | attack_response | release_response
+            threshold_response | range_response | attack_response | release_response
         }
     }
 
@@ -3,7 +3,7 @@
 use super::{
     Arpeggiator, BiQuadFilterAllPass, BiQuadFilterBandPass, BiQuadFilterBandStop,
     BiQuadFilterHighPass, BiQuadFilterLowPass24db, Bitcrusher, Chorus, Compressor, Delay, Drumkit,
-    FmSynth, Gain, LfoController, Limiter, Reverb, Sampler, SignalPassthroughController,
+    FmSynth, Gain, Gate, LfoController, Limiter, Reverb, Sampler, SignalPassthroughController,
     SubtractiveSynth, Timer, Trigger,
 };
 use crate::{
@@ -12,7 +12,8 @@ use crate::{
         effects::{
             BiQuadFilterAllPassCoreBuilder, BiQuadFilterBandPassCoreBuilder,
             BiQuadFilterBandStopCoreBuilder, BiQuadFilterHighPassCoreBuilder,
-            BiQuadFilterLowPass24dbCoreBuilder, BitcrusherCoreBuilder, LimiterCoreBuilder,
+            BiQuadFilterLowPass24dbCoreBuilder, BitcrusherCoreBuilder, CompressorCoreBuilder,
+            GateCoreBuilder, LimiterCoreBuilder,
         },
     },
     elements::OscillatorBuilder,
@@ -76,8 +77,11 @@ impl BuiltInEntities {
             ))
         });
         factory.register_entity_with_str_key(Chorus::ENTITY_KEY, |_uid| Box::<Chorus>::default());
-        factory.register_entity_with_str_key(Compressor::ENTITY_KEY, |_uid| {
-            Box::<Compressor>::default()
+        factory.register_entity_with_str_key(Compressor::ENTITY_KEY, |uid| {
+            Box::new(Compressor::new_with(
+                uid,
+                CompressorCoreBuilder::default().build().unwrap(),
+            ))
         });
         factory.register_entity_with_str_key(BiQuadFilterLowPass24db::ENTITY_KEY, |uid| {
             Box::new(BiQuadFilterLowPass24db::new_with(
@@ -136,6 +140,12 @@ impl BuiltInEntities {
                     .unwrap(),
             ))
         });
+        factory.register_entity_with_str_key(Gate::ENTITY_KEY, |uid| {
+            Box::new(Gate::new_with(
+                uid,
+                GateCoreBuilder::default().build().unwrap(),
+            ))
+        });
         factory.register_entity_with_str_key(Limiter::ENTITY_KEY, |uid| {
             Box::new(Limiter::new_with(
                 uid,
@@ -21,6 +21,7 @@ use ensnare_proc_macros::{
     IsEntity, Metadata,
 };
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(
     Debug,
@@ -178,6 +179,16 @@ impl Sampler {
         }
     }
 
+    /// Creates a sampler from an SFZ file. See [SamplerCore::new_from_sfz()].
+    pub fn new_from_sfz(uid: Uid, path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            uid,
+            inner: SamplerCore::new_from_sfz(path)?,
+            widget_action: Default::default(),
+            action: Default::default(),
+        })
+    }
+
     pub fn load(&mut self) -> anyhow::Result<()> {
         self.inner.load()
     }
@@ -5,11 +5,12 @@
 pub use {
     controllers::{Arpeggiator, LfoController, SignalPassthroughController, Timer, Trigger},
     effects::{
+        accepts_sidechain_key,
         filter::{
             BiQuadFilterAllPass, BiQuadFilterBandPass, BiQuadFilterBandStop, BiQuadFilterHighPass,
             BiQuadFilterLowPass24db,
         },
-        Bitcrusher, Chorus, Compressor, Limiter,
+        Bitcrusher, Chorus, Compressor, Gate, Limiter,
     },
     instruments::{Drumkit, FmSynth, Sampler, SubtractiveSynth},
     //EntityFactory,
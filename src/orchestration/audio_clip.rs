@@ -0,0 +1,571 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+use crate::{
+    cores::instruments::{Interpolation, SamplerCore},
+    orchestration::TempoMap,
+    prelude::*,
+};
+use anyhow::{anyhow, Result};
+use delegate::delegate;
+use derivative::Derivative;
+use derive_builder::Builder;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use synonym::Synonym;
+
+/// A [Uid] that identifies an [AudioClip] in a project.
+#[derive(Synonym, Serialize, Deserialize)]
+pub struct AudioClipUid(usize);
+impl IsUid for AudioClipUid {
+    fn as_usize(&self) -> usize {
+        self.0
+    }
+}
+
+/// Mints unique [AudioClipUid]s.
+#[derive(Synonym, Debug, Serialize, Deserialize)]
+pub struct AudioClipUidFactory(UidFactory<AudioClipUid>);
+impl Default for AudioClipUidFactory {
+    fn default() -> Self {
+        Self(UidFactory::<AudioClipUid>::new(Self::FIRST_UID))
+    }
+}
+impl AudioClipUidFactory {
+    // Clip uids are their own type, so the compiler won't let one stand in for
+    // another kind of uid. Starting here just continues the sequence that
+    // pattern (131072) and arrangement (262144) uids use, so that a bare
+    // number in a log or a saved project is recognizable as a clip. A project
+    // would need 262144 arrangements before their numbers reached this one.
+    const FIRST_UID: usize = 524288;
+
+    delegate! {
+        to self.0 {
+            /// Generates the next unique [AudioClipUid].
+            pub fn mint_next(&self) -> AudioClipUid;
+        }
+    }
+}
+
+/// A recording, such as a WAV file, placed on an audio track.
+///
+/// The clip plays the part of its source that starts [AudioClip::trim_start]
+/// into it and lasts [AudioClip::length] (or to the end of the source, if
+/// there is no length). The played part is scaled by [AudioClip::gain] and
+/// shaped by linear fades at each end.
+#[derive(Debug, Builder, Derivative, Serialize, Deserialize)]
+#[derivative(Default)]
+#[serde(rename_all = "kebab-case")]
+#[builder(build_fn(private, name = "build_from_builder"))]
+pub struct AudioClip {
+    /// Where the audio comes from.
+    #[builder(default)]
+    pub source: SampleSource,
+    /// When the clip starts playing.
+    #[builder(default)]
+    pub position: MusicalTime,
+    /// How much of the start of the source to skip.
+    #[builder(default)]
+    #[serde(default)]
+    pub trim_start: Seconds,
+    /// How much of the source to play, or None to play all of it after
+    /// [AudioClip::trim_start].
+    #[builder(default)]
+    #[serde(default)]
+    pub length: Option<Seconds>,
+    /// The clip's volume.
+    #[builder(default = "Normal::maximum()")]
+    #[derivative(Default(value = "Normal::maximum()"))]
+    #[serde(default = "AudioClip::default_gain")]
+    pub gain: Normal,
+    /// How long the clip takes to rise from silence at its start.
+    #[builder(default)]
+    #[serde(default)]
+    pub fade_in: Seconds,
+    /// How long the clip takes to fall to silence at its end.
+    #[builder(default)]
+    #[serde(default)]
+    pub fade_out: Seconds,
+
+    #[builder(setter(skip))]
+    #[serde(skip)]
+    e: AudioClipEphemerals,
+}
+#[derive(Debug, Default)]
+pub struct AudioClipEphemerals {
+    samples: Option<Arc<Vec<StereoSample>>>,
+    sample_rate: SampleRate,
+}
+impl AudioClipBuilder {
+    /// Builds the [AudioClip] and loads its source.
+    pub fn build(&self) -> Result<AudioClip> {
+        match self.build_from_builder() {
+            Ok(mut clip) => {
+                clip.load()?;
+                Ok(clip)
+            }
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+}
+impl AudioClip {
+    fn default_gain() -> Normal {
+        Normal::maximum()
+    }
+
+    /// Reads the clip's source into memory.
+    pub fn load(&mut self) -> Result<()> {
+        let file = self.source.open()?;
+        let (samples, sample_rate) = SamplerCore::read_samples_and_sample_rate_from_file(&file)?;
+        self.e.samples = Some(Arc::new(samples));
+        self.e.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    /// Whether the clip's source is in memory.
+    pub fn is_loaded(&self) -> bool {
+        self.e.samples.is_some()
+    }
+
+    /// How long the clip plays, which is zero until the source is loaded.
+    pub fn duration(&self) -> Seconds {
+        let source_duration = if let Some(samples) = self.e.samples.as_ref() {
+            samples.len() as f64 / self.e.sample_rate.0 as f64
+        } else {
+            0.0
+        };
+        let available = (source_duration - self.trim_start.0).max(0.0);
+        if let Some(length) = self.length {
+            Seconds(length.0.clamp(0.0, available))
+        } else {
+            Seconds(available)
+        }
+    }
+
+    /// The time the clip occupies on the timeline, given the tempo map and
+    /// the tempo before its first change.
+    pub fn extent(&self, tempo_map: &TempoMap, initial_tempo: Tempo) -> TimeRange {
+        let end = tempo_map.seconds_to_time(
+            initial_tempo,
+            Seconds(self.start_seconds(tempo_map, initial_tempo) + self.duration().0),
+        );
+        TimeRange(self.position..end)
+    }
+
+    // How many seconds after time zero the clip starts.
+    fn start_seconds(&self, tempo_map: &TempoMap, initial_tempo: Tempo) -> f64 {
+        tempo_map.time_to_seconds(initial_tempo, self.position).0
+    }
+
+    // The clip's level, including fades, the given number of seconds after it
+    // starts.
+    fn amplitude_at(&self, seconds: f64, duration: f64) -> f64 {
+        let mut amplitude = self.gain.0;
+        if seconds < self.fade_in.0 {
+            amplitude *= seconds / self.fade_in.0;
+        }
+        let remaining = duration - seconds;
+        if remaining < self.fade_out.0 {
+            amplitude *= remaining / self.fade_out.0;
+        }
+        amplitude
+    }
+
+    // Adds the clip's audio to the given buffer, whose first frame happens
+    // `seconds_at_start` after the clip starts (negative if it hasn't started
+    // yet). The clip's position within its source is calculated from scratch
+    // each time, so jumps in the timeline need no special handling.
+    fn render(&self, seconds_at_start: f64, sample_rate: SampleRate, values: &mut [StereoSample]) {
+        let Some(samples) = self.e.samples.as_ref() else {
+            return;
+        };
+        let duration = self.duration().0;
+        let clip_sample_rate = self.e.sample_rate.0 as f64;
+        // How far through the source each output frame moves. Sources
+        // recorded at another rate fall between their samples, so they're
+        // resampled with a windowed sinc, which also keeps a higher-rate source
+        // from aliasing.
+        let step = clip_sample_rate / sample_rate.0 as f64;
+        let is_resampling = self.e.sample_rate != sample_rate;
+        for (i, value) in values.iter_mut().enumerate() {
+            let seconds = seconds_at_start + i as f64 / sample_rate.0 as f64;
+            if seconds < 0.0 {
+                continue;
+            }
+            if seconds >= duration {
+                break;
+            }
+            let position = (self.trim_start.0 + seconds) * clip_sample_rate;
+            let sample = if is_resampling {
+                Interpolation::WindowedSinc.interpolate(samples, position, step)
+            } else if let Some(sample) = samples.get(position as usize) {
+                *sample
+            } else {
+                continue;
+            };
+            *value += sample * self.amplitude_at(seconds, duration);
+        }
+    }
+}
+
+/// Keeps the [AudioClip]s on each track and renders them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AudioClipRepository {
+    uid_factory: AudioClipUidFactory,
+    pub clips: FxHashMap<AudioClipUid, AudioClip>,
+    pub clips_for_track: FxHashMap<TrackUid, Vec<AudioClipUid>>,
+
+    #[serde(skip)]
+    time_range: TimeRange,
+    // If the current buffer reached the end of the loop, the part of it that
+    // starts over at the loop's start.
+    #[serde(skip)]
+    wrapped_time_range: Option<TimeRange>,
+    #[serde(skip)]
+    tempo_map: TempoMap,
+    // The tempo before the tempo map's first change, which isn't necessarily
+    // the tempo in effect.
+    #[serde(skip)]
+    initial_tempo: Tempo,
+    #[serde(skip)]
+    c: Configurables,
+}
+impl AudioClipRepository {
+    /// Places the given clip on the given track. This doesn't know what kind of
+    /// track it is, so callers should go through
+    /// [Orchestrator::add_audio_clip()](crate::orchestration::Orchestrator::add_audio_clip()),
+    /// which checks.
+    pub fn add_clip(&mut self, track_uid: TrackUid, clip: AudioClip) -> Result<AudioClipUid> {
+        let clip_uid = self.uid_factory.mint_next();
+        self.clips.insert(clip_uid, clip);
+        self.clips_for_track
+            .entry(track_uid)
+            .or_default()
+            .push(clip_uid);
+        Ok(clip_uid)
+    }
+
+    /// Takes the given clip off its track.
+    pub fn remove_clip(&mut self, clip_uid: AudioClipUid) -> Result<AudioClip> {
+        if let Some(clip) = self.clips.remove(&clip_uid) {
+            self.clips_for_track
+                .values_mut()
+                .for_each(|clip_uids| clip_uids.retain(|uid| *uid != clip_uid));
+            Ok(clip)
+        } else {
+            Err(anyhow!("Audio clip {clip_uid} not found"))
+        }
+    }
+
+    /// Removes all the clips on the given track.
+    pub fn remove_clips_for_track(&mut self, track_uid: TrackUid) {
+        if let Some(clip_uids) = self.clips_for_track.remove(&track_uid) {
+            clip_uids.iter().for_each(|uid| {
+                self.clips.remove(uid);
+            });
+        }
+    }
+
+    pub fn clip(&self, clip_uid: AudioClipUid) -> Option<&AudioClip> {
+        self.clips.get(&clip_uid)
+    }
+
+    pub fn clip_mut(&mut self, clip_uid: AudioClipUid) -> Option<&mut AudioClip> {
+        self.clips.get_mut(&clip_uid)
+    }
+
+    /// Returns the clips on the given track, in the order they were added.
+    pub fn clip_uids(&self, track_uid: TrackUid) -> &[AudioClipUid] {
+        self.clips_for_track
+            .get(&track_uid)
+            .map(|uids| uids.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Tells the repository how the timeline maps to seconds, so that clips
+    /// start on the right frame when the tempo changes.
+    pub fn update_timeline(&mut self, tempo_map: &TempoMap, initial_tempo: Tempo) {
+        if self.tempo_map != *tempo_map {
+            self.tempo_map = tempo_map.clone();
+        }
+        self.initial_tempo = initial_tempo;
+    }
+
+    /// Sets the time range that the next buffer covers, plus, if playback
+    /// reached the end of the loop partway through the buffer, the range that
+    /// the rest of the buffer covers after starting over.
+    pub fn update_time_ranges(
+        &mut self,
+        time_range: &TimeRange,
+        wrapped_time_range: Option<&TimeRange>,
+    ) {
+        self.time_range = time_range.clone();
+        self.wrapped_time_range = wrapped_time_range.cloned();
+    }
+
+    fn seconds_at(&self, time: MusicalTime) -> f64 {
+        self.tempo_map.time_to_seconds(self.initial_tempo, time).0
+    }
+
+    /// Adds the given track's clips to the buffer, which covers the current
+    /// time range.
+    pub fn generate_for_track(&self, track_uid: TrackUid, values: &mut [StereoSample]) {
+        let Some(clip_uids) = self.clips_for_track.get(&track_uid) else {
+            return;
+        };
+        let start_seconds = self.seconds_at(self.time_range.start());
+        let (values, mut wrapped_values) = if self.wrapped_time_range.is_some() {
+            let seconds_to_wrap = self.seconds_at(self.time_range.end()) - start_seconds;
+            let frames_to_wrap = (seconds_to_wrap * self.sample_rate().0 as f64).round() as usize;
+            let (values, wrapped_values) = values.split_at_mut(frames_to_wrap.min(values.len()));
+            (values, Some(wrapped_values))
+        } else {
+            (values, None)
+        };
+        for clip_uid in clip_uids {
+            let Some(clip) = self.clips.get(clip_uid) else {
+                continue;
+            };
+            let clip_start_seconds = clip.start_seconds(&self.tempo_map, self.initial_tempo);
+            clip.render(
+                start_seconds - clip_start_seconds,
+                self.sample_rate(),
+                values,
+            );
+            if let (Some(wrapped_time_range), Some(wrapped_values)) = (
+                self.wrapped_time_range.as_ref(),
+                wrapped_values.as_deref_mut(),
+            ) {
+                clip.render(
+                    self.seconds_at(wrapped_time_range.start()) - clip_start_seconds,
+                    self.sample_rate(),
+                    wrapped_values,
+                );
+            }
+        }
+    }
+}
+impl Controls for AudioClipRepository {
+    fn time_range(&self) -> Option<TimeRange> {
+        Some(self.time_range.clone())
+    }
+
+    fn update_time_range(&mut self, time_range: &TimeRange) {
+        self.update_time_ranges(time_range, None);
+    }
+
+    fn is_finished(&self) -> bool {
+        let time_range = self.wrapped_time_range.as_ref().unwrap_or(&self.time_range);
+        self.clips
+            .values()
+            .all(|clip| time_range.0.end >= clip.extent(&self.tempo_map, self.initial_tempo).0.end)
+    }
+}
+impl Configurable for AudioClipRepository {
+    delegate! {
+        to self.c {
+            fn sample_rate(&self) -> SampleRate;
+            fn update_sample_rate(&mut self, sample_rate: SampleRate);
+            fn tempo(&self) -> Tempo;
+            fn update_tempo(&mut self, tempo: Tempo);
+            fn time_signature(&self) -> TimeSignature;
+            fn update_time_signature(&mut self, time_signature: TimeSignature);
+        }
+    }
+}
+impl Serializable for AudioClipRepository {
+    fn after_deser(&mut self) {
+        self.clips.values_mut().for_each(|clip| {
+            if let Err(e) = clip.load() {
+                eprintln!("Couldn't load audio clip {:?}: {e:?}", clip.source);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{orchestration::TempoEvent, util::Paths};
+
+    fn square_wave_clip() -> AudioClipBuilder {
+        let mut paths = Paths::default();
+        paths.push_hive(Paths::test_data_rel());
+        Paths::set_instance(paths);
+        let mut builder = AudioClipBuilder::default();
+        builder.source(SampleSource::Path(
+            "square-440Hz-1-second-mono-24-bit-PCM.wav".into(),
+        ));
+        builder
+    }
+
+    #[test]
+    fn audio_clip_trims_and_fades() {
+        let clip = square_wave_clip()
+            .trim_start(Seconds(0.25))
+            .length(Some(Seconds(0.5)))
+            .fade_in(Seconds(0.1))
+            .build()
+            .unwrap();
+        assert!(clip.is_loaded());
+        assert_eq!(clip.duration(), Seconds(0.5));
+        assert_eq!(
+            clip.extent(&TempoMap::default(), Tempo(60.0)),
+            TimeRange(MusicalTime::START..MusicalTime::new_with_fractional_beats(0.5))
+        );
+        assert_eq!(clip.amplitude_at(0.0, 0.5), 0.0);
+        assert_eq!(clip.amplitude_at(0.05, 0.5), 0.5);
+        assert_eq!(clip.amplitude_at(0.25, 0.5), 1.0);
+
+        let clip = square_wave_clip()
+            .trim_start(Seconds(0.75))
+            .length(Some(Seconds(10.0)))
+            .build()
+            .unwrap();
+        assert_eq!(
+            clip.duration(),
+            Seconds(0.25),
+            "a clip can't play past the end of its source"
+        );
+    }
+
+    #[test]
+    fn audio_clip_repository_renders_clips_at_their_positions() {
+        let mut repo = AudioClipRepository::default();
+        repo.update_sample_rate(SampleRate::from(1000));
+        repo.update_tempo(Tempo(60.0));
+        repo.update_timeline(&TempoMap::default(), Tempo(60.0));
+        let track_uid = TrackUid(1);
+        let clip = square_wave_clip()
+            .position(MusicalTime::ONE_BEAT)
+            .build()
+            .unwrap();
+        let clip_uid = repo.add_clip(track_uid, clip).unwrap();
+        assert_eq!(repo.clip_uids(track_uid), &[clip_uid]);
+
+        let mut buffer = [StereoSample::SILENCE; 1000];
+        repo.update_time_range(&TimeRange(MusicalTime::START..MusicalTime::ONE_BEAT));
+        repo.generate_for_track(track_uid, &mut buffer);
+        assert!(
+            buffer.iter().all(|s| *s == StereoSample::SILENCE),
+            "a clip should be silent before its position"
+        );
+        assert!(!repo.is_finished());
+
+        repo.update_time_range(&TimeRange(
+            MusicalTime::ONE_BEAT..MusicalTime::new_with_beats(2),
+        ));
+        repo.generate_for_track(track_uid, &mut buffer);
+        assert!(buffer.iter().any(|s| *s != StereoSample::SILENCE));
+        assert!(repo.is_finished());
+
+        let mut other_track_buffer = [StereoSample::SILENCE; 1000];
+        repo.generate_for_track(TrackUid(2), &mut other_track_buffer);
+        assert!(other_track_buffer
+            .iter()
+            .all(|s| *s == StereoSample::SILENCE));
+
+        assert!(repo.remove_clip(clip_uid).is_ok());
+        assert!(repo.remove_clip(clip_uid).is_err());
+        assert!(repo.clip_uids(track_uid).is_empty());
+    }
+
+    #[test]
+    fn audio_clip_repository_follows_tempo_map_and_loop_wrap() {
+        let mut repo = AudioClipRepository::default();
+        repo.update_sample_rate(SampleRate::from(1000));
+        let mut tempo_map = TempoMap::default();
+        let _ = tempo_map.add_event(TempoEvent::new_step(MusicalTime::ONE_BEAT, Tempo(120.0)));
+        repo.update_timeline(&tempo_map, Tempo(60.0));
+        let track_uid = TrackUid(1);
+        let _ = repo.add_clip(
+            track_uid,
+            square_wave_clip()
+                .position(MusicalTime::new_with_beats(2))
+                .build()
+                .unwrap(),
+        );
+
+        // Beat 2 is one second at 60 BPM plus half a second at 120 BPM in, so
+        // a buffer starting at beat 1 should be silent for 500 frames.
+        let mut buffer = [StereoSample::SILENCE; 1000];
+        repo.update_time_range(&TimeRange(
+            MusicalTime::ONE_BEAT..MusicalTime::new_with_beats(3),
+        ));
+        repo.generate_for_track(track_uid, &mut buffer);
+        assert!(buffer[..500].iter().all(|s| *s == StereoSample::SILENCE));
+        assert!(buffer[500..].iter().any(|s| *s != StereoSample::SILENCE));
+        assert_eq!(
+            repo.clips
+                .values()
+                .next()
+                .unwrap()
+                .extent(&tempo_map, Tempo(60.0)),
+            TimeRange(MusicalTime::new_with_beats(2)..MusicalTime::new_with_beats(4))
+        );
+
+        // A loop from beat 2 to beat 3 that wraps a quarter second into the
+        // buffer should restart the clip there.
+        let mut buffer = [StereoSample::SILENCE; 500];
+        repo.update_time_ranges(
+            &TimeRange(MusicalTime::new_with_fractional_beats(2.5)..MusicalTime::new_with_beats(3)),
+            Some(&TimeRange(
+                MusicalTime::new_with_beats(2)..MusicalTime::new_with_fractional_beats(2.5),
+            )),
+        );
+        repo.generate_for_track(track_uid, &mut buffer);
+        let mut unwrapped_buffer = [StereoSample::SILENCE; 500];
+        repo.update_time_range(&TimeRange(
+            MusicalTime::new_with_beats(2)..MusicalTime::new_with_beats(3),
+        ));
+        repo.generate_for_track(track_uid, &mut unwrapped_buffer);
+        assert_eq!(
+            buffer[250..],
+            unwrapped_buffer[..250],
+            "after the wrap, the clip should play from its start again"
+        );
+    }
+
+    #[test]
+    fn audio_clip_serializes_without_samples() {
+        let clip = square_wave_clip()
+            .position(MusicalTime::new_with_beats(4))
+            .fade_out(Seconds(0.1))
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&clip).unwrap();
+        let mut clip: AudioClip = serde_json::from_str(&json).unwrap();
+        assert!(!clip.is_loaded());
+        assert_eq!(clip.position, MusicalTime::new_with_beats(4));
+        assert_eq!(clip.fade_out, Seconds(0.1));
+        assert_eq!(clip.gain, Normal::maximum());
+        assert!(clip.load().is_ok());
+    }
+
+    #[test]
+    fn audio_clip_resamples_sources_at_other_rates() {
+        // A 100Hz sine recorded at 48KHz, played in a 44.1KHz project.
+        let clip_sample_rate = SampleRate::from(48000);
+        let sample_rate = SampleRate::from(44100);
+        let sine = |seconds: f64| (2.0 * std::f64::consts::PI * 100.0 * seconds).sin();
+        let mut clip = AudioClip::default();
+        clip.e.samples = Some(Arc::new(
+            (0..clip_sample_rate.0)
+                .map(|i| StereoSample::from(sine(i as f64 / clip_sample_rate.0 as f64)))
+                .collect(),
+        ));
+        clip.e.sample_rate = clip_sample_rate;
+
+        let mut buffer = [StereoSample::SILENCE; 4410];
+        clip.render(0.0, sample_rate, &mut buffer);
+        for (i, value) in buffer.iter().enumerate().skip(100) {
+            let expected = sine(i as f64 / sample_rate.0 as f64);
+            assert!(
+                (value.0 .0 - expected).abs() < 0.001,
+                "frame {i} should be {expected}, not {}",
+                value.0 .0
+            );
+        }
+    }
+}
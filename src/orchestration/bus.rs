@@ -1,9 +1,25 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
 use crate::prelude::*;
+use anyhow::{anyhow, Result};
 use core::fmt::Debug;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+/// Where a send takes its signal from the sending track.
+#[derive(
+    Clone, Copy, Debug, Default, Display, EnumIter, IntoStaticStr, PartialEq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SendTap {
+    /// Before the track's output level, so the send doesn't follow the fader.
+    PreFader,
+    /// After the track's output level, so turning down the track also turns
+    /// down what it sends.
+    #[default]
+    PostFader,
+}
 
 /// A [BusRoute] represents a signal connection between two tracks.
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,30 +29,121 @@ pub struct BusRoute {
     pub aux_track_uid: TrackUid,
     /// How much gain should be applied to this connection.
     pub amount: Normal,
+    /// Where the signal is taken from.
+    #[serde(default = "BusRoute::legacy_tap")]
+    pub tap: SendTap,
+    /// Where the signal lands in the receiving track's stereo field, using the
+    /// mixer's pan law.
+    #[serde(default)]
+    pub pan: BipolarNormal,
+}
+
+impl BusRoute {
+    // Sends were always pre-fader before they had a tap, so projects saved
+    // then should go on sounding the way they did.
+    fn legacy_tap() -> SendTap {
+        SendTap::PreFader
+    }
 }
 
 /// A [BusStation] manages how signals move between tracks and aux tracks. These
 /// collections of signals are sometimes called buses.
+///
+/// It also manages sidechains, which feed a track's output to an effect's key
+/// input rather than mixing it into another track.
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct BusStation {
     pub routes: FxHashMap<TrackUid, Vec<BusRoute>>,
+
+    /// Which track keys each sidechained effect.
+    #[serde(default)]
+    pub sidechains: FxHashMap<Uid, TrackUid>,
 }
 
 impl BusStation {
+    /// Sends the track's signal to the destination track, replacing any prior
+    /// send between them. A new send is post-fader and centered. It's an error
+    /// for a send to lead back to the track it came from.
     pub fn add_send(
         &mut self,
         track_uid: TrackUid,
         dst_uid: TrackUid,
         amount: Normal,
     ) -> anyhow::Result<()> {
-        self.routes.entry(track_uid).or_default().push(BusRoute {
-            aux_track_uid: dst_uid,
-            amount,
-        });
+        if self.sends_reach(dst_uid, track_uid) {
+            return Err(anyhow!(
+                "Sending from track {track_uid} to track {dst_uid} would create a cycle"
+            ));
+        }
+        if let Some(route) = self.send_mut(track_uid, dst_uid) {
+            route.amount = amount;
+        } else {
+            self.routes.entry(track_uid).or_default().push(BusRoute {
+                aux_track_uid: dst_uid,
+                amount,
+                tap: SendTap::default(),
+                pan: BipolarNormal::default(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Changes where an existing send takes its signal from.
+    pub fn set_send_tap(
+        &mut self,
+        track_uid: TrackUid,
+        aux_track_uid: TrackUid,
+        tap: SendTap,
+    ) -> Result<()> {
+        self.existing_send_mut(track_uid, aux_track_uid)?.tap = tap;
+        Ok(())
+    }
+
+    /// Changes an existing send's pan.
+    pub fn set_send_pan(
+        &mut self,
+        track_uid: TrackUid,
+        aux_track_uid: TrackUid,
+        pan: BipolarNormal,
+    ) -> Result<()> {
+        self.existing_send_mut(track_uid, aux_track_uid)?.pan = pan;
         Ok(())
     }
 
+    fn send_mut(&mut self, track_uid: TrackUid, aux_track_uid: TrackUid) -> Option<&mut BusRoute> {
+        self.routes
+            .get_mut(&track_uid)
+            .and_then(|routes| routes.iter_mut().find(|r| r.aux_track_uid == aux_track_uid))
+    }
+
+    fn existing_send_mut(
+        &mut self,
+        track_uid: TrackUid,
+        aux_track_uid: TrackUid,
+    ) -> Result<&mut BusRoute> {
+        self.send_mut(track_uid, aux_track_uid)
+            .ok_or_else(|| anyhow!("Track {track_uid} doesn't send to track {aux_track_uid}"))
+    }
+
+    /// Whether signal from the first track reaches the second one through
+    /// sends, directly or by way of other tracks. A track reaches itself.
+    pub fn sends_reach(&self, track_uid: TrackUid, other_track_uid: TrackUid) -> bool {
+        let mut visited = FxHashSet::default();
+        let mut to_visit = vec![track_uid];
+        while let Some(track_uid) = to_visit.pop() {
+            if track_uid == other_track_uid {
+                return true;
+            }
+            if visited.insert(track_uid) {
+                if let Some(routes) = self.routes.get(&track_uid) {
+                    to_visit.extend(routes.iter().map(|route| route.aux_track_uid));
+                }
+            }
+        }
+        false
+    }
+
     pub fn remove_send(&mut self, track_uid: TrackUid, aux_track_uid: TrackUid) {
         if let Some(routes) = self.routes.get_mut(&track_uid) {
             routes.retain(|route| route.aux_track_uid != aux_track_uid);
@@ -60,4 +167,32 @@ impl BusStation {
             .values_mut()
             .for_each(|routes| routes.retain(|route| route.aux_track_uid != track_uid));
     }
+
+    /// Feeds the given track's output to the effect's sidechain key input,
+    /// replacing any prior key track for that effect. This doesn't check
+    /// whether the route makes sense; [Orchestrator](super::Orchestrator) does
+    /// that.
+    pub fn set_sidechain(&mut self, effect_uid: Uid, key_track_uid: TrackUid) {
+        self.sidechains.insert(effect_uid, key_track_uid);
+    }
+
+    /// Disconnects the effect's sidechain key input.
+    pub fn remove_sidechain(&mut self, effect_uid: Uid) -> Option<TrackUid> {
+        self.sidechains.remove(&effect_uid)
+    }
+
+    /// Returns the track that keys the given effect, if any.
+    pub fn sidechain(&self, effect_uid: Uid) -> Option<TrackUid> {
+        self.sidechains.get(&effect_uid).copied()
+    }
+
+    /// Whether the given track keys any effect.
+    pub fn is_sidechain_key(&self, track_uid: TrackUid) -> bool {
+        self.sidechains.values().any(|t| *t == track_uid)
+    }
+
+    pub(crate) fn remove_sidechains_keyed_by_track(&mut self, track_uid: TrackUid) {
+        self.sidechains
+            .retain(|_, key_track_uid| *key_track_uid != track_uid);
+    }
 }
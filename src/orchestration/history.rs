@@ -0,0 +1,416 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Undo and redo for [Project] edits.
+
+use crate::prelude::*;
+use anyhow::{anyhow, Result};
+use derivative::Derivative;
+use ensnare::prelude::*;
+use std::collections::VecDeque;
+
+/// One step on a [ProjectHistory] stack: what it takes to put the project
+/// back the way it was before (for undo) or after (for redo) an edit.
+#[derive(Debug, PartialEq)]
+pub enum HistoryEntry {
+    /// A complete serialized snapshot of the project. Snapshots are coarse,
+    /// but they mean that every kind of edit can be undone without each one
+    /// needing its own inverse operation, and they reuse the same code path as
+    /// loading and saving, so a restored project is exactly one that could
+    /// have been loaded from disk.
+    ///
+    /// The cost is that each snapshot holds the whole project as JSON (sample
+    /// data stays on disk, but every pattern, clip, and entity setting is
+    /// copied), and restoring one reloads every audio clip and sampler file
+    /// from disk. Undoing a snapshot of a large project can therefore take a
+    /// noticeable moment.
+    Snapshot(String),
+    /// Notes to take out of a pattern, and notes to put into it. Note editing
+    /// happens far more often than any other edit, so it's recorded as just
+    /// the notes that changed rather than as a snapshot.
+    PatternNotes {
+        pattern_uid: PatternUid,
+        remove: Vec<Note>,
+        add: Vec<Note>,
+    },
+}
+
+/// The undo and redo stacks for a [Project].
+#[derive(Debug, Derivative)]
+#[derivative(Default)]
+pub struct ProjectHistory {
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    #[derivative(Default(value = "Self::DEFAULT_LIMIT"))]
+    limit: usize,
+}
+impl ProjectHistory {
+    /// How many edits can be undone unless [ProjectHistory::set_limit()] says
+    /// otherwise. Because most entries are full [HistoryEntry::Snapshot]s,
+    /// the stacks can hold up to this many copies of the project, so a
+    /// memory-constrained host should lower it.
+    pub const DEFAULT_LIMIT: usize = 64;
+
+    /// Remembers how to undo an edit. The oldest entry is forgotten if the
+    /// undo stack is full, and anything that could have been redone is
+    /// forgotten because it's no longer reachable from the new state.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.undo_stack.push_back(entry);
+        self.trim();
+        self.redo_stack.clear();
+    }
+
+    /// Takes the most recent undo entry. Whoever applies it should
+    /// [ProjectHistory::push_redo()] the entry that reverses it.
+    pub fn pop_undo(&mut self) -> Option<HistoryEntry> {
+        self.undo_stack.pop_back()
+    }
+
+    /// Takes the most recent redo entry. Whoever applies it should
+    /// [ProjectHistory::push_undo()] the entry that reverses it.
+    pub fn pop_redo(&mut self) -> Option<HistoryEntry> {
+        self.redo_stack.pop()
+    }
+
+    /// Makes an entry undoable without forgetting what can be redone.
+    pub fn push_undo(&mut self, entry: HistoryEntry) {
+        self.undo_stack.push_back(entry);
+        self.trim();
+    }
+
+    /// Makes an entry redoable.
+    pub fn push_redo(&mut self, entry: HistoryEntry) {
+        self.redo_stack.push(entry);
+    }
+
+    /// Whether there's anything to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Whether there's anything to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Forgets everything.
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// The maximum number of undoable edits.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Sets the maximum number of undoable edits, forgetting the oldest ones
+    /// if there are already more than that.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo_stack.len() > self.limit {
+            self.undo_stack.pop_front();
+        }
+    }
+}
+
+impl Project {
+    /// Records the project's current state so that the next change can be
+    /// undone. Prefer [Project::edit()], which records only edits that
+    /// succeed; use this when the change happens somewhere that can't be
+    /// wrapped in a closure, such as inside a widget.
+    pub fn checkpoint(&mut self) {
+        match self.snapshot() {
+            Ok(snapshot) => self.e.history.record(HistoryEntry::Snapshot(snapshot)),
+            Err(e) => eprintln!("Couldn't record undo checkpoint: {e:?}"),
+        }
+    }
+
+    /// Applies an edit as a single undoable step. If the edit fails, the
+    /// project is rolled back to the way it was before the edit, and nothing
+    /// is recorded.
+    ///
+    /// This snapshots the whole project, which is fine for occasional
+    /// structural edits like adding a track, but too slow for anything that
+    /// can happen on every UI frame. Note edits should use
+    /// [Project::edit_pattern_notes()] instead.
+    pub fn edit<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let snapshot = self.snapshot()?;
+        match f(self) {
+            Ok(r) => {
+                self.e.history.record(HistoryEntry::Snapshot(snapshot));
+                Ok(r)
+            }
+            Err(e) => {
+                self.restore(&snapshot)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Takes the given notes out of a pattern and puts the others in, as a
+    /// single undoable step.
+    pub fn edit_pattern_notes(
+        &mut self,
+        pattern_uid: PatternUid,
+        remove: Vec<Note>,
+        add: Vec<Note>,
+    ) -> Result<()> {
+        let inverse = self.apply_history_entry(HistoryEntry::PatternNotes {
+            pattern_uid,
+            remove,
+            add,
+        })?;
+        self.e.history.record(inverse);
+        Ok(())
+    }
+
+    /// Reverts the most recent edit.
+    pub fn undo(&mut self) -> Result<()> {
+        let Some(entry) = self.e.history.pop_undo() else {
+            return Err(anyhow!("Nothing to undo"));
+        };
+        let inverse = self.apply_history_entry(entry)?;
+        self.e.history.push_redo(inverse);
+        Ok(())
+    }
+
+    /// Reapplies the most recently undone edit.
+    pub fn redo(&mut self) -> Result<()> {
+        let Some(entry) = self.e.history.pop_redo() else {
+            return Err(anyhow!("Nothing to redo"));
+        };
+        let inverse = self.apply_history_entry(entry)?;
+        self.e.history.push_undo(inverse);
+        Ok(())
+    }
+
+    /// Whether there's an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        self.e.history.can_undo()
+    }
+
+    /// Whether there's an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        self.e.history.can_redo()
+    }
+
+    // Puts the project in the state that the entry describes, and returns the
+    // entry that would put it back.
+    fn apply_history_entry(&mut self, entry: HistoryEntry) -> Result<HistoryEntry> {
+        match entry {
+            HistoryEntry::Snapshot(snapshot) => {
+                let current = self.snapshot()?;
+                self.restore(&snapshot)?;
+                Ok(HistoryEntry::Snapshot(current))
+            }
+            HistoryEntry::PatternNotes {
+                pattern_uid,
+                remove,
+                add,
+            } => {
+                let Some(pattern) = self.composer.patterns.get_mut(&pattern_uid) else {
+                    return Err(anyhow!("Pattern {pattern_uid} not found"));
+                };
+                // Only one instance of each note, because the pattern might
+                // hold identical notes that weren't part of this edit.
+                remove.iter().for_each(|note| pattern.remove_one_note(note));
+                add.iter().for_each(|note| pattern.add_note(note.clone()));
+                self.notify_pattern_change();
+                Ok(HistoryEntry::PatternNotes {
+                    pattern_uid,
+                    remove: add,
+                    add: remove,
+                })
+            }
+        }
+    }
+
+    fn snapshot(&mut self) -> Result<String> {
+        self.before_ser();
+        Ok(serde_json::to_string(&self)?)
+    }
+
+    // Replaces the project's persistent state with the snapshot's, keeping
+    // ephemeral state such as the audio sender, the load path, the playback
+    // position, and the history itself. Playback carries on if it was going.
+    //
+    // This goes through after_deser(), so every audio clip and sampler reloads
+    // its file from disk. That's what keeps snapshots small, but it makes
+    // restoring much slower than taking a snapshot.
+    fn restore(&mut self, snapshot: &str) -> Result<()> {
+        let mut project = serde_json::from_str::<Self>(snapshot)?;
+        let was_performing = self.is_performing();
+        let sample_rate = self.sample_rate();
+        std::mem::swap(&mut project.e, &mut self.e);
+        *self = project;
+        self.after_deser();
+        self.update_sample_rate(sample_rate);
+        self.update_tempo(self.tempo());
+        self.update_time_signature(self.time_signature());
+
+        // Cached track information may refer to entities and tracks that the
+        // snapshot doesn't have.
+        self.e.track_info.clear();
+        let mut track_uids = self.orchestrator.track_uids().to_vec();
+        track_uids.push(Orchestrator::MASTER_TRACK_UID);
+        track_uids
+            .into_iter()
+            .for_each(|track_uid| self.regenerate_signal_chain(track_uid));
+        if was_performing {
+            self.play();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::TestInstrument;
+
+    fn snapshot(s: &str) -> HistoryEntry {
+        HistoryEntry::Snapshot(s.to_string())
+    }
+
+    #[test]
+    fn history_stacks_are_bounded() {
+        let mut history = ProjectHistory::default();
+        history.set_limit(2);
+        history.record(snapshot("a"));
+        history.record(snapshot("b"));
+        history.record(snapshot("c"));
+        assert_eq!(history.pop_undo(), Some(snapshot("c")));
+        history.push_redo(snapshot("d"));
+        assert_eq!(history.pop_undo(), Some(snapshot("b")));
+        history.push_redo(snapshot("c"));
+        assert_eq!(
+            history.pop_undo(),
+            None,
+            "the oldest snapshot should have been forgotten"
+        );
+        assert_eq!(history.pop_redo(), Some(snapshot("c")));
+        history.push_undo(snapshot("b"));
+        assert!(history.can_undo());
+        assert!(history.can_redo());
+        history.record(snapshot("e"));
+        assert!(
+            !history.can_redo(),
+            "a new edit should clear the redo stack"
+        );
+    }
+
+    #[test]
+    fn project_undo_and_redo() {
+        let mut project = Project::default();
+        assert!(!project.can_undo());
+        assert!(project.undo().is_err());
+
+        let track_uid = project
+            .edit(|project| project.new_midi_track())
+            .expect("adding a track should succeed");
+        let uid = project
+            .edit(|project| {
+                let uid = project.mint_entity_uid();
+                project.add_entity(track_uid, Box::new(TestInstrument::new_with(uid)))
+            })
+            .unwrap();
+        assert_eq!(project.entity_uids(track_uid).unwrap(), &[uid]);
+
+        assert!(project.undo().is_ok());
+        assert!(project
+            .entity_uids(track_uid)
+            .unwrap_or_default()
+            .is_empty());
+        assert_eq!(project.track_uids(), &[track_uid]);
+        assert!(project.undo().is_ok());
+        assert!(project.track_uids().is_empty());
+        assert!(!project.can_undo());
+
+        assert!(project.redo().is_ok());
+        assert!(project.redo().is_ok());
+        assert!(!project.can_redo());
+        assert_eq!(project.entity_uids(track_uid).unwrap(), &[uid]);
+        assert!(
+            project.orchestrator.entity_repo.entity(uid).is_some(),
+            "a redone entity should be usable"
+        );
+
+        // A failed edit leaves no trace.
+        let r: Result<()> = project.edit(|project| {
+            project.delete_track(track_uid)?;
+            Err(anyhow!("something went wrong"))
+        });
+        assert!(r.is_err());
+        assert_eq!(project.track_uids(), &[track_uid]);
+        assert!(!project.can_redo());
+        assert!(project.undo().is_ok());
+        assert_eq!(project.entity_uids(track_uid).unwrap_or_default().len(), 0);
+    }
+
+    #[test]
+    fn pattern_note_edits_undo_without_snapshots() {
+        let mut project = Project::default();
+        let pattern_uid = project
+            .add_pattern(PatternBuilder::default().build().unwrap(), None)
+            .unwrap();
+        let note = Note::new_with(60, MusicalTime::START, MusicalTime::DURATION_QUARTER);
+        let other_note = Note::new_with(62, MusicalTime::ONE_BEAT, MusicalTime::DURATION_QUARTER);
+        let notes = |project: &Project| project.composer.patterns[&pattern_uid].notes.clone();
+
+        assert!(project
+            .edit_pattern_notes(pattern_uid, Vec::default(), vec![note.clone()])
+            .is_ok());
+        assert!(project
+            .edit_pattern_notes(pattern_uid, vec![note.clone()], vec![other_note.clone()])
+            .is_ok());
+        assert_eq!(notes(&project), vec![other_note.clone()]);
+        assert!(matches!(
+            project.e.history.undo_stack.back(),
+            Some(HistoryEntry::PatternNotes { .. })
+        ));
+
+        project.play();
+        assert!(project.undo().is_ok());
+        assert_eq!(notes(&project), vec![note.clone()]);
+        assert!(project.undo().is_ok());
+        assert!(notes(&project).is_empty());
+        assert!(project.redo().is_ok());
+        assert_eq!(notes(&project), vec![note]);
+
+        // Rolling back a failed edit shouldn't interrupt playback.
+        let r: Result<()> = project.edit(|_| Err(anyhow!("something went wrong")));
+        assert!(r.is_err());
+        assert!(project.is_performing());
+    }
+
+    #[test]
+    fn undoing_a_duplicate_note_leaves_the_original() {
+        let mut project = Project::default();
+        let pattern_uid = project
+            .add_pattern(PatternBuilder::default().build().unwrap(), None)
+            .unwrap();
+        let note = Note::new_with(60, MusicalTime::START, MusicalTime::DURATION_QUARTER);
+        let notes = |project: &Project| project.composer.patterns[&pattern_uid].notes.clone();
+
+        assert!(project
+            .edit_pattern_notes(pattern_uid, Vec::default(), vec![note.clone()])
+            .is_ok());
+        assert!(project
+            .edit_pattern_notes(pattern_uid, Vec::default(), vec![note.clone()])
+            .is_ok());
+        assert_eq!(notes(&project), vec![note.clone(), note.clone()]);
+
+        assert!(project.undo().is_ok());
+        assert_eq!(
+            notes(&project),
+            vec![note.clone()],
+            "undoing the second add should remove only one copy"
+        );
+        assert!(project.redo().is_ok());
+        assert_eq!(notes(&project), vec![note.clone(), note]);
+    }
+}
@@ -30,18 +30,37 @@ impl Humidifier {
         effect: &mut Box<dyn Entity>,
         samples: &mut [StereoSample],
     ) {
-        self.transformation_buffer.resize(samples.len());
-        self.transformation_buffer
-            .buffer_mut()
-            .copy_from_slice(samples);
-        effect.transform(samples);
+        self.transform_batch_with(humidity, samples, |samples| effect.transform(samples));
+    }
+
+    /// Like [Humidifier::transform_batch()], but for effects that need more
+    /// than the samples, such as a sidechain key.
+    pub fn transform_batch_with(
+        &mut self,
+        humidity: Normal,
+        samples: &mut [StereoSample],
+        transform_fn: impl FnOnce(&mut [StereoSample]),
+    ) {
+        let mut pre_effect = std::mem::take(&mut self.transformation_buffer);
+        self.transform_batch_using(humidity, samples, &mut pre_effect, transform_fn);
+        self.transformation_buffer = pre_effect;
+    }
+
+    /// Like [Humidifier::transform_batch_with()], but keeps the dry signal in
+    /// the caller's buffer rather than its own, so that several threads can
+    /// share one [Humidifier].
+    pub fn transform_batch_using(
+        &self,
+        humidity: Normal,
+        samples: &mut [StereoSample],
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+        transform_fn: impl FnOnce(&mut [StereoSample]),
+    ) {
+        pre_effect.resize(samples.len());
+        pre_effect.buffer_mut().copy_from_slice(samples);
+        transform_fn(samples);
 
-        for (pre, post) in self
-            .transformation_buffer
-            .buffer()
-            .iter()
-            .zip(samples.iter_mut())
-        {
+        for (pre, post) in pre_effect.buffer().iter().zip(samples.iter_mut()) {
             *post = StereoSample(
                 self.transform_channel(humidity, 0, pre.0, post.0),
                 self.transform_channel(humidity, 1, pre.1, post.1),
@@ -0,0 +1,507 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Level and loudness meters for tracks and the final mix.
+
+use crate::{cores::effects::linear_to_db, prelude::*};
+use core::f64::consts::PI;
+use ensnare::prelude::*;
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+
+/// A snapshot of a [LevelMeter]. Levels are linear amplitudes, one per
+/// channel; see [LevelReading::to_dbfs()].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LevelReading {
+    /// The recent peak level, which falls back gradually after each peak.
+    pub peak: [f64; 2],
+    /// The highest recent peak, held for a moment so that it can be read.
+    pub peak_hold: [f64; 2],
+    /// The average level over the last few hundred milliseconds.
+    pub rms: [f64; 2],
+    /// Whether any sample has gone beyond full scale since the meter was
+    /// reset.
+    pub has_clipped: bool,
+}
+impl LevelReading {
+    /// Converts a linear amplitude to dBFS.
+    pub fn to_dbfs(amplitude: f64) -> ParameterType {
+        linear_to_db(amplitude)
+    }
+}
+
+/// A snapshot of a [LoudnessMeter], in LUFS. Each value is None until enough
+/// audio has gone by to measure it.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LoudnessReading {
+    /// Loudness over the last 400 milliseconds.
+    pub momentary: Option<f64>,
+    /// Loudness over the last three seconds.
+    pub short_term: Option<f64>,
+    /// Gated loudness since the meter was reset.
+    pub integrated: Option<f64>,
+}
+
+/// Everything the meters have to say about the most recently rendered audio.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MeterReadings {
+    /// Each track's level after its fader and pan.
+    pub tracks: FxHashMap<TrackUid, LevelReading>,
+    /// The level of the final mix.
+    pub master: LevelReading,
+    /// The loudness of the final mix.
+    pub loudness: LoudnessReading,
+}
+
+/// Measures peak and RMS levels, with peak hold and a clip indicator.
+#[derive(Debug)]
+pub struct LevelMeter {
+    peak: [f64; 2],
+    peak_hold: [f64; 2],
+    hold_frames_remaining: [usize; 2],
+    rms_power: [f64; 2],
+    has_clipped: bool,
+
+    peak_decay: f64,
+    hold_frames: usize,
+    rms_coefficient: f64,
+}
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new_with(SampleRate::default())
+    }
+}
+impl LevelMeter {
+    /// How long the peak takes to fall by 20 dB.
+    const PEAK_FALL_TIME: Seconds = Seconds(1.5);
+    /// How long the peak hold lasts.
+    const PEAK_HOLD_TIME: Seconds = Seconds(2.0);
+    /// The time constant of the RMS average.
+    const RMS_TIME: Seconds = Seconds(0.3);
+
+    #[allow(missing_docs)]
+    pub fn new_with(sample_rate: SampleRate) -> Self {
+        let frames_per_second = sample_rate.0 as f64;
+        Self {
+            peak: Default::default(),
+            peak_hold: Default::default(),
+            hold_frames_remaining: Default::default(),
+            rms_power: Default::default(),
+            has_clipped: Default::default(),
+            peak_decay: 10.0f64.powf(-1.0 / (Self::PEAK_FALL_TIME.0 * frames_per_second)),
+            hold_frames: (Self::PEAK_HOLD_TIME.0 * frames_per_second) as usize,
+            rms_coefficient: (-1.0 / (Self::RMS_TIME.0 * frames_per_second)).exp(),
+        }
+    }
+
+    /// Measures the samples after multiplying each channel by its gain.
+    pub fn process(&mut self, samples: &[StereoSample], gains: (f64, f64)) {
+        for sample in samples {
+            self.process_channel(0, sample.0 .0 * gains.0);
+            self.process_channel(1, sample.1 .0 * gains.1);
+        }
+    }
+
+    fn process_channel(&mut self, channel: usize, value: f64) {
+        let level = value.abs();
+        self.has_clipped |= level > 1.0;
+        self.peak[channel] = level.max(self.peak[channel] * self.peak_decay);
+        if self.peak[channel] >= self.peak_hold[channel] {
+            self.peak_hold[channel] = self.peak[channel];
+            self.hold_frames_remaining[channel] = self.hold_frames;
+        } else if self.hold_frames_remaining[channel] > 0 {
+            self.hold_frames_remaining[channel] -= 1;
+        } else {
+            self.peak_hold[channel] = self.peak[channel];
+        }
+        self.rms_power[channel] = self.rms_coefficient * self.rms_power[channel]
+            + (1.0 - self.rms_coefficient) * value * value;
+    }
+
+    #[allow(missing_docs)]
+    pub fn reading(&self) -> LevelReading {
+        LevelReading {
+            peak: self.peak,
+            peak_hold: self.peak_hold,
+            rms: self.rms_power.map(f64::sqrt),
+            has_clipped: self.has_clipped,
+        }
+    }
+}
+
+/// Measures loudness as described in ITU-R BS.1770: K-weighted, with
+/// momentary, short-term, and gated integrated values.
+#[derive(Debug)]
+pub struct LoudnessMeter {
+    // Two filter stages for each channel.
+    filters: [[KWeightingStage; 2]; 2],
+
+    // Loudness is measured over blocks made of 100-millisecond sub-blocks.
+    sub_block_frames: usize,
+    sub_block_frame_count: usize,
+    sub_block_power: f64,
+    // The mean power of the most recent sub-blocks, oldest first.
+    sub_block_powers: VecDeque<f64>,
+    // A histogram of every 400-millisecond block since the last reset, for the
+    // integrated loudness. Each bin covers 0.1 LU above the absolute gate and
+    // holds its block count and the sum of their mean powers, so the meter
+    // needs the same memory however long it runs.
+    block_histogram: Vec<(usize, f64)>,
+}
+impl Default for LoudnessMeter {
+    fn default() -> Self {
+        Self::new_with(SampleRate::default())
+    }
+}
+impl LoudnessMeter {
+    const SUB_BLOCK_TIME: Seconds = Seconds(0.1);
+    const MOMENTARY_SUB_BLOCKS: usize = 4;
+    const SHORT_TERM_SUB_BLOCKS: usize = 30;
+    const ABSOLUTE_GATE: f64 = -70.0;
+    const RELATIVE_GATE: f64 = -10.0;
+    // From the absolute gate up to +30 LUFS, in steps of 0.1 LU.
+    const HISTOGRAM_BINS: usize = 1000;
+    const HISTOGRAM_BINS_PER_LU: f64 = 10.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(sample_rate: SampleRate) -> Self {
+        let [pre_filter, rlb_filter] = KWeightingStage::new_pair(sample_rate);
+        Self {
+            filters: [
+                [pre_filter.clone(), rlb_filter.clone()],
+                [pre_filter, rlb_filter],
+            ],
+            sub_block_frames: ((Self::SUB_BLOCK_TIME.0 * sample_rate.0 as f64) as usize).max(1),
+            sub_block_frame_count: Default::default(),
+            sub_block_power: Default::default(),
+            sub_block_powers: Default::default(),
+            block_histogram: vec![Default::default(); Self::HISTOGRAM_BINS],
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn process(&mut self, samples: &[StereoSample]) {
+        for sample in samples {
+            for (channel, value) in [sample.0 .0, sample.1 .0].into_iter().enumerate() {
+                let [pre_filter, rlb_filter] = &mut self.filters[channel];
+                let weighted = rlb_filter.process(pre_filter.process(value));
+                // Both channels have a weight of 1.0.
+                self.sub_block_power += weighted * weighted;
+            }
+            self.sub_block_frame_count += 1;
+            if self.sub_block_frame_count == self.sub_block_frames {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        self.sub_block_powers
+            .push_back(self.sub_block_power / self.sub_block_frames as f64);
+        if self.sub_block_powers.len() > Self::SHORT_TERM_SUB_BLOCKS {
+            self.sub_block_powers.pop_front();
+        }
+        self.sub_block_power = 0.0;
+        self.sub_block_frame_count = 0;
+
+        // Blocks overlap by 75%, so every sub-block finishes one.
+        if let Some(power) = self.mean_power(Self::MOMENTARY_SUB_BLOCKS) {
+            if let Some(bin) = Self::histogram_bin(Self::power_to_lufs(power)) {
+                let (count, sum) = &mut self.block_histogram[bin];
+                *count += 1;
+                *sum += power;
+            }
+        }
+    }
+
+    // The mean power of the most recent sub-blocks, if there are enough.
+    fn mean_power(&self, sub_block_count: usize) -> Option<f64> {
+        if self.sub_block_powers.len() < sub_block_count {
+            return None;
+        }
+        Some(
+            self.sub_block_powers
+                .iter()
+                .rev()
+                .take(sub_block_count)
+                .sum::<f64>()
+                / sub_block_count as f64,
+        )
+    }
+
+    fn power_to_lufs(power: f64) -> f64 {
+        -0.691 + 10.0 * power.log10()
+    }
+
+    // The histogram bin for a block of the given loudness, or None if the
+    // absolute gate drops it. Blocks louder than the top bin go in that bin.
+    fn histogram_bin(lufs: f64) -> Option<usize> {
+        if lufs > Self::ABSOLUTE_GATE {
+            Some(
+                (((lufs - Self::ABSOLUTE_GATE) * Self::HISTOGRAM_BINS_PER_LU) as usize)
+                    .min(Self::HISTOGRAM_BINS - 1),
+            )
+        } else {
+            None
+        }
+    }
+
+    // The mean power of the blocks from the given bin up.
+    fn gated_mean_power(&self, first_bin: usize) -> Option<f64> {
+        let (count, sum) = self.block_histogram[first_bin..]
+            .iter()
+            .fold((0, 0.0), |(count, sum), (bin_count, bin_sum)| {
+                (count + bin_count, sum + bin_sum)
+            });
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    // As in libebur128, the relative gate keeps the whole bin that it falls
+    // in, which puts it within 0.1 LU of where BS.1770 says.
+    fn integrated(&self) -> Option<f64> {
+        let absolutely_gated_power = self.gated_mean_power(0)?;
+        let relative_gate = Self::power_to_lufs(absolutely_gated_power) + Self::RELATIVE_GATE;
+        self.gated_mean_power(Self::histogram_bin(relative_gate).unwrap_or_default())
+            .map(Self::power_to_lufs)
+    }
+
+    #[allow(missing_docs)]
+    pub fn reading(&self) -> LoudnessReading {
+        LoudnessReading {
+            momentary: self
+                .mean_power(Self::MOMENTARY_SUB_BLOCKS)
+                .map(Self::power_to_lufs),
+            short_term: self
+                .mean_power(Self::SHORT_TERM_SUB_BLOCKS)
+                .map(Self::power_to_lufs),
+            integrated: self.integrated(),
+        }
+    }
+}
+
+// One biquad stage of the K-weighting filter.
+#[derive(Debug, Clone, Default)]
+struct KWeightingStage {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+impl KWeightingStage {
+    // The high-shelf pre-filter and the RLB high-pass filter, with the
+    // coefficients of BS.1770 adapted to the sample rate.
+    fn new_pair(sample_rate: SampleRate) -> [Self; 2] {
+        let sample_rate = sample_rate.0 as f64;
+
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10.0f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let pre_filter = Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            ..Default::default()
+        };
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let rlb_filter = Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            ..Default::default()
+        };
+        [pre_filter, rlb_filter]
+    }
+
+    fn process(&mut self, x0: f64) -> f64 {
+        let y0 = self.b[0] * x0 + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x0, self.x[0]];
+        self.y = [y0, self.y[0]];
+        y0
+    }
+}
+
+/// The meters that [Orchestrator](super::Orchestrator) keeps up to date as it
+/// renders.
+#[derive(Debug, Default)]
+pub struct Meters {
+    sample_rate: SampleRate,
+    tracks: FxHashMap<TrackUid, LevelMeter>,
+    master: LevelMeter,
+    loudness: LoudnessMeter,
+}
+impl Meters {
+    /// Measures a track's output after its fader and pan, whose combined gains
+    /// are given.
+    pub fn process_track(
+        &mut self,
+        track_uid: TrackUid,
+        samples: &[StereoSample],
+        gains: (f64, f64),
+    ) {
+        let sample_rate = self.sample_rate;
+        self.tracks
+            .entry(track_uid)
+            .or_insert_with(|| LevelMeter::new_with(sample_rate))
+            .process(samples, gains);
+    }
+
+    /// Measures the final mix.
+    pub fn process_master(&mut self, samples: &[StereoSample]) {
+        self.master.process(samples, (1.0, 1.0));
+        self.loudness.process(samples);
+    }
+
+    /// Forgets a track's meter.
+    pub fn remove_track(&mut self, track_uid: TrackUid) {
+        self.tracks.remove(&track_uid);
+    }
+
+    /// Starts all the meters over, clearing peak holds, clip indicators, and
+    /// the integrated loudness.
+    pub fn reset(&mut self) {
+        let sample_rate = self.sample_rate;
+        self.tracks
+            .values_mut()
+            .for_each(|meter| *meter = LevelMeter::new_with(sample_rate));
+        self.master = LevelMeter::new_with(sample_rate);
+        self.loudness = LoudnessMeter::new_with(sample_rate);
+    }
+
+    #[allow(missing_docs)]
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.reset();
+    }
+
+    #[allow(missing_docs)]
+    pub fn readings(&self) -> MeterReadings {
+        let mut readings = MeterReadings::default();
+        self.update_readings(&mut readings);
+        readings
+    }
+
+    /// Overwrites the given readings with the current ones, reusing their
+    /// storage.
+    pub fn update_readings(&self, readings: &mut MeterReadings) {
+        readings
+            .tracks
+            .retain(|track_uid, _| self.tracks.contains_key(track_uid));
+        for (track_uid, meter) in self.tracks.iter() {
+            readings.tracks.insert(*track_uid, meter.reading());
+        }
+        readings.master = self.master.reading();
+        readings.loudness = self.loudness.reading();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(
+        frequency: f64,
+        amplitude: f64,
+        sample_rate: SampleRate,
+        seconds: f64,
+    ) -> Vec<StereoSample> {
+        let frames = (seconds * sample_rate.0 as f64) as usize;
+        (0..frames)
+            .map(|i| {
+                let value =
+                    amplitude * (2.0 * PI * frequency * i as f64 / sample_rate.0 as f64).sin();
+                StereoSample::from(value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn level_meter_mainline() {
+        let sample_rate = SampleRate::from(48000);
+        let mut meter = LevelMeter::new_with(sample_rate);
+        meter.process(&sine(1000.0, 0.5, sample_rate, 2.0), (1.0, 0.5));
+        let reading = meter.reading();
+        assert!((reading.peak[0] - 0.5).abs() < 0.01);
+        assert!((reading.peak[1] - 0.25).abs() < 0.01);
+        assert!(
+            (reading.rms[0] - 0.5 * core::f64::consts::FRAC_1_SQRT_2).abs() < 0.01,
+            "a sine's RMS should be its peak over root two, but got {}",
+            reading.rms[0]
+        );
+        assert!(!reading.has_clipped);
+
+        // After the signal stops, the peak falls, but the hold stays put for
+        // a while.
+        meter.process(&vec![StereoSample::SILENCE; 4800], (1.0, 1.0));
+        let reading = meter.reading();
+        assert!(reading.peak[0] < 0.5);
+        assert!((reading.peak_hold[0] - 0.5).abs() < 0.01);
+        meter.process(&vec![StereoSample::SILENCE; 96000], (1.0, 1.0));
+        let reading = meter.reading();
+        assert!(reading.peak_hold[0] < 0.5);
+        assert!(reading.rms[0] < 0.02);
+
+        meter.process(&[StereoSample::from(1.5)], (1.0, 1.0));
+        assert!(meter.reading().has_clipped);
+        meter.process(&vec![StereoSample::SILENCE; 96000], (1.0, 1.0));
+        assert!(
+            meter.reading().has_clipped,
+            "the clip indicator should stay lit until reset"
+        );
+    }
+
+    #[test]
+    fn loudness_meter_matches_bs1770() {
+        // A full-scale 997 Hz sine in both channels measures 0 LUFS, give or
+        // take the K-weighting's gain at that frequency.
+        let sample_rate = SampleRate::from(48000);
+        let mut meter = LoudnessMeter::new_with(sample_rate);
+        assert_eq!(meter.reading(), LoudnessReading::default());
+        meter.process(&sine(997.0, 1.0, sample_rate, 3.5));
+        let reading = meter.reading();
+        for value in [reading.momentary, reading.short_term, reading.integrated] {
+            let value = value.unwrap();
+            assert!(value.abs() < 0.1, "expected about 0 LUFS, got {value}");
+        }
+
+        // 6 dB quieter is 6 LU quieter.
+        let mut meter = LoudnessMeter::new_with(sample_rate);
+        meter.process(&sine(997.0, 0.5, sample_rate, 1.0));
+        let momentary = meter.reading().momentary.unwrap();
+        assert!((momentary + 6.02).abs() < 0.1, "got {momentary}");
+        assert!(meter.reading().short_term.is_none());
+    }
+
+    #[test]
+    fn integrated_loudness_ignores_silence() {
+        let sample_rate = SampleRate::from(48000);
+        let mut meter = LoudnessMeter::new_with(sample_rate);
+        meter.process(&vec![StereoSample::SILENCE; 48000 * 5]);
+        assert_eq!(meter.reading().integrated, None);
+        meter.process(&sine(997.0, 0.5, sample_rate, 5.0));
+        meter.process(&vec![StereoSample::SILENCE; 48000 * 5]);
+        let integrated = meter.reading().integrated.unwrap();
+        assert!(
+            (integrated + 6.02).abs() < 0.5,
+            "silence should be gated out of the integrated loudness, but got {integrated}"
+        );
+
+        // A long quiet passage more than 10 LU down is gated out relative to
+        // the loud one, and the meter doesn't grow as it runs.
+        let histogram_len = meter.block_histogram.len();
+        meter.process(&sine(997.0, 0.05, sample_rate, 5.0));
+        assert_eq!(meter.block_histogram.len(), histogram_len);
+        let integrated = meter.reading().integrated.unwrap();
+        assert!(
+            (integrated + 6.02).abs() < 0.5,
+            "blocks below the relative gate should be ignored, but got {integrated}"
+        );
+    }
+}
@@ -4,22 +4,44 @@
 
 /// The most commonly used imports.
 pub mod prelude {
-    pub use super::{Orchestrator, Project, ProjectTitle, TrackUid};
+    pub use super::{Orchestrator, Project, ProjectTitle, TempoMap, TimeSignatureMap, TrackUid};
 }
 
-pub use bus::{BusRoute, BusStation};
+pub use audio_clip::{
+    AudioClip, AudioClipBuilder, AudioClipRepository, AudioClipUid, AudioClipUidFactory,
+};
+pub use bus::{BusRoute, BusStation, SendTap};
 pub use ensnare::orchestration::{TrackTitle, TrackUid, TrackUidFactory};
+pub use history::{HistoryEntry, ProjectHistory};
+pub use metering::{
+    LevelMeter, LevelReading, LoudnessMeter, LoudnessReading, MeterReadings, Meters,
+};
 pub use midi_router::MidiRouter;
-pub use orchestrator::Orchestrator;
+pub use offline::{OfflineRenderJob, OfflineRenderOutcome, OfflineRenderTarget, RenderProgress};
+pub use orchestrator::{Mixer, Orchestrator, PanLaw};
 pub use project::{
     AudioSenderFn, Project, ProjectTitle, ProjectViewState, SignalChainItem, TrackViewMode,
 };
 pub(crate) use repositories::EntityRepository;
+pub use stems::StemExportOptions;
+pub use tempo_map::{TempoEvent, TempoMap, TempoTransition};
+pub use time_signature_map::{TimeSignatureChange, TimeSignatureMap};
+pub use wav::{Dither, Normalization, WavExportOptions, WavSampleFormat};
 
+mod audio_clip;
 mod bus;
+mod history;
 mod humidity;
+mod metering;
 mod midi_router;
+mod offline;
 mod orchestrator;
 mod project;
+mod render;
 mod repositories;
+mod smf;
+mod stems;
+mod tempo_map;
+mod time_signature_map;
 mod track;
+mod wav;
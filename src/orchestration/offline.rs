@@ -0,0 +1,235 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Renders a project to files without tying up the project itself.
+
+use super::{StemExportOptions, WavExportOptions};
+use crate::prelude::*;
+use anyhow::Result;
+use ensnare::prelude::*;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Follows an offline render from any thread, and can ask it to stop. Clones
+/// share the same render.
+#[derive(Debug, Clone, Default)]
+pub struct RenderProgress {
+    frames_done: Arc<AtomicUsize>,
+    frames_total: Arc<AtomicUsize>,
+    is_cancelled: Arc<AtomicBool>,
+    is_finished: Arc<AtomicBool>,
+}
+impl RenderProgress {
+    /// How many frames have been rendered so far.
+    pub fn frames_done(&self) -> usize {
+        self.frames_done.load(Ordering::Relaxed)
+    }
+
+    /// About how many frames the render will take. It's an estimate because
+    /// effects can ring on past the end of the last arrangement.
+    pub fn frames_total(&self) -> usize {
+        self.frames_total.load(Ordering::Relaxed)
+    }
+
+    /// How far along the render is, from 0.0 to 1.0.
+    pub fn fraction(&self) -> f32 {
+        let frames_total = self.frames_total();
+        if frames_total == 0 {
+            0.0
+        } else {
+            (self.frames_done() as f32 / frames_total as f32).min(1.0)
+        }
+    }
+
+    /// Asks the render to stop as soon as it can. Whatever it had written is
+    /// deleted.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether someone asked the render to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the render has stopped, however it ended.
+    pub fn is_finished(&self) -> bool {
+        self.is_finished.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn finish(&self) {
+        self.is_finished.store(true, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_frames_total(&self, frames_total: usize) {
+        self.frames_total.store(frames_total, Ordering::Relaxed);
+    }
+
+    pub(super) fn advance(&self, frames: usize) {
+        self.frames_done.fetch_add(frames, Ordering::Relaxed);
+    }
+}
+
+/// What an [OfflineRenderJob] writes.
+#[derive(Debug, Clone)]
+pub enum OfflineRenderTarget {
+    /// The whole mix, to a single WAV file.
+    Wav(PathBuf, WavExportOptions),
+    /// Tracks, each to its own WAV file in a directory.
+    Stems(PathBuf, StemExportOptions),
+}
+impl OfflineRenderTarget {
+    /// The file or directory that the render writes.
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            OfflineRenderTarget::Wav(path, _) | OfflineRenderTarget::Stems(path, _) => path,
+        }
+    }
+}
+
+/// How an [OfflineRenderJob] ended, if it didn't fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineRenderOutcome {
+    /// Everything was written.
+    Finished,
+    /// The render stopped early, and its files were deleted.
+    Cancelled,
+}
+
+/// Renders a copy of a [Project] to files. The copy is taken when the job is
+/// created, so the job can run on a thread of its own while the original goes
+/// on playing and being edited.
+#[derive(Debug)]
+pub struct OfflineRenderJob {
+    project: Project,
+    target: OfflineRenderTarget,
+    progress: RenderProgress,
+}
+impl OfflineRenderJob {
+    pub fn new_with(project: &mut Project, target: OfflineRenderTarget) -> Result<Self> {
+        Ok(Self {
+            project: project.duplicate()?,
+            target,
+            progress: RenderProgress::default(),
+        })
+    }
+
+    /// A handle for following the job's progress and cancelling it.
+    pub fn progress(&self) -> RenderProgress {
+        self.progress.clone()
+    }
+
+    pub fn target(&self) -> &OfflineRenderTarget {
+        &self.target
+    }
+
+    /// Renders until the job is finished or cancelled. A cancelled job leaves
+    /// no files behind.
+    pub fn run(mut self) -> Result<OfflineRenderOutcome> {
+        let outcome = self.render();
+        self.progress.finish();
+        outcome
+    }
+
+    fn render(&mut self) -> Result<OfflineRenderOutcome> {
+        // Nobody is listening, so use every CPU to get it done sooner.
+        self.project
+            .set_render_thread_count(Some(Orchestrator::default_render_thread_count()));
+        let paths = match &self.target {
+            OfflineRenderTarget::Wav(path, options) => {
+                self.project
+                    .write_wav(path.clone(), options, &self.progress)?;
+                vec![path.clone()]
+            }
+            OfflineRenderTarget::Stems(path, options) => {
+                self.project
+                    .write_stems(path.clone(), options, &self.progress)?
+            }
+        };
+        if self.progress.is_cancelled() {
+            paths.iter().for_each(|path| {
+                let _ = std::fs::remove_file(path);
+            });
+            Ok(OfflineRenderOutcome::Cancelled)
+        } else {
+            Ok(OfflineRenderOutcome::Finished)
+        }
+    }
+}
+
+impl Project {
+    /// About how many frames it takes to render the whole piece, going by the
+    /// end of the last arrangement.
+    pub fn estimated_frame_count(&self) -> usize {
+        self.tempo_map.time_to_frames(
+            self.tempo(),
+            self.sample_rate(),
+            self.composer.extent().0.end,
+        )
+    }
+
+    // An independent copy of the project, made the same way as an undo
+    // snapshot.
+    fn duplicate(&mut self) -> Result<Project> {
+        self.before_ser();
+        let json = serde_json::to_string(&self)?;
+        let mut project = serde_json::from_str::<Project>(&json)?;
+        project.after_deser();
+        project.update_sample_rate(self.sample_rate());
+        project.update_tempo(project.tempo());
+        project.update_time_signature(project.time_signature());
+        Ok(project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_progress_mainline() {
+        let progress = RenderProgress::default();
+        assert_eq!(progress.fraction(), 0.0);
+
+        let other_handle = progress.clone();
+        progress.set_frames_total(100);
+        progress.advance(25);
+        assert_eq!(other_handle.frames_done(), 25);
+        assert_eq!(other_handle.fraction(), 0.25);
+        progress.advance(100);
+        assert_eq!(
+            other_handle.fraction(),
+            1.0,
+            "Effect tails shouldn't push progress past the end"
+        );
+
+        assert!(!progress.is_cancelled());
+        other_handle.cancel();
+        assert!(progress.is_cancelled());
+
+        assert!(!other_handle.is_finished());
+        progress.finish();
+        assert!(other_handle.is_finished());
+    }
+
+    #[test]
+    fn cancelled_job_leaves_no_files() {
+        let mut project = Project::default();
+        let path = std::env::temp_dir().join("ensnare-cancelled-render.wav");
+        let job = OfflineRenderJob::new_with(
+            &mut project,
+            OfflineRenderTarget::Wav(path.clone(), WavExportOptions::default()),
+        )
+        .unwrap();
+        assert_eq!(job.target().path(), &path);
+        let progress = job.progress();
+        progress.cancel();
+        assert_eq!(job.run().unwrap(), OfflineRenderOutcome::Cancelled);
+        assert!(!path.exists());
+        assert!(progress.is_finished());
+    }
+}
@@ -2,16 +2,24 @@
 
 use super::{
     humidity::Humidifier,
+    render::{render_wave, TrackBuffers, TrackRenderContext, TrackRenderJob, TrackRenderKind},
     repositories::{EntityRepository, TrackRepository},
-    BusStation,
+    stems::StemCapture,
+    AudioClip, AudioClipRepository, AudioClipUid, BusStation, MeterReadings, Meters, SendTap,
+    StemExportOptions,
 };
-use crate::prelude::*;
-use anyhow::Result;
+use crate::{entities::accepts_sidechain_key, prelude::*};
+use anyhow::{anyhow, Result};
 use core::fmt::Debug;
 use delegate::delegate;
 use ensnare::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 use serde::{Deserialize, Serialize};
+use std::{
+    hash::{Hash, Hasher},
+    sync::OnceLock,
+};
+use strum_macros::{Display, EnumIter, IntoStaticStr};
 
 /// [Orchestrator] brings together all a project's musical instruments and
 /// effects. Working mainly with [Composer] and [Automator](crate::Automator),
@@ -21,13 +29,44 @@ use serde::{Deserialize, Serialize};
 pub struct Orchestrator {
     pub track_repo: TrackRepository,
     pub entity_repo: EntityRepository,
+    #[serde(default)]
+    pub audio_clip_repo: AudioClipRepository,
 
     pub aux_track_uids: Vec<TrackUid>,
+    #[serde(default)]
+    pub group_track_uids: Vec<TrackUid>,
+    /// The group that each grouped track belongs to.
+    #[serde(default)]
+    pub track_groups: FxHashMap<TrackUid, TrackUid>,
     pub bus_station: BusStation,
     pub humidifier: Humidifier,
     pub mixer: Mixer,
+
+    /// How many threads may render tracks at once, or None for just one.
+    #[serde(skip)]
+    render_thread_count: Option<usize>,
+
+    #[serde(skip)]
+    render_waves: RenderWaves,
+
+    // Dry-signal scratch space for wet/dry effects rendered on this thread.
+    #[serde(skip)]
+    pre_effect_buffer: GenerationBuffer<StereoSample>,
+
+    #[serde(skip)]
+    meters: Meters,
+
+    #[serde(skip)]
+    stem_capture: Option<StemCapture>,
 }
 impl Orchestrator {
+    /// The fixed [TrackUid] of the master track, whose effects and output level
+    /// apply to the final mix. It's never in [Orchestrator::track_uids()], and
+    /// it can't be deleted, grouped, or sent from. Add effects to it with
+    /// [Orchestrator::add_entity()] and set its level with
+    /// [Orchestrator::set_track_output()].
+    pub const MASTER_TRACK_UID: TrackUid = TrackUid(0);
+
     delegate! {
         to self.track_repo {
             pub fn create_track(&mut self) -> Result<TrackUid>;
@@ -48,17 +87,24 @@ impl Orchestrator {
                 new_track_uid: Option<TrackUid>,
                 new_position: Option<usize>,
             ) -> Result<()>;
-            pub fn delete_entity(&mut self, uid: Uid) -> Result<()>;
-            pub fn remove_entity(&mut self, uid: Uid) -> Result<Box<dyn Entity>>;
             pub fn mint_entity_uid(&self) -> Uid;
         }
         to self.entity_repo.entities {
             #[call(get_mut)]
             pub fn get_entity_mut(&mut self, uid: &Uid) -> Option<&mut Box<(dyn Entity)>>;
         }
+        to self.audio_clip_repo {
+            #[call(remove_clip)]
+            pub fn remove_audio_clip(&mut self, clip_uid: AudioClipUid) -> Result<AudioClip>;
+            #[call(clip_uids)]
+            pub fn audio_clip_uids(&self, track_uid: TrackUid) -> &[AudioClipUid];
+        }
         to self.bus_station {
-            pub fn add_send(&mut self, src_uid: TrackUid, dst_uid: TrackUid, amount: Normal) -> anyhow::Result<()>;
+            pub fn set_send_tap(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid, tap: SendTap) -> Result<()>;
+            pub fn set_send_pan(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid, pan: BipolarNormal) -> Result<()>;
             pub fn remove_send(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid);
+            pub fn remove_sidechain(&mut self, effect_uid: Uid) -> Option<TrackUid>;
+            pub fn sidechain(&self, effect_uid: Uid) -> Option<TrackUid>;
         }
         to self.humidifier {
             pub fn get_humidity(&self, uid: &Uid) -> Normal;
@@ -73,18 +119,554 @@ impl Orchestrator {
         to self.mixer {
             pub fn track_output(&mut self, track_uid: TrackUid) -> Normal;
             pub fn set_track_output(&mut self, track_uid: TrackUid, output: Normal);
+            pub fn track_pan(&self, track_uid: TrackUid) -> BipolarNormal;
+            pub fn set_track_pan(&mut self, track_uid: TrackUid, pan: BipolarNormal);
+            pub fn pan_law(&self) -> PanLaw;
+            pub fn set_pan_law(&mut self, pan_law: PanLaw);
             pub fn mute_track(&mut self, track_uid: TrackUid, should_mute: bool);
-            pub fn is_track_muted(&mut self, track_uid: TrackUid) -> bool;
+            pub fn is_track_muted(&self, track_uid: TrackUid) -> bool;
             pub fn solo_track(&self) -> Option<TrackUid>;
             pub fn set_solo_track(&mut self, track_uid: Option<TrackUid>);
+            pub fn solo_tracks(&self) -> &[TrackUid];
+            pub fn is_track_soloed(&self, track_uid: TrackUid) -> bool;
+            pub fn set_track_solo(&mut self, track_uid: TrackUid, should_solo: bool);
+            pub fn is_track_solo_safe(&self, track_uid: TrackUid) -> bool;
+            pub fn set_track_solo_safe(&mut self, track_uid: TrackUid, is_solo_safe: bool);
         }
     }
 
     pub fn delete_track(&mut self, uid: TrackUid) -> Result<()> {
+        if uid == Self::MASTER_TRACK_UID {
+            return Err(anyhow!("The master track can't be deleted"));
+        }
         self.bus_station.remove_sends_for_track(uid);
+        self.bus_station.remove_sidechains_keyed_by_track(uid);
+        self.mixer.set_track_solo(uid, false);
+        self.meters.remove_track(uid);
+
+        // Anything in a deleted group moves up into the group's own group.
+        let parent_group_uid = self.track_groups.remove(&uid);
+        for child_uid in self.group_children(uid) {
+            if let Some(parent_group_uid) = parent_group_uid {
+                self.track_groups.insert(child_uid, parent_group_uid);
+            } else {
+                self.track_groups.remove(&child_uid);
+            }
+        }
+        self.group_track_uids.retain(|t| *t != uid);
+
+        if let Some(entity_uids) = self.entity_repo.uids_for_track.get(&uid) {
+            entity_uids.iter().for_each(|entity_uid| {
+                self.bus_station.remove_sidechain(*entity_uid);
+            });
+        }
+        self.audio_clip_repo.remove_clips_for_track(uid);
         self.track_repo.delete_track(uid)
     }
 
+    /// Sends some of a track's signal to an aux track. Both regular and aux
+    /// tracks can send, but only aux tracks can receive, and an aux track
+    /// can't send to itself, directly or through other aux tracks.
+    pub fn add_send(&mut self, src_uid: TrackUid, dst_uid: TrackUid, amount: Normal) -> Result<()> {
+        if !self.aux_track_uids.contains(&dst_uid) {
+            return Err(anyhow!("Track {dst_uid} isn't an aux track"));
+        }
+        if src_uid == Self::MASTER_TRACK_UID {
+            return Err(anyhow!("The master track can't send to other tracks"));
+        }
+        self.bus_station.add_send(src_uid, dst_uid, amount)
+    }
+
+    pub fn delete_entity(&mut self, uid: Uid) -> Result<()> {
+        self.bus_station.remove_sidechain(uid);
+        self.entity_repo.delete_entity(uid)
+    }
+
+    pub fn remove_entity(&mut self, uid: Uid) -> Result<Box<dyn Entity>> {
+        self.bus_station.remove_sidechain(uid);
+        self.entity_repo.remove_entity(uid)
+    }
+
+    /// Feeds the output of the key track to the effect's sidechain key input.
+    /// The effect must be one that takes a key, such as a compressor or gate;
+    /// see [TransformsAudioWithKey].
+    ///
+    /// The key is the track's signal before its output level is applied, so a
+    /// track can key an effect without being heard. Aux tracks can't be keys,
+    /// and a route that would make a track key itself, directly or through
+    /// other tracks, is an error.
+    pub fn add_sidechain(&mut self, effect_uid: Uid, key_track_uid: TrackUid) -> Result<()> {
+        let Some(effect_track_uid) = self.track_for_entity(effect_uid) else {
+            return Err(anyhow!("Entity {effect_uid} isn't on a track"));
+        };
+        if !self
+            .entity_repo
+            .entity(effect_uid)
+            .is_some_and(|entity| accepts_sidechain_key(entity.as_ref()))
+        {
+            return Err(anyhow!("Entity {effect_uid} doesn't take a sidechain key"));
+        }
+        if !self.track_repo.uids.contains(&key_track_uid) {
+            return Err(anyhow!("Track {key_track_uid} doesn't exist"));
+        }
+        if self.aux_track_uids.contains(&key_track_uid) {
+            return Err(anyhow!(
+                "Aux track {key_track_uid} can't be a sidechain key"
+            ));
+        }
+        if self.depends_on(key_track_uid, effect_track_uid) {
+            return Err(anyhow!(
+                "Keying track {effect_track_uid} with track {key_track_uid} would create a cycle"
+            ));
+        }
+        self.bus_station.set_sidechain(effect_uid, key_track_uid);
+        Ok(())
+    }
+
+    /// Places the given clip on the given track. Clips belong on audio tracks,
+    /// so it's an error to put one on an aux, group, or master track, or on a
+    /// track that doesn't exist.
+    pub fn add_audio_clip(&mut self, track_uid: TrackUid, clip: AudioClip) -> Result<AudioClipUid> {
+        if track_uid == Self::MASTER_TRACK_UID
+            || self.aux_track_uids.contains(&track_uid)
+            || self.is_group_track(track_uid)
+        {
+            return Err(anyhow!("Track {track_uid} can't hold audio clips"));
+        }
+        if !self.track_repo.uids.contains(&track_uid) {
+            return Err(anyhow!("Track {track_uid} doesn't exist"));
+        }
+        self.audio_clip_repo.add_clip(track_uid, clip)
+    }
+
+    /// Whether the track is a group track, which sums the output of the tracks
+    /// in it.
+    pub fn is_group_track(&self, track_uid: TrackUid) -> bool {
+        self.group_track_uids.contains(&track_uid)
+    }
+
+    /// The group that the track belongs to, if any.
+    pub fn track_group(&self, track_uid: TrackUid) -> Option<TrackUid> {
+        self.track_groups.get(&track_uid).copied()
+    }
+
+    /// The tracks directly in the given group, in track order.
+    pub fn group_children(&self, group_uid: TrackUid) -> Vec<TrackUid> {
+        self.track_repo
+            .uids
+            .iter()
+            .filter(|track_uid| self.track_group(**track_uid) == Some(group_uid))
+            .copied()
+            .collect()
+    }
+
+    /// Puts the track in the group, or (with None) takes it out of whatever
+    /// group it's in. Instead of going to the main mix, a grouped track's
+    /// output goes through the group's effects and fader. Groups can contain
+    /// other groups, but not aux tracks, and a group can't end up inside
+    /// itself.
+    pub fn set_track_group(
+        &mut self,
+        track_uid: TrackUid,
+        group_uid: Option<TrackUid>,
+    ) -> Result<()> {
+        if !self.track_repo.uids.contains(&track_uid) {
+            return Err(anyhow!("Track {track_uid} doesn't exist"));
+        }
+        let Some(group_uid) = group_uid else {
+            self.track_groups.remove(&track_uid);
+            return Ok(());
+        };
+        if !self.is_group_track(group_uid) {
+            return Err(anyhow!("Track {group_uid} isn't a group track"));
+        }
+        if self.aux_track_uids.contains(&track_uid) {
+            return Err(anyhow!("Aux track {track_uid} can't be in a group"));
+        }
+        if self.depends_on(track_uid, group_uid) {
+            return Err(anyhow!(
+                "Putting track {track_uid} in group {group_uid} would create a cycle"
+            ));
+        }
+        self.track_groups.insert(track_uid, group_uid);
+        Ok(())
+    }
+
+    // Whether the track is inside the group, directly or through other groups.
+    fn is_in_group(&self, track_uid: TrackUid, group_uid: TrackUid) -> bool {
+        let mut ancestor_uid = self.track_group(track_uid);
+        while let Some(uid) = ancestor_uid {
+            if uid == group_uid {
+                return true;
+            }
+            ancestor_uid = self.track_group(uid);
+        }
+        false
+    }
+
+    /// Whether the track should be heard. This is up to the [Mixer], except
+    /// that soloing a track also lets the groups around it be heard, and
+    /// soloing a group also lets the tracks in it be heard.
+    pub fn is_track_audible(&self, track_uid: TrackUid) -> bool {
+        if self.mixer.is_track_audible(track_uid) {
+            return true;
+        }
+        if self.mixer.is_track_muted(track_uid) || self.mixer.solo_tracks().is_empty() {
+            return false;
+        }
+        self.mixer.solo_tracks().iter().any(|solo_track_uid| {
+            self.is_in_group(track_uid, *solo_track_uid)
+                || self.is_in_group(*solo_track_uid, track_uid)
+        })
+    }
+
+    // The tracks that have to be rendered before the given one: those whose
+    // output keys an effect on it, and, for a group, the tracks in it.
+    fn render_dependencies(&self, track_uid: TrackUid) -> Vec<TrackUid> {
+        let mut dependencies: Vec<TrackUid> = self
+            .bus_station
+            .sidechains
+            .iter()
+            .filter(|(effect_uid, _)| self.track_for_entity(**effect_uid) == Some(track_uid))
+            .map(|(_, key_track_uid)| *key_track_uid)
+            .collect();
+        dependencies.extend(self.group_children(track_uid));
+        dependencies
+    }
+
+    // Whether the track needs the other track's output, directly or
+    // indirectly, before it can be rendered. A track depends on itself.
+    fn depends_on(&self, track_uid: TrackUid, other_track_uid: TrackUid) -> bool {
+        let mut visited = FxHashSet::default();
+        let mut to_visit = vec![track_uid];
+        while let Some(track_uid) = to_visit.pop() {
+            if track_uid == other_track_uid {
+                return true;
+            }
+            if visited.insert(track_uid) {
+                to_visit.extend(self.render_dependencies(track_uid));
+            }
+        }
+        false
+    }
+
+    // The non-aux tracks in the order they should be rendered, so that every
+    // sidechain key is ready before the effect that listens to it, and every
+    // group's tracks are ready before the group.
+    fn track_render_order(&self) -> Vec<TrackUid> {
+        fn visit(
+            orchestrator: &Orchestrator,
+            track_uid: TrackUid,
+            visited: &mut FxHashSet<TrackUid>,
+            order: &mut Vec<TrackUid>,
+        ) {
+            if !visited.insert(track_uid) {
+                return;
+            }
+            for dependency_uid in orchestrator.render_dependencies(track_uid) {
+                if orchestrator.track_repo.uids.contains(&dependency_uid)
+                    && !orchestrator.aux_track_uids.contains(&dependency_uid)
+                {
+                    visit(orchestrator, dependency_uid, visited, order);
+                }
+            }
+            order.push(track_uid);
+        }
+
+        let mut visited = FxHashSet::default();
+        let mut order = Vec::default();
+        for track_uid in self.track_repo.uids.iter() {
+            if !self.aux_track_uids.contains(track_uid) {
+                visit(self, *track_uid, &mut visited, &mut order);
+            }
+        }
+        order
+    }
+
+    // The aux tracks in the order they should be processed, so that an aux
+    // track that sends to another is finished before the other one starts.
+    fn aux_track_render_order(&self) -> Vec<TrackUid> {
+        fn visit(
+            orchestrator: &Orchestrator,
+            track_uid: TrackUid,
+            visited: &mut FxHashSet<TrackUid>,
+            order: &mut Vec<TrackUid>,
+        ) {
+            if !visited.insert(track_uid) {
+                return;
+            }
+            if let Some(routes) = orchestrator.bus_station.sends_for_track(&track_uid) {
+                for route in routes {
+                    if orchestrator.aux_track_uids.contains(&route.aux_track_uid) {
+                        visit(orchestrator, route.aux_track_uid, visited, order);
+                    }
+                }
+            }
+            order.push(track_uid);
+        }
+
+        // This visits receivers before senders, so reverse it at the end.
+        let mut visited = FxHashSet::default();
+        let mut order = Vec::default();
+        for track_uid in self.track_repo.uids.iter() {
+            if self.aux_track_uids.contains(track_uid) {
+                visit(self, *track_uid, &mut visited, &mut order);
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    // The non-aux tracks, split into waves that can each be rendered all at
+    // once because every track in a wave depends only on earlier waves.
+    fn track_render_waves(&self) -> Vec<Vec<TrackUid>> {
+        let order = self.track_render_order();
+        let mut waves: FxHashMap<TrackUid, usize> = FxHashMap::default();
+        for track_uid in order.iter() {
+            let wave = self
+                .render_dependencies(*track_uid)
+                .iter()
+                .filter_map(|dependency_uid| waves.get(dependency_uid))
+                .map(|wave| wave + 1)
+                .max()
+                .unwrap_or_default();
+            waves.insert(*track_uid, wave);
+        }
+        Self::split_into_waves(&order, &waves)
+    }
+
+    // Like [Orchestrator::track_render_waves()], but for the aux tracks, which
+    // depend on the aux tracks that send to them.
+    fn aux_track_render_waves(&self) -> Vec<Vec<TrackUid>> {
+        let order = self.aux_track_render_order();
+        let mut waves: FxHashMap<TrackUid, usize> = FxHashMap::default();
+        for track_uid in order.iter() {
+            let wave = waves.get(track_uid).copied().unwrap_or_default();
+            waves.insert(*track_uid, wave);
+            if let Some(routes) = self.bus_station.sends_for_track(track_uid) {
+                for route in routes {
+                    let receiver_wave = waves.entry(route.aux_track_uid).or_default();
+                    *receiver_wave = (*receiver_wave).max(wave + 1);
+                }
+            }
+        }
+        Self::split_into_waves(&order, &waves)
+    }
+
+    // Groups the tracks by wave number, keeping them in the given order within
+    // each wave.
+    fn split_into_waves(
+        order: &[TrackUid],
+        waves: &FxHashMap<TrackUid, usize>,
+    ) -> Vec<Vec<TrackUid>> {
+        let mut split: Vec<Vec<TrackUid>> = Vec::default();
+        for track_uid in order {
+            let wave = waves.get(track_uid).copied().unwrap_or_default();
+            if split.len() <= wave {
+                split.resize_with(wave + 1, Vec::default);
+            }
+            split[wave].push(*track_uid);
+        }
+        split
+    }
+
+    // Recomputes the render waves if anything they depend on has changed
+    // since they were last worked out.
+    fn update_render_waves(&mut self) {
+        let fingerprint = self.render_waves_fingerprint();
+        if self.render_waves.fingerprint != Some(fingerprint) {
+            self.render_waves = RenderWaves {
+                fingerprint: Some(fingerprint),
+                track_waves: self.track_render_waves(),
+                aux_track_waves: self.aux_track_render_waves(),
+            };
+        }
+    }
+
+    // A hash of everything that decides the render waves: the tracks and their
+    // order, which of them are aux or group tracks, what's in each group, who
+    // sends to whom, and which track each sidechained effect is on. It's cheap
+    // enough to check before every buffer, and unlike invalidating the waves
+    // by hand, it can't miss an edit.
+    fn render_waves_fingerprint(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.track_repo.uids.hash(&mut hasher);
+        self.aux_track_uids.hash(&mut hasher);
+        self.group_track_uids.hash(&mut hasher);
+        for (track_uid, group_uid) in self.track_groups.iter() {
+            (track_uid, group_uid).hash(&mut hasher);
+        }
+        for (track_uid, routes) in self.bus_station.sends() {
+            track_uid.hash(&mut hasher);
+            routes
+                .iter()
+                .for_each(|route| route.aux_track_uid.hash(&mut hasher));
+        }
+        for (effect_uid, key_track_uid) in self.bus_station.sidechains.iter() {
+            (self.track_for_entity(*effect_uid), key_track_uid).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// One thread per CPU, which is what an offline render asks for.
+    pub fn default_render_thread_count() -> usize {
+        static COUNT: OnceLock<usize> = OnceLock::new();
+        *COUNT.get_or_init(|| {
+            std::thread::available_parallelism()
+                .map(|count| count.get())
+                .unwrap_or(1)
+        })
+    }
+
+    /// How many threads render tracks that don't depend on each other.
+    pub fn render_thread_count(&self) -> usize {
+        self.render_thread_count.unwrap_or(1).max(1)
+    }
+
+    /// Sets how many threads render tracks, or None for just one. The rendered
+    /// audio is the same no matter how many threads there are.
+    ///
+    /// The extra threads are started for each wave of each buffer, which costs
+    /// more than it saves at realtime buffer sizes, so this is meant for
+    /// offline renders, where throughput matters more than latency.
+    pub fn set_render_thread_count(&mut self, count: Option<usize>) {
+        self.render_thread_count = count;
+    }
+
+    /// The levels of each track and of the final mix, and the loudness of the
+    /// final mix, as of the most recent [Generates::generate()].
+    pub fn meter_readings(&self) -> MeterReadings {
+        self.meters.readings()
+    }
+
+    /// Like [Orchestrator::meter_readings()], but overwrites existing readings
+    /// rather than allocating new ones.
+    pub fn update_meter_readings(&self, readings: &mut MeterReadings) {
+        self.meters.update_readings(readings);
+    }
+
+    /// Starts the meters over. This happens automatically when playback
+    /// skips to the start.
+    pub fn reset_meters(&mut self) {
+        self.meters.reset();
+    }
+
+    /// The tracks that a stem export with the given options covers, in track
+    /// order.
+    pub fn stem_track_uids(&self, options: &StemExportOptions) -> Vec<TrackUid> {
+        let mut selected: FxHashSet<TrackUid> = match options.track_uids.as_ref() {
+            Some(track_uids) => track_uids.iter().copied().collect(),
+            None => self
+                .track_repo
+                .uids
+                .iter()
+                .filter(|track_uid| !self.aux_track_uids.contains(track_uid))
+                .copied()
+                .collect(),
+        };
+        if options.include_sends {
+            // Aux tracks can send to other aux tracks, so keep following sends
+            // until there's nowhere new to go.
+            let mut pending: Vec<TrackUid> = selected.iter().copied().collect();
+            while let Some(track_uid) = pending.pop() {
+                let Some(routes) = self.bus_station.sends_for_track(&track_uid) else {
+                    continue;
+                };
+                for route in routes {
+                    if selected.insert(route.aux_track_uid) {
+                        pending.push(route.aux_track_uid);
+                    }
+                }
+            }
+        }
+        self.track_repo
+            .uids
+            .iter()
+            .filter(|track_uid| selected.contains(track_uid))
+            .copied()
+            .collect()
+    }
+
+    /// Starts keeping a copy of the given tracks' output on each
+    /// [Generates::generate()]. See [Orchestrator::captured_stem()].
+    pub fn start_stem_capture(&mut self, track_uids: &[TrackUid], is_post_fader: bool) {
+        self.stem_capture = Some(StemCapture::new_with(track_uids, is_post_fader));
+    }
+
+    /// Stops keeping copies of tracks' output.
+    pub fn stop_stem_capture(&mut self) {
+        self.stem_capture = None;
+    }
+
+    /// The track's output from the most recent [Generates::generate()], if
+    /// it's being captured. A track that isn't heard is silent.
+    pub fn captured_stem(&self, track_uid: TrackUid) -> Option<&[StereoSample]> {
+        self.stem_capture
+            .as_ref()
+            .and_then(|capture| capture.buffers.get(&track_uid))
+            .map(|buffer| buffer.as_slice())
+    }
+
+    // Copies the output of each track being captured, either as the mixer
+    // hears it or as it left the track's last effect.
+    fn capture_stems(
+        &mut self,
+        track_buffers: &FxHashMap<TrackUid, Vec<StereoSample>>,
+        aux_track_buffers: &FxHashMap<TrackUid, Vec<StereoSample>>,
+        audible_track_uids: &FxHashSet<TrackUid>,
+        buffer_len: usize,
+    ) {
+        let Some(capture) = self.stem_capture.as_mut() else {
+            return;
+        };
+        for (track_uid, stem) in capture.buffers.iter_mut() {
+            stem.clear();
+            stem.resize(buffer_len, StereoSample::SILENCE);
+            if !audible_track_uids.contains(track_uid) {
+                continue;
+            }
+            let Some(buffer) = track_buffers
+                .get(track_uid)
+                .or_else(|| aux_track_buffers.get(track_uid))
+            else {
+                continue;
+            };
+            if capture.is_post_fader {
+                self.mixer.mix_track(*track_uid, buffer, stem);
+            } else {
+                stem.copy_from_slice(buffer);
+            }
+        }
+    }
+
+    // Measures each track's output as the mixer will hear it, and then the
+    // final mix.
+    fn update_meters(
+        &mut self,
+        track_buffers: &FxHashMap<TrackUid, Vec<StereoSample>>,
+        aux_track_buffers: &FxHashMap<TrackUid, Vec<StereoSample>>,
+        audible_track_uids: &FxHashSet<TrackUid>,
+        final_mix: &[StereoSample],
+    ) {
+        for track_uid in self.track_repo.uids.iter() {
+            let Some(buffer) = track_buffers
+                .get(track_uid)
+                .or_else(|| aux_track_buffers.get(track_uid))
+            else {
+                continue;
+            };
+            let gains = if audible_track_uids.contains(track_uid) {
+                let output = self.mixer.track_output(*track_uid).0;
+                let (left_gain, right_gain) = self.mixer.pan_gains(*track_uid);
+                (output * left_gain, output * right_gain)
+            } else {
+                (0.0, 0.0)
+            };
+            self.meters.process_track(*track_uid, buffer, gains);
+        }
+        self.meters.process_master(final_mix);
+    }
+
     pub fn entity_uids(&self, uid: TrackUid) -> Option<&[Uid]> {
         let uids = self.entity_repo.uids_for_track.get(&uid);
         if let Some(uids) = uids {
@@ -105,11 +687,12 @@ impl Controls for Orchestrator {
     }
 
     fn update_time_range(&mut self, time_range: &TimeRange) {
-        self.entity_repo.update_time_range(time_range)
+        self.entity_repo.update_time_range(time_range);
+        self.audio_clip_repo.update_time_range(time_range);
     }
 
     fn is_finished(&self) -> bool {
-        self.entity_repo.is_finished()
+        self.entity_repo.is_finished() && self.audio_clip_repo.is_finished()
     }
 
     fn play(&mut self) {
@@ -121,7 +704,8 @@ impl Controls for Orchestrator {
     }
 
     fn skip_to_start(&mut self) {
-        self.entity_repo.skip_to_start()
+        self.entity_repo.skip_to_start();
+        self.reset_meters();
     }
 
     fn is_performing(&self) -> bool {
@@ -136,95 +720,160 @@ impl ControlsAsProxy for Orchestrator {
 impl Generates<StereoSample> for Orchestrator {
     fn generate(&mut self, values: &mut [StereoSample]) -> bool {
         let buffer_len = values.len();
-        let solo_track_uid = self.solo_track();
-
-        // First handle all non-aux tracks. As a side effect, we also create empty buffers for the aux tracks.
-        let (track_buffers, mut aux_track_buffers): (
-            FxHashMap<TrackUid, Vec<StereoSample>>,
-            FxHashMap<TrackUid, Vec<StereoSample>>,
-        ) = self.track_repo.uids.iter().fold(
-            (FxHashMap::default(), FxHashMap::default()),
-            |(mut h, mut aux_h), track_uid| {
-                let mut track_buffer = Vec::default();
-                track_buffer.resize(buffer_len, StereoSample::SILENCE);
-                if self.aux_track_uids.contains(track_uid) {
-                    aux_h.insert(*track_uid, track_buffer);
-                } else {
-                    let should_work = !self.mixer.is_track_muted(*track_uid)
-                        && (solo_track_uid.is_none() || solo_track_uid == Some(*track_uid));
-                    if should_work {
-                        if let Some(entity_uids) = self.entity_repo.uids_for_track.get(track_uid) {
-                            entity_uids.iter().for_each(|uid| {
-                                if let Some(entity) = self.entity_repo.entities.get_mut(uid) {
-                                    entity.generate(&mut track_buffer);
-                                    let humidity = self.humidifier.get_humidity(uid);
-                                    if humidity != Normal::zero() {
-                                        self.humidifier.transform_batch(
-                                            humidity,
-                                            entity,
-                                            &mut track_buffer,
-                                        );
-                                    }
-                                }
-                            });
-                        }
-                    }
-                    h.insert(*track_uid, track_buffer);
-                }
-                (h, aux_h)
-            },
-        );
+        let audible_track_uids: FxHashSet<TrackUid> = self
+            .track_repo
+            .uids
+            .iter()
+            .filter(|track_uid| self.is_track_audible(**track_uid))
+            .copied()
+            .collect();
+        let group_children: FxHashMap<TrackUid, Vec<TrackUid>> = self
+            .group_track_uids
+            .iter()
+            .map(|group_uid| (*group_uid, self.group_children(*group_uid)))
+            .collect();
+        let thread_count = self.render_thread_count();
+        self.update_render_waves();
 
-        // Then send audio to the aux tracks.
-        for (track_uid, routes) in self.bus_station.sends() {
-            // We have a source track_uid and the aux tracks that should receive it.
-            if let Some(source_track_buffer) = track_buffers.get(track_uid) {
-                // Mix the source into the destination aux track.
-                for route in routes {
-                    if let Some(aux) = aux_track_buffers.get_mut(&route.aux_track_uid) {
-                        for (src, dst) in source_track_buffer.iter().zip(aux.iter_mut()) {
-                            *dst += *src * route.amount;
-                        }
-                    }
-                }
+        // The render jobs borrow the entities, and everything else they need
+        // is read-only, so take the orchestrator apart to lend out each piece
+        // separately.
+        let Self {
+            track_repo,
+            entity_repo,
+            audio_clip_repo,
+            aux_track_uids,
+            track_groups,
+            bus_station,
+            humidifier,
+            mixer,
+            render_waves,
+            pre_effect_buffer,
+            ..
+        } = self;
+        let context = TrackRenderContext {
+            audio_clip_repo,
+            humidifier,
+            bus_station,
+            mixer,
+            audible_track_uids: &audible_track_uids,
+            group_children: &group_children,
+        };
+        let mut entities = entity_repo.entities_by_track_mut();
+
+        // Create empty buffers for the aux tracks.
+        let mut aux_track_buffers: FxHashMap<TrackUid, Vec<StereoSample>> = aux_track_uids
+            .iter()
+            .filter(|track_uid| track_repo.uids.contains(track_uid))
+            .map(|track_uid| (*track_uid, vec![StereoSample::SILENCE; buffer_len]))
+            .collect();
+
+        // Then handle all non-aux tracks, a wave at a time, making sure that
+        // sidechain keys and the tracks in groups are ready before anyone needs
+        // them.
+        let mut track_buffers = TrackBuffers::default();
+        for wave in render_waves.track_waves.iter() {
+            let mut jobs: Vec<TrackRenderJob> = wave
+                .iter()
+                .map(|track_uid| TrackRenderJob {
+                    track_uid: *track_uid,
+                    kind: TrackRenderKind::Regular,
+                    entities: entities.remove(track_uid).unwrap_or_default(),
+                    buffer: vec![StereoSample::SILENCE; buffer_len],
+                })
+                .collect();
+            render_wave(
+                &mut jobs,
+                &context,
+                &track_buffers,
+                thread_count,
+                pre_effect_buffer,
+            );
+            for job in jobs {
+                track_buffers.insert(job.track_uid, job.buffer);
             }
         }
 
-        // Let the aux tracks do their processing.
-        aux_track_buffers
-            .iter_mut()
-            .for_each(|(track_uid, track_buffer)| {
-                let should_work = !self.mixer.is_track_muted(*track_uid)
-                    && (solo_track_uid.is_none() || solo_track_uid == Some(*track_uid));
-                if should_work {
-                    if let Some(entity_uids) = self.entity_repo.uids_for_track.get(track_uid) {
-                        entity_uids.iter().for_each(|uid| {
-                            if let Some(entity) = self.entity_repo.entities.get_mut(uid) {
-                                entity.transform(track_buffer);
-                            }
-                        });
-                    }
-                }
-            });
+        // Then send audio from the regular tracks to the aux tracks.
+        for track_uid in track_repo.uids.iter() {
+            if let Some(track_buffer) = track_buffers.get(track_uid) {
+                context.mix_sends(*track_uid, track_buffer, &mut aux_track_buffers);
+            }
+        }
 
-        let mut generated_some_signal = false;
+        // Let the aux tracks do their processing. Aux tracks can send to other
+        // aux tracks, so each one is processed in a wave after everything that
+        // sends to it.
+        for wave in render_waves.aux_track_waves.iter() {
+            let mut jobs: Vec<TrackRenderJob> = wave
+                .iter()
+                .filter_map(|track_uid| {
+                    aux_track_buffers
+                        .remove(track_uid)
+                        .map(|track_buffer| TrackRenderJob {
+                            track_uid: *track_uid,
+                            kind: TrackRenderKind::Aux,
+                            entities: entities.remove(track_uid).unwrap_or_default(),
+                            buffer: track_buffer,
+                        })
+                })
+                .collect();
+            render_wave(
+                &mut jobs,
+                &context,
+                &track_buffers,
+                thread_count,
+                pre_effect_buffer,
+            );
+            for job in jobs {
+                context.mix_sends(job.track_uid, &job.buffer, &mut aux_track_buffers);
+                aux_track_buffers.insert(job.track_uid, job.buffer);
+            }
+        }
 
-        // Mix all the tracks into the final buffer.
-        track_buffers
-            .iter()
-            .chain(aux_track_buffers.iter())
-            .for_each(|(track_uid, buffer)| {
-                let should_mix = !self.mixer.is_track_muted(*track_uid)
-                    && (solo_track_uid.is_none() || solo_track_uid == Some(*track_uid));
-                if should_mix {
-                    let output = self.track_output(*track_uid);
-                    for (dst, src) in values.iter_mut().zip(buffer) {
-                        let stereo_sample = *src * output;
-                        generated_some_signal |= stereo_sample != StereoSample::default();
-                        *dst += stereo_sample;
-                    }
-                }
-            });
+        // Mix all the tracks into the master track, except for those in groups,
+        // which have already been mixed into their groups.
+        let mut master_buffer = vec![StereoSample::SILENCE; buffer_len];
+        for track_uid in track_repo.uids.iter() {
+            if !audible_track_uids.contains(track_uid) || track_groups.contains_key(track_uid) {
+                continue;
+            }
+            if let Some(buffer) = track_buffers
+                .get(track_uid)
+                .or_else(|| aux_track_buffers.get(track_uid))
+            {
+                context
+                    .mixer
+                    .mix_track(*track_uid, buffer, &mut master_buffer);
+            }
+        }
+
+        // Finally, the master track's effects and fader make the final mix.
+        let mut jobs = [TrackRenderJob {
+            track_uid: Self::MASTER_TRACK_UID,
+            kind: TrackRenderKind::Master,
+            entities: entities.remove(&Self::MASTER_TRACK_UID).unwrap_or_default(),
+            buffer: master_buffer,
+        }];
+        render_wave(&mut jobs, &context, &track_buffers, 1, pre_effect_buffer);
+        let [master_job] = jobs;
+        let generated_some_signal =
+            context
+                .mixer
+                .mix_track(Self::MASTER_TRACK_UID, &master_job.buffer, values);
+
+        self.update_meters(
+            &track_buffers,
+            &aux_track_buffers,
+            &audible_track_uids,
+            values,
+        );
+        self.capture_stems(
+            &track_buffers,
+            &aux_track_buffers,
+            &audible_track_uids,
+            buffer_len,
+        );
         generated_some_signal
     }
 }
@@ -234,7 +883,9 @@ impl Configurable for Orchestrator {
     }
 
     fn update_sample_rate(&mut self, sample_rate: SampleRate) {
-        self.entity_repo.update_sample_rate(sample_rate)
+        self.entity_repo.update_sample_rate(sample_rate);
+        self.audio_clip_repo.update_sample_rate(sample_rate);
+        self.meters.update_sample_rate(sample_rate);
     }
 
     fn tempo(&self) -> Tempo {
@@ -242,7 +893,8 @@ impl Configurable for Orchestrator {
     }
 
     fn update_tempo(&mut self, tempo: Tempo) {
-        self.entity_repo.update_tempo(tempo)
+        self.entity_repo.update_tempo(tempo);
+        self.audio_clip_repo.update_tempo(tempo);
     }
 
     fn time_signature(&self) -> TimeSignature {
@@ -250,7 +902,8 @@ impl Configurable for Orchestrator {
     }
 
     fn update_time_signature(&mut self, time_signature: TimeSignature) {
-        self.entity_repo.update_time_signature(time_signature)
+        self.entity_repo.update_time_signature(time_signature);
+        self.audio_clip_repo.update_time_signature(time_signature);
     }
 
     fn reset(&mut self) {
@@ -266,6 +919,61 @@ impl Serializable for Orchestrator {
     fn after_deser(&mut self) {
         self.track_repo.after_deser();
         self.entity_repo.after_deser();
+        self.audio_clip_repo.after_deser();
+
+        // A project file could key an effect that can't take a key, which
+        // would panic the first time it rendered.
+        let entity_repo = &self.entity_repo;
+        self.bus_station.sidechains.retain(|effect_uid, _| {
+            entity_repo
+                .entity(*effect_uid)
+                .is_some_and(|entity| accepts_sidechain_key(entity.as_ref()))
+        });
+    }
+}
+
+/// The order in which [Orchestrator::generate()] renders tracks, worked out
+/// once and kept until the routing changes.
+#[derive(Debug, Default)]
+struct RenderWaves {
+    // What the waves were worked out from. See
+    // [Orchestrator::render_waves_fingerprint()].
+    fingerprint: Option<u64>,
+    track_waves: Vec<Vec<TrackUid>>,
+    aux_track_waves: Vec<Vec<TrackUid>>,
+}
+
+/// How a track's pan position becomes the gains of its left and right
+/// channels.
+#[derive(
+    Clone, Copy, Debug, Default, Display, EnumIter, IntoStaticStr, PartialEq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanLaw {
+    /// Works like a stereo balance control. Both channels are at full level
+    /// when centered, and panning turns down the opposite channel.
+    #[default]
+    Balance,
+    /// Keeps the total power constant across the stereo field. Each channel is
+    /// at -3 dB when centered.
+    ConstantPower,
+    /// Keeps the sum of the channel gains constant. Each channel is at -6 dB
+    /// when centered.
+    Linear,
+}
+impl PanLaw {
+    /// Returns the left and right channel gains for the given pan position,
+    /// where -1.0 is hard left and 1.0 is hard right.
+    pub fn gains(&self, pan: BipolarNormal) -> (f64, f64) {
+        let pan = pan.0.clamp(-1.0, 1.0);
+        match self {
+            PanLaw::Balance => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+            PanLaw::ConstantPower => {
+                let angle = (pan + 1.0) * core::f64::consts::FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+            PanLaw::Linear => ((1.0 - pan) / 2.0, (1.0 + pan) / 2.0),
+        }
     }
 }
 
@@ -273,11 +981,89 @@ impl Serializable for Orchestrator {
 #[serde(rename_all = "kebab-case")]
 pub struct Mixer {
     track_output: FxHashMap<TrackUid, Normal>,
+    #[serde(default)]
+    track_pan: FxHashMap<TrackUid, BipolarNormal>,
+    #[serde(default)]
+    pan_law: PanLaw,
     track_mute: FxHashMap<TrackUid, bool>,
-    pub solo_track: Option<TrackUid>,
+    // Projects saved before several tracks could be soloed have a single
+    // `solo-track` instead.
+    #[serde(
+        default,
+        alias = "solo-track",
+        deserialize_with = "Mixer::deserialize_solo_tracks"
+    )]
+    solo_tracks: Vec<TrackUid>,
+    #[serde(default)]
+    track_solo_safe: FxHashMap<TrackUid, bool>,
 }
 impl Mixer {
-    pub fn track_output(&mut self, track_uid: TrackUid) -> Normal {
+    fn deserialize_solo_tracks<'de, D>(deserializer: D) -> Result<Vec<TrackUid>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SoloTracks {
+            Many(Vec<TrackUid>),
+            One(Option<TrackUid>),
+        }
+        Ok(match SoloTracks::deserialize(deserializer)? {
+            SoloTracks::Many(track_uids) => track_uids,
+            SoloTracks::One(track_uid) => track_uid.into_iter().collect(),
+        })
+    }
+
+    /// The [ControlIndex] that automates the given track's pan. Link it with
+    /// [Project::MIXER_UID] as the target.
+    pub fn pan_control_index(track_uid: TrackUid) -> ControlIndex {
+        ControlIndex(track_uid.0)
+    }
+
+    pub fn track_pan(&self, track_uid: TrackUid) -> BipolarNormal {
+        self.track_pan.get(&track_uid).cloned().unwrap_or_default()
+    }
+
+    pub fn set_track_pan(&mut self, track_uid: TrackUid, pan: BipolarNormal) {
+        self.track_pan.insert(track_uid, pan);
+    }
+
+    pub fn pan_law(&self) -> PanLaw {
+        self.pan_law
+    }
+
+    pub fn set_pan_law(&mut self, pan_law: PanLaw) {
+        self.pan_law = pan_law;
+    }
+
+    /// The left and right channel gains for the track's current pan.
+    pub fn pan_gains(&self, track_uid: TrackUid) -> (f64, f64) {
+        self.pan_law.gains(self.track_pan(track_uid))
+    }
+
+    /// Adds the track's signal to the destination after applying the track's
+    /// output level and pan. Returns whether the result was anything other
+    /// than silence.
+    pub fn mix_track(
+        &self,
+        track_uid: TrackUid,
+        src: &[StereoSample],
+        dst: &mut [StereoSample],
+    ) -> bool {
+        let mut generated_some_signal = false;
+        let output = self.track_output(track_uid);
+        let (left_gain, right_gain) = self.pan_gains(track_uid);
+        for (dst, src) in dst.iter_mut().zip(src) {
+            let stereo_sample = *src * output;
+            let stereo_sample =
+                StereoSample(stereo_sample.0 * left_gain, stereo_sample.1 * right_gain);
+            generated_some_signal |= stereo_sample != StereoSample::default();
+            *dst += stereo_sample;
+        }
+        generated_some_signal
+    }
+
+    pub fn track_output(&self, track_uid: TrackUid) -> Normal {
         self.track_output
             .get(&track_uid)
             .cloned()
@@ -292,16 +1078,87 @@ impl Mixer {
         self.track_mute.insert(track_uid, should_mute);
     }
 
-    pub fn is_track_muted(&mut self, track_uid: TrackUid) -> bool {
+    pub fn is_track_muted(&self, track_uid: TrackUid) -> bool {
         self.track_mute.get(&track_uid).copied().unwrap_or_default()
     }
 
+    /// The first of the soloed tracks, if any.
     pub fn solo_track(&self) -> Option<TrackUid> {
-        self.solo_track
+        self.solo_tracks.first().copied()
     }
 
+    /// Solos just the given track, or (with None) ends soloing.
     pub fn set_solo_track(&mut self, track_uid: Option<TrackUid>) {
-        self.solo_track = track_uid
+        self.solo_tracks.clear();
+        if let Some(track_uid) = track_uid {
+            self.solo_tracks.push(track_uid);
+        }
+    }
+
+    /// The soloed tracks, in the order they were soloed.
+    pub fn solo_tracks(&self) -> &[TrackUid] {
+        &self.solo_tracks
+    }
+
+    pub fn is_track_soloed(&self, track_uid: TrackUid) -> bool {
+        self.solo_tracks.contains(&track_uid)
+    }
+
+    /// Adds the track to or removes it from the soloed tracks, leaving the
+    /// others alone.
+    pub fn set_track_solo(&mut self, track_uid: TrackUid, should_solo: bool) {
+        if should_solo {
+            if !self.is_track_soloed(track_uid) {
+                self.solo_tracks.push(track_uid);
+            }
+        } else {
+            self.solo_tracks.retain(|t| *t != track_uid);
+        }
+    }
+
+    pub fn is_track_solo_safe(&self, track_uid: TrackUid) -> bool {
+        self.track_solo_safe
+            .get(&track_uid)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// A solo-safe track stays audible when other tracks are soloed. This is
+    /// usually what you want for aux tracks, so that a soloed track is still
+    /// heard through its effect returns.
+    pub fn set_track_solo_safe(&mut self, track_uid: TrackUid, is_solo_safe: bool) {
+        self.track_solo_safe.insert(track_uid, is_solo_safe);
+    }
+
+    /// Whether the track should be heard, considering mute, solo, and
+    /// solo-safe.
+    pub fn is_track_audible(&self, track_uid: TrackUid) -> bool {
+        !self.is_track_muted(track_uid)
+            && (self.solo_tracks.is_empty()
+                || self.is_track_soloed(track_uid)
+                || self.is_track_solo_safe(track_uid))
+    }
+}
+// Each track's pan is a parameter. See [Mixer::pan_control_index()].
+impl Controllable for Mixer {
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        name.strip_prefix("pan-")
+            .and_then(|track_uid| track_uid.parse().ok())
+            .map(|track_uid| Self::pan_control_index(TrackUid(track_uid)))
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        Some(format!("pan-{}", index.0))
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        self.set_track_pan(TrackUid(index.0), value.into());
     }
 }
 
@@ -309,6 +1166,7 @@ impl Mixer {
 mod tests {
     use super::*;
     use crate::{cores::effects::TestEffectNegatesInputCore, entities::TestInstrument};
+    use float_cmp::approx_eq;
 
     #[test]
     fn orchestrator_mainline() {
@@ -365,6 +1223,15 @@ mod tests {
             1,
             "Adding a new send route with a new amount should replace the prior one"
         );
+        assert_eq!(
+            station.sends_for_track(&TrackUid(7)).unwrap().len(),
+            1,
+            "Adding a new send route with a new amount should replace the prior one"
+        );
+        assert_eq!(
+            station.sends_for_track(&TrackUid(7)).unwrap()[0].amount,
+            Normal::from(0.7)
+        );
 
         station.remove_send(TrackUid(7), TrackUid(13));
         assert_eq!(
@@ -404,6 +1271,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn bus_station_rejects_cycles() {
+        let mut station = BusStation::default();
+        assert!(station
+            .add_send(TrackUid(1), TrackUid(1), Normal::from(0.5))
+            .is_err());
+        assert!(station
+            .add_send(TrackUid(1), TrackUid(2), Normal::from(0.5))
+            .is_ok());
+        assert!(station
+            .add_send(TrackUid(2), TrackUid(3), Normal::from(0.5))
+            .is_ok());
+        assert!(station.sends_reach(TrackUid(1), TrackUid(3)));
+        assert!(!station.sends_reach(TrackUid(3), TrackUid(1)));
+        assert!(
+            station
+                .add_send(TrackUid(3), TrackUid(1), Normal::from(0.5))
+                .is_err(),
+            "A send that leads back to its source should be rejected"
+        );
+        assert!(station.sends_for_track(&TrackUid(3)).is_none());
+
+        assert!(station
+            .set_send_tap(TrackUid(1), TrackUid(2), SendTap::PreFader)
+            .is_ok());
+        assert!(station
+            .set_send_pan(TrackUid(1), TrackUid(3), BipolarNormal::from(0.5))
+            .is_err());
+    }
+
     #[test]
     fn humidifier_lookups_work() {
         let mut wd = Humidifier::default();
@@ -484,11 +1381,106 @@ mod tests {
         mixer.set_solo_track(None);
         assert_eq!(mixer.solo_track(), None);
 
+        mixer.set_track_solo(track_1, true);
+        mixer.set_track_solo(track_2, true);
+        assert_eq!(mixer.solo_tracks(), &[track_1, track_2]);
+        mixer.set_track_solo(track_1, false);
+        assert_eq!(mixer.solo_track(), Some(track_2));
+        mixer.set_solo_track(Some(track_1));
+        assert_eq!(
+            mixer.solo_tracks(),
+            &[track_1],
+            "set_solo_track() should be exclusive"
+        );
+        mixer.set_solo_track(None);
+
+        // Older projects saved a single solo track.
+        let mut value = serde_json::to_value(&mixer).unwrap();
+        let map = value.as_object_mut().unwrap();
+        map.remove("solo-tracks");
+        map.insert(
+            "solo-track".to_string(),
+            serde_json::to_value(track_2).unwrap(),
+        );
+        let old_mixer: Mixer = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(old_mixer.solo_tracks(), &[track_2]);
+        value["solo-track"] = serde_json::Value::Null;
+        let old_mixer: Mixer = serde_json::from_value(value).unwrap();
+        assert!(old_mixer.solo_tracks().is_empty());
+
         assert_eq!(mixer.track_output(track_1), Normal::maximum());
         assert_eq!(mixer.track_output(track_2), Normal::maximum());
 
         mixer.set_track_output(track_2, Normal::from(0.5));
         assert_eq!(mixer.track_output(track_1), Normal::maximum());
         assert_eq!(mixer.track_output(track_2), Normal::from(0.5));
+
+        assert_eq!(mixer.track_pan(track_1), BipolarNormal::default());
+        assert_eq!(
+            mixer.pan_gains(track_1),
+            (1.0, 1.0),
+            "A centered track should be unchanged"
+        );
+        mixer.control_set_param_by_index(Mixer::pan_control_index(track_2), ControlValue::MIN);
+        assert_eq!(mixer.track_pan(track_2), BipolarNormal::from(-1.0));
+        assert_eq!(mixer.pan_gains(track_2), (1.0, 0.0));
+        assert_eq!(
+            mixer.control_index_for_name("pan-2"),
+            Some(Mixer::pan_control_index(track_2))
+        );
+    }
+
+    #[test]
+    fn mixer_audibility() {
+        let mut mixer = Mixer::default();
+        let track_1 = TrackUid(1);
+        let track_2 = TrackUid(2);
+        let track_3 = TrackUid(3);
+        let aux = TrackUid(4);
+        mixer.set_track_solo_safe(aux, true);
+
+        assert!([track_1, track_2, track_3, aux]
+            .iter()
+            .all(|t| mixer.is_track_audible(*t)));
+
+        mixer.set_track_solo(track_1, true);
+        mixer.set_track_solo(track_2, true);
+        assert!(mixer.is_track_audible(track_1));
+        assert!(mixer.is_track_audible(track_2));
+        assert!(!mixer.is_track_audible(track_3));
+        assert!(
+            mixer.is_track_audible(aux),
+            "A solo-safe track should stay audible during a solo"
+        );
+
+        mixer.mute_track(aux, true);
+        mixer.mute_track(track_1, true);
+        assert!(!mixer.is_track_audible(aux), "Mute should beat solo-safe");
+        assert!(!mixer.is_track_audible(track_1), "Mute should beat solo");
+    }
+
+    #[test]
+    fn pan_laws() {
+        let center = BipolarNormal::default();
+        let left = BipolarNormal::from(-1.0);
+        let right = BipolarNormal::from(1.0);
+
+        assert_eq!(PanLaw::Balance.gains(center), (1.0, 1.0));
+        assert_eq!(PanLaw::Balance.gains(left), (1.0, 0.0));
+        assert_eq!(PanLaw::Balance.gains(BipolarNormal::from(0.5)), (0.5, 1.0));
+
+        assert_eq!(PanLaw::Linear.gains(center), (0.5, 0.5));
+        assert_eq!(PanLaw::Linear.gains(right), (0.0, 1.0));
+
+        let (l, r) = PanLaw::ConstantPower.gains(center);
+        assert!(approx_eq!(f64, l, core::f64::consts::FRAC_1_SQRT_2));
+        assert!(approx_eq!(f64, r, core::f64::consts::FRAC_1_SQRT_2));
+        for pan in [-1.0, -0.3, 0.0, 0.6, 1.0] {
+            let (l, r) = PanLaw::ConstantPower.gains(BipolarNormal::from(pan));
+            assert!(
+                approx_eq!(f64, l * l + r * r, 1.0, epsilon = 0.000001),
+                "constant power should stay constant at pan {pan}"
+            );
+        }
     }
 }
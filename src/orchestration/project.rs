@@ -7,9 +7,13 @@ use crate::{
     automation::Automator,
     composition::Composer,
     egui::TargetInstrument,
-    orchestration::{MidiRouter, Orchestrator, TrackTitle},
+    orchestration::{
+        wav::WavSampleWriter, AudioClip, AudioClipUid, LoudnessMeter, MeterReadings, MidiRouter,
+        Orchestrator, PanLaw, ProjectHistory, RenderProgress, SendTap, StemExportOptions,
+        TrackTitle, WavExportOptions,
+    },
     prelude::*,
-    types::{ColorScheme, VisualizationQueue},
+    types::{ColorScheme, MeterReadingsQueue, VisualizationQueue},
     util::SelectionSet,
 };
 use anyhow::{anyhow, Result};
@@ -17,7 +21,7 @@ use ensnare::{prelude::*, util::Rng};
 //use crossbeam_channel::Sender;
 use delegate::delegate;
 use derivative::Derivative;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -64,7 +68,11 @@ pub struct ProjectViewState {
     /// Which widget to render in the track arrangement section.
     #[serde(default)]
     pub track_view_mode: FxHashMap<TrackUid, TrackViewMode>,
-    /// The current playback point. This is redundant -- copied from Transport.
+    /// Group tracks whose tracks are hidden.
+    #[serde(default)]
+    pub folded_groups: FxHashSet<TrackUid>,
+    /// The current playback point. This is redundant -- copied from
+    /// [Project::current_time()].
     pub cursor: Option<MusicalTime>,
 }
 
@@ -113,6 +121,13 @@ pub struct ProjectEphemerals {
     /// generated audio frames.
     pub visualization_queue: Option<VisualizationQueue>,
 
+    /// A non-owned slot for the latest track and master meter readings.
+    pub meter_readings_queue: Option<MeterReadingsQueue>,
+
+    /// How many frames have been generated since the meter readings were last
+    /// published.
+    frames_since_meter_readings: usize,
+
     /// The random source for all entropic behavior that should be consistenly
     /// replayable. Example: a noise generator or a random arpeggiator should
     /// use this source because a the project should render the same way each
@@ -128,6 +143,17 @@ pub struct ProjectEphemerals {
     /// tell the ArrangementWidget that it should select that arrangement.
     pub(crate) new_arrangement_track_uid: Option<TrackUid>,
     pub(crate) new_arrangement_arrangement_uid: Option<ArrangementUid>,
+
+    /// The playback position, in beats. It advances each slice of time at the
+    /// tempo in effect, so that tempo changes don't move it.
+    position: f64,
+
+    /// If the [TempoMap] has changed the tempo that entities see, then this is
+    /// that tempo.
+    tempo_in_effect: Option<Tempo>,
+
+    /// Snapshots for undo and redo.
+    pub(crate) history: ProjectHistory,
 }
 
 /// A musical piece. Also knows how to render the piece to digital audio.
@@ -149,6 +175,20 @@ pub struct Project {
     pub rng_seed: u128,
 
     pub transport: Transport,
+    /// Tempo changes over the timeline. The [Transport]'s tempo applies until
+    /// the first one.
+    #[serde(default)]
+    pub tempo_map: TempoMap,
+    /// Meter changes over the timeline. The [Transport]'s time signature
+    /// applies until the first one.
+    #[serde(default)]
+    pub time_signature_map: TimeSignatureMap,
+    /// The region that playback repeats while looping is enabled.
+    #[serde(default)]
+    loop_range: TimeRange,
+    /// Whether playback repeats the loop range.
+    #[serde(default)]
+    is_loop_enabled: bool,
     pub orchestrator: Orchestrator,
     pub automator: Automator,
     pub composer: Composer,
@@ -171,6 +211,7 @@ impl Projects for Project {
     fn delete_track(&mut self, uid: TrackUid) -> Result<()> {
         self.track_to_midi_router.remove(&uid);
         self.orchestrator.aux_track_uids.retain(|t| *t != uid);
+        self.view_state.folded_groups.remove(&uid);
         self.orchestrator.delete_track(uid)
     }
 
@@ -225,18 +266,36 @@ impl Projects for Project {
         mut midi_events_fn: Option<&mut MidiMessagesFn>,
     ) {
         let is_finished_at_start = self.e.is_finished;
-        let time_range = self.transport.advance(frames.len());
-        self.update_time_range(&time_range);
-        self.work(&mut |e| {
-            if let Some( midi_events_fn) = midi_events_fn.as_mut() {
+        let (time_range, wrapped_time_range) = self.advance_position(frames.len());
+        let mut work_fn = |e: WorkEvent| {
+            if let Some(midi_events_fn) = midi_events_fn.as_mut() {
                 match e {
                     WorkEvent::Midi(channel, message) => midi_events_fn(channel,message),
                     WorkEvent::MidiForTrack(_track, channel, message) => midi_events_fn(channel,message),
                     WorkEvent::Control(_control_value) => panic!("generate_frames() received WorkEvent::Control, which should be handled elsewhere"),
                 }
             }
-        });
-        if !is_finished_at_start && self.e.is_finished {
+        };
+        self.orchestrator
+            .audio_clip_repo
+            .update_timeline(&self.tempo_map, self.tempo());
+        self.update_time_range(&time_range);
+        self.work(&mut work_fn);
+        if let Some(wrapped_time_range) = wrapped_time_range.as_ref() {
+            // Notes that were sounding at the end of the loop won't see their
+            // note-offs, which are beyond it.
+            self.track_to_midi_router
+                .values_mut()
+                .for_each(|router| router.all_notes_off(&mut self.orchestrator.entity_repo));
+            self.update_time_range(wrapped_time_range);
+            self.work(&mut work_fn);
+            // Audio clips render the whole buffer at once, so they need to know
+            // where in it the loop starts over.
+            self.orchestrator
+                .audio_clip_repo
+                .update_time_ranges(&time_range, Some(wrapped_time_range));
+        }
+        if !is_finished_at_start && self.e.is_finished && self.loop_range().is_none() {
             self.stop();
         }
         self.generate(frames);
@@ -297,17 +356,31 @@ impl Projects for Project {
                     });
                 }
             }
+            self.e.frames_since_meter_readings += to_generate;
+            if self.e.frames_since_meter_readings
+                >= self.sample_rate().0 / Self::METER_READINGS_PER_SECOND
+            {
+                self.e.frames_since_meter_readings = 0;
+                self.publish_meter_readings();
+            }
             remaining -= to_generate;
         }
     }
 }
 impl Project {
+    /// How often playback publishes meter readings. The UI can't show them
+    /// any faster than it repaints.
+    const METER_READINGS_PER_SECOND: usize = 30;
+
     /// The fixed [Uid] for the project's Orchestrator.
     pub const ORCHESTRATOR_UID: Uid = Uid(1);
 
     /// The fixed [Uid] for the project's [Transport].
     pub const TRANSPORT_UID: Uid = Uid(2);
 
+    /// The fixed [Uid] for the project's [Mixer](crate::orchestration::Mixer).
+    pub const MIXER_UID: Uid = Uid(3);
+
     delegate! {
         to self.orchestrator {
             pub fn get_humidity(&self, uid: &Uid) -> Normal;
@@ -315,9 +388,41 @@ impl Project {
 
             pub fn track_output(&mut self, track_uid: TrackUid) -> Normal;
             pub fn set_track_output(&mut self, track_uid: TrackUid, output: Normal);
+            pub fn track_pan(&self, track_uid: TrackUid) -> BipolarNormal;
+            pub fn set_track_pan(&mut self, track_uid: TrackUid, pan: BipolarNormal);
+            pub fn pan_law(&self) -> PanLaw;
+            pub fn set_pan_law(&mut self, pan_law: PanLaw);
+
+            pub fn solo_tracks(&self) -> &[TrackUid];
+            pub fn is_track_soloed(&self, track_uid: TrackUid) -> bool;
+            pub fn set_track_solo(&mut self, track_uid: TrackUid, should_solo: bool);
+            pub fn is_track_solo_safe(&self, track_uid: TrackUid) -> bool;
+            pub fn set_track_solo_safe(&mut self, track_uid: TrackUid, is_solo_safe: bool);
+            pub fn is_track_audible(&self, track_uid: TrackUid) -> bool;
+
+            pub fn is_group_track(&self, track_uid: TrackUid) -> bool;
+            pub fn track_group(&self, track_uid: TrackUid) -> Option<TrackUid>;
+            pub fn group_children(&self, group_uid: TrackUid) -> Vec<TrackUid>;
+            pub fn set_track_group(&mut self, track_uid: TrackUid, group_uid: Option<TrackUid>) -> Result<()>;
 
             pub fn add_send(&mut self, src_uid: TrackUid, dst_uid: TrackUid, amount: Normal) -> anyhow::Result<()>;
+            pub fn set_send_tap(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid, tap: SendTap) -> Result<()>;
+            pub fn set_send_pan(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid, pan: BipolarNormal) -> Result<()>;
             pub fn remove_send(&mut self, send_track_uid: TrackUid, aux_track_uid: TrackUid);
+
+            pub fn add_sidechain(&mut self, effect_uid: Uid, key_track_uid: TrackUid) -> Result<()>;
+            pub fn remove_sidechain(&mut self, effect_uid: Uid) -> Option<TrackUid>;
+            pub fn sidechain(&self, effect_uid: Uid) -> Option<TrackUid>;
+
+            pub fn add_audio_clip(&mut self, track_uid: TrackUid, clip: AudioClip) -> Result<AudioClipUid>;
+            pub fn remove_audio_clip(&mut self, clip_uid: AudioClipUid) -> Result<AudioClip>;
+            pub fn audio_clip_uids(&self, track_uid: TrackUid) -> &[AudioClipUid];
+
+            pub fn render_thread_count(&self) -> usize;
+            pub fn set_render_thread_count(&mut self, count: Option<usize>);
+
+            pub fn meter_readings(&self) -> MeterReadings;
+            pub fn reset_meters(&mut self);
         }
         to self.composer {
             pub fn add_pattern(&mut self, contents: Pattern, pattern_uid: Option<PatternUid>) -> Result<PatternUid>;
@@ -396,9 +501,50 @@ impl Project {
         self.track_titles
             .insert(track_uid, TrackTitle(format!("Aux {}", track_uid)));
         self.orchestrator.aux_track_uids.push(track_uid);
+
+        // Effect returns should still be heard when their sources are soloed.
+        self.orchestrator.set_track_solo_safe(track_uid, true);
+        Ok(track_uid)
+    }
+
+    /// Adds a new group track, which sums the output of the tracks put in it
+    /// with [Project::set_track_group()] and runs the result through its own
+    /// effects and fader. Returns the new track's [TrackUid] if successful.
+    pub fn new_group_track(&mut self) -> anyhow::Result<TrackUid> {
+        let track_uid = self.create_track()?;
+        self.track_titles
+            .insert(track_uid, TrackTitle(format!("Group {}", track_uid)));
+        self.orchestrator.group_track_uids.push(track_uid);
         Ok(track_uid)
     }
 
+    /// Whether the group's tracks are hidden in the UI.
+    pub fn is_group_folded(&self, group_uid: TrackUid) -> bool {
+        self.view_state.folded_groups.contains(&group_uid)
+    }
+
+    /// Hides or shows the group's tracks in the UI.
+    pub fn set_group_folded(&mut self, group_uid: TrackUid, is_folded: bool) {
+        if is_folded {
+            self.view_state.folded_groups.insert(group_uid);
+        } else {
+            self.view_state.folded_groups.remove(&group_uid);
+        }
+    }
+
+    /// Whether the track is hidden in the UI because a group that contains
+    /// it, directly or otherwise, is folded.
+    pub fn is_track_folded_away(&self, track_uid: TrackUid) -> bool {
+        let mut group_uid = self.track_group(track_uid);
+        while let Some(uid) = group_uid {
+            if self.is_group_folded(uid) {
+                return true;
+            }
+            group_uid = self.track_group(uid);
+        }
+        false
+    }
+
     pub fn get_midi_receiver_channel(&mut self, entity_uid: Uid) -> Option<MidiChannel> {
         if let Some(track_uid) = self.orchestrator.track_for_entity(entity_uid) {
             if let Some(midi_router) = self.track_to_midi_router.get_mut(&track_uid) {
@@ -428,30 +574,356 @@ impl Project {
         }
     }
 
+    /// The current playback position, which follows the project's [TempoMap]
+    /// and loop range. The [Transport] holds the base tempo, time signature,
+    /// and whether the project is playing, but its own position stays put.
+    pub fn current_time(&self) -> MusicalTime {
+        TempoMap::beats_as_time(self.e.position)
+    }
+
+    /// The tempo in effect at the given time, according to the project's
+    /// [TempoMap].
+    pub fn tempo_at(&self, time: MusicalTime) -> Tempo {
+        self.tempo_map.tempo_at(self.tempo(), time)
+    }
+
+    /// The time signature in effect at the given time, according to the
+    /// project's [TimeSignatureMap].
+    pub fn time_signature_at(&self, time: MusicalTime) -> TimeSignature {
+        self.time_signature_map
+            .time_signature_at(&self.time_signature(), time)
+    }
+
+    /// The zero-based bar that contains the given time.
+    pub fn bar_at(&self, time: MusicalTime) -> usize {
+        self.time_signature_map.bar_at(&self.time_signature(), time)
+    }
+
+    /// When the given zero-based bar starts.
+    pub fn bar_start(&self, bar: usize) -> MusicalTime {
+        self.time_signature_map
+            .bar_start(&self.time_signature(), bar)
+    }
+
+    /// The start of the bar closest to the given time, taking meter changes
+    /// into account.
+    pub fn quantized_to_measure(&self, time: MusicalTime) -> MusicalTime {
+        self.time_signature_map
+            .quantized_to_measure(&self.time_signature(), time)
+    }
+
+    /// The region that playback repeats, if looping is enabled and the region
+    /// isn't empty.
+    pub fn loop_range(&self) -> Option<&TimeRange> {
+        if self.is_loop_enabled && !self.loop_range.0.is_empty() {
+            Some(&self.loop_range)
+        } else {
+            None
+        }
+    }
+
+    /// Sets the region that playback repeats while looping is enabled.
+    pub fn set_loop_range(&mut self, loop_range: TimeRange) -> Result<()> {
+        if loop_range.0.is_empty() {
+            return Err(anyhow!("Loop range {:?} is empty", loop_range.0));
+        }
+        self.loop_range = loop_range;
+        Ok(())
+    }
+
+    /// Whether playback repeats the loop range.
+    pub fn is_loop_enabled(&self) -> bool {
+        self.is_loop_enabled
+    }
+
+    /// Turns looping on or off. Playback that has already passed the end of
+    /// the loop range keeps going.
+    pub fn set_loop_enabled(&mut self, is_loop_enabled: bool) {
+        self.is_loop_enabled = is_loop_enabled;
+    }
+
+    // Moves playback forward by the given number of frames, and returns the
+    // range of time that those frames cover. If the project isn't performing,
+    // then the position doesn't move, but the range still gives entities the
+    // appearance of time moving forward, just as Transport::advance() does.
+    //
+    // The position moves at the tempo in effect as it goes, rather than being
+    // recomputed from the start of the piece, so a change to the tempo or the
+    // tempo map takes effect from here on without making the position jump.
+    // The Transport assumes a constant tempo and knows nothing of loops, so it
+    // isn't advanced, and this position is the only one.
+    //
+    // If playback reached the end of the loop range, then the first range ends
+    // there, and the second covers the rest of the frames from the loop's
+    // start.
+    fn advance_position(&mut self, frame_count: usize) -> (TimeRange, Option<TimeRange>) {
+        let tempo = self.tempo();
+        let seconds = Seconds(frame_count as f64 / self.sample_rate().0 as f64);
+        let start_beat = self.e.position;
+        let end_beat = self.tempo_map.beats_after(tempo, start_beat, seconds);
+        let start = TempoMap::beats_as_time(start_beat);
+        let end = TempoMap::beats_as_time(end_beat).max(start);
+        self.set_tempo_in_effect(if self.tempo_map.is_empty() {
+            None
+        } else {
+            Some(self.tempo_at(start))
+        });
+        if !self.is_performing() {
+            return (TimeRange(start..end), None);
+        }
+        if let Some(loop_range) = self.loop_range().cloned() {
+            let loop_start_beat = TempoMap::time_as_beats(loop_range.0.start);
+            let loop_end_beat = TempoMap::time_as_beats(loop_range.0.end);
+            if start_beat < loop_end_beat && end_beat >= loop_end_beat {
+                let seconds_to_loop_end =
+                    self.tempo_map
+                        .seconds_between(tempo, start_beat, loop_end_beat);
+                let wrapped_beat = self
+                    .tempo_map
+                    .beats_after(
+                        tempo,
+                        loop_start_beat,
+                        Seconds(seconds.0 - seconds_to_loop_end.0),
+                    )
+                    .min(loop_end_beat);
+                self.e.position = wrapped_beat;
+                return (
+                    TimeRange(start..loop_range.0.end),
+                    Some(TimeRange(
+                        loop_range.0.start..TempoMap::beats_as_time(wrapped_beat),
+                    )),
+                );
+            }
+        }
+        self.e.position = end_beat;
+        (TimeRange(start..end), None)
+    }
+
+    // Tells entities about a tempo other than the Transport's, or (with None)
+    // restores the Transport's.
+    fn set_tempo_in_effect(&mut self, tempo: Option<Tempo>) {
+        if tempo == self.e.tempo_in_effect {
+            return;
+        }
+        self.e.tempo_in_effect = tempo;
+        let tempo = tempo.unwrap_or(self.tempo());
+        self.orchestrator.update_tempo(tempo);
+        self.composer.update_tempo(tempo);
+    }
+
     fn update_is_finished(&mut self) {
         self.e.is_finished = self.composer.is_finished() && self.orchestrator.is_finished();
     }
 
-    /// Renders the project as a WAV file to the specified path.
+    /// Renders the project as a 16-bit WAV file to the specified path.
     pub fn export_to_wav(&mut self, path: PathBuf) -> anyhow::Result<()> {
-        let spec = hound::WavSpec {
-            channels: 2,
-            sample_rate: self.sample_rate().into(),
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
+        self.export_to_wav_with(path, &WavExportOptions::default())
+    }
+
+    /// Renders the project as a WAV file to the specified path, in the format
+    /// that the [WavExportOptions] describe.
+    pub fn export_to_wav_with(
+        &mut self,
+        path: PathBuf,
+        options: &WavExportOptions,
+    ) -> anyhow::Result<()> {
+        self.write_wav(path, options, &RenderProgress::default())
+    }
+
+    // Does the work of export_to_wav_with(), keeping the RenderProgress up to
+    // date. If the render is cancelled, the file is left incomplete.
+    pub(super) fn write_wav(
+        &mut self,
+        path: PathBuf,
+        options: &WavExportOptions,
+        progress: &RenderProgress,
+    ) -> anyhow::Result<()> {
+        let pass_count = if options.normalization.is_some() {
+            2
+        } else {
+            1
+        };
+        progress.set_frames_total(pass_count * self.estimated_frame_count());
+        let gain = match options.normalization {
+            Some(normalization) => {
+                let mut peak: f64 = 0.0;
+                let mut loudness_meter = LoudnessMeter::new_with(self.sample_rate());
+                self.render_piece(progress, &mut |frame| {
+                    peak = peak.max(frame.0 .0.abs()).max(frame.1 .0.abs());
+                    loudness_meter.process(&[frame]);
+                    Ok(())
+                })?;
+                if progress.is_cancelled() {
+                    return Ok(());
+                }
+                normalization.gain_for(peak, loudness_meter.reading().integrated)
+            }
+            None => 1.0,
         };
-        let mut writer = hound::WavWriter::create(path, spec)?;
+
+        let mut writer = WavSampleWriter::create(
+            &path,
+            self.sample_rate(),
+            options.sample_format,
+            options.dither,
+            self.rng_seed,
+        )?;
+        writer.set_gain(gain);
+        self.render_piece(progress, &mut |frame| writer.write(&frame))?;
+        writer.finalize()?;
+
+        // The meters now describe the whole export, including whether it
+        // clipped.
+        self.publish_meter_readings();
+
+        Ok(())
+    }
+
+    /// Plays the project from its current position until it finishes, one
+    /// frame at a time. Looping is turned off until the renderer is dropped,
+    /// because a looping project never finishes. Unlike [Projects::render()],
+    /// this knows about the loop range.
+    pub fn render_piece_frames(&mut self) -> PieceRenderer {
+        PieceRenderer::new_with(self)
+    }
+
+    // Renders the whole piece from the start, handing each frame to frame_fn,
+    // until it's done or the render is cancelled.
+    fn render_piece(
+        &mut self,
+        progress: &RenderProgress,
+        frame_fn: &mut dyn FnMut(StereoSample) -> Result<(), hound::Error>,
+    ) -> Result<(), hound::Error> {
+        // Progress is reported, and cancellation noticed, in batches of frames
+        // rather than on every one.
+        const PROGRESS_INTERVAL: usize = 1024;
 
         self.skip_to_start();
 
-        let mut renderer = self.render();
+        let mut result = Ok(());
+        let mut frames_since_progress = 0;
+        let mut renderer = self.render_piece_frames();
         while let Some(frame) = renderer.next() {
-            let (left, right) = frame.into_i16();
-            let _ = writer.write_sample(left);
-            let _ = writer.write_sample(right);
+            result = frame_fn(frame);
+            if result.is_err() {
+                break;
+            }
+            frames_since_progress += 1;
+            if frames_since_progress == PROGRESS_INTERVAL {
+                progress.advance(frames_since_progress);
+                frames_since_progress = 0;
+                if progress.is_cancelled() {
+                    break;
+                }
+            }
         }
+        progress.advance(frames_since_progress);
+        result
+    }
 
-        Ok(())
+    /// Renders each track chosen by the [StemExportOptions] to its own WAV file
+    /// in the specified directory, all in one pass. Files are named after
+    /// their tracks' positions and titles. Returns the paths of the files.
+    pub fn export_stems_to_wav(
+        &mut self,
+        dir: PathBuf,
+        options: &StemExportOptions,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        self.write_stems(dir, options, &RenderProgress::default())
+    }
+
+    // Does the work of export_stems_to_wav(), keeping the RenderProgress up to
+    // date. If the render is cancelled, the files are left incomplete.
+    pub(super) fn write_stems(
+        &mut self,
+        dir: PathBuf,
+        options: &StemExportOptions,
+        progress: &RenderProgress,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let track_uids = self.orchestrator.stem_track_uids(options);
+        if track_uids.is_empty() {
+            return Err(anyhow!("There are no tracks to export"));
+        }
+        std::fs::create_dir_all(&dir)?;
+        let mut paths = Vec::default();
+        let mut writers = Vec::default();
+        for (index, track_uid) in track_uids.iter().enumerate() {
+            let title = self
+                .track_titles
+                .get(track_uid)
+                .map(|title| title.0.clone())
+                .unwrap_or_else(|| format!("Track {track_uid}"));
+            let path = dir.join(format!("{:02}-{}.wav", index + 1, Self::file_stem(&title)));
+            // Each stem gets its own dither noise so that the noise doesn't
+            // build up when the stems are mixed back together.
+            let writer = WavSampleWriter::create(
+                &path,
+                self.sample_rate(),
+                options.sample_format,
+                options.dither,
+                self.rng_seed.wrapping_add(index as u128 + 1),
+            )?;
+            writers.push((*track_uid, writer));
+            paths.push(path);
+        }
+
+        progress.set_frames_total(self.estimated_frame_count());
+        self.skip_to_start();
+        self.orchestrator
+            .start_stem_capture(&track_uids, options.is_post_fader);
+
+        let mut result = Ok(());
+        let mut renderer = self.render_piece_frames();
+        while !progress.is_cancelled() && result.is_ok() {
+            let Some(frame_count) = renderer.render_slice() else {
+                break;
+            };
+            progress.advance(frame_count);
+            let orchestrator = &renderer.project().orchestrator;
+            result = writers.iter_mut().try_for_each(|(track_uid, writer)| {
+                if let Some(stem) = orchestrator.captured_stem(*track_uid) {
+                    for frame in stem {
+                        writer.write(frame)?;
+                    }
+                }
+                Ok::<(), hound::Error>(())
+            });
+        }
+        drop(renderer);
+        self.orchestrator.stop_stem_capture();
+        result?;
+
+        for (_, writer) in writers {
+            writer.finalize()?;
+        }
+        Ok(paths)
+    }
+
+    // Turns a track title into something safe to use in a filename.
+    fn file_stem(title: &str) -> String {
+        title
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Copies the orchestrator's latest meter readings to the
+    /// [MeterReadingsQueue], if there is one. If the UI is reading them right
+    /// now, this skips them rather than wait for it.
+    fn publish_meter_readings(&self) {
+        if let Some(queue) = self.e.meter_readings_queue.as_ref() {
+            if let Ok(mut readings) = queue.0.try_write() {
+                self.orchestrator.update_meter_readings(&mut readings);
+            }
+        }
     }
 
     fn dispatch_control_event(&mut self, source: ControlLinkSource, value: ControlValue) {
@@ -459,6 +931,10 @@ impl Project {
             &mut self.orchestrator.entity_repo,
             Some(&mut |link| match link.uid {
                 Self::TRANSPORT_UID => self.transport.control_set_param_by_index(link.param, value),
+                Self::MIXER_UID => self
+                    .orchestrator
+                    .mixer
+                    .control_set_param_by_index(link.param, value),
                 _ => {
                     eprintln!("Asked to route from unknown source {source}");
                 }
@@ -752,6 +1228,63 @@ impl Project {
         self.e.audio_sender_fn = Some(audio_sender_fn);
     }
 }
+/// Renders a [Project] until it finishes. See [Project::render_piece_frames()].
+#[derive(Debug)]
+pub struct PieceRenderer<'a> {
+    project: &'a mut Project,
+    // Whether looping was on before the render started.
+    was_loop_enabled: bool,
+    buffer: [StereoSample; 64],
+    // The next frame of the buffer to hand out.
+    position: usize,
+}
+impl<'a> PieceRenderer<'a> {
+    fn new_with(project: &'a mut Project) -> Self {
+        let was_loop_enabled = project.is_loop_enabled;
+        project.is_loop_enabled = false;
+        project.play();
+        Self {
+            project,
+            was_loop_enabled,
+            buffer: [StereoSample::SILENCE; 64],
+            position: 64,
+        }
+    }
+
+    // Generates the next slice of the piece and returns how many frames it
+    // holds, or None if the piece is over.
+    fn render_slice(&mut self) -> Option<usize> {
+        if !self.project.is_performing() || self.project.is_finished() {
+            return None;
+        }
+        self.buffer.fill(StereoSample::SILENCE);
+        self.project.generate_audio(&mut self.buffer, None);
+        self.position = 0;
+        Some(self.buffer.len())
+    }
+
+    fn project(&self) -> &Project {
+        self.project
+    }
+}
+impl<'a> Iterator for PieceRenderer<'a> {
+    type Item = StereoSample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.buffer.len() {
+            self.render_slice()?;
+        }
+        let frame = self.buffer[self.position];
+        self.position += 1;
+        Some(frame)
+    }
+}
+impl<'a> Drop for PieceRenderer<'a> {
+    fn drop(&mut self) {
+        self.project.is_loop_enabled = self.was_loop_enabled;
+    }
+}
+
 impl Generates<StereoSample> for Project {
     delegate! {
         to self.orchestrator {
@@ -769,6 +1302,10 @@ impl Configurable for Project {
         self.transport.update_tempo(tempo);
         self.orchestrator.update_tempo(tempo);
         self.composer.update_tempo(tempo);
+
+        // If the tempo map is in charge, it'll reassert itself on the next
+        // slice of time.
+        self.e.tempo_in_effect = None;
     }
     fn update_time_signature(&mut self, time_signature: TimeSignature) {
         self.transport.update_time_signature(time_signature);
@@ -817,6 +1354,8 @@ impl Controls for Project {
 
     fn skip_to_start(&mut self) {
         self.reset();
+        self.e.position = 0.0;
+        self.set_tempo_in_effect(None);
         self.transport.skip_to_start();
         self.automator.skip_to_start();
         self.orchestrator.skip_to_start();
@@ -897,6 +1436,7 @@ impl Serializable for Project {
     }
 
     fn after_deser(&mut self) {
+        self.tempo_map.after_deser();
         self.automator.after_deser();
         self.orchestrator.after_deser();
         self.composer.after_deser();
@@ -912,11 +1452,12 @@ impl Serializable for Project {
 mod tests {
     use super::*;
     use crate::{
-        cores::instruments::TestAudioSourceCoreBuilder,
+        cores::{effects::CompressorCoreBuilder, instruments::TestAudioSourceCoreBuilder},
         entities::{
-            TestAudioSource, TestControllerAlwaysSendsMidiMessage, TestEffectNegatesInput,
-            TestInstrumentCountsMidiMessages,
+            Compressor, TestAudioSource, TestControllerAlwaysSendsMidiMessage,
+            TestEffectNegatesInput, TestInstrumentCountsMidiMessages,
         },
+        orchestration::{AudioClipBuilder, Mixer, TempoEvent},
         traits::tests::test_trait_configurable,
     };
     use ensnare::traits::Entity;
@@ -946,6 +1487,22 @@ mod tests {
     }
     impl TestEntity for TestControllerSendsOneEvent {}
 
+    // Adds a MIDI track that plays a constant level.
+    fn new_source_track(project: &mut Project, level: ParameterType) -> TrackUid {
+        let track_uid = project.new_midi_track().unwrap();
+        let _ = project.add_entity(
+            track_uid,
+            Box::new(TestAudioSource::new_with(
+                Uid::default(),
+                TestAudioSourceCoreBuilder::default()
+                    .level(level)
+                    .build()
+                    .unwrap(),
+            )),
+        );
+        track_uid
+    }
+
     #[test]
     fn project_basics() {
         let mut project = Project::default();
@@ -1093,147 +1650,322 @@ mod tests {
     }
 
     #[test]
-    fn midi_routing_from_external_reaches_instruments() {
-        let mut project = Project::default();
-        let track_uid = project.new_midi_track().unwrap();
-
-        let instrument = TestInstrumentCountsMidiMessages::default();
-        let midi_messages_received = Arc::clone(instrument.received_midi_message_count_mutex());
-        let instrument_uid = project.add_entity(track_uid, Box::new(instrument)).unwrap();
-
-        let test_message = MidiMessage::NoteOn {
-            key: 7.into(),
-            vel: 13.into(),
-        };
-        if let Ok(received) = midi_messages_received.lock() {
-            assert_eq!(
-                *received, 0,
-                "Before sending an external MIDI message to Project, count should be zero"
-            );
-        };
-        project.handle_midi_message(
-            MidiChannel::default(),
-            test_message,
-            &mut |channel, message| panic!("Didn't expect {channel:?} {message:?}",),
-        );
-        if let Ok(received) = midi_messages_received.lock() {
-            assert_eq!(
-                *received, 1,
-                "Count should update after sending an external MIDI message to Project"
-            );
-        };
-    }
-
-    #[test]
-    fn midi_messages_from_track_a_do_not_reach_track_b() {
-        let mut project = Project::default();
-        let track_a_uid = project.new_midi_track().unwrap();
-        let track_b_uid = project.new_midi_track().unwrap();
-
-        // On Track 1, put a sender and receiver.
-        let sender_uid = project
-            .add_entity(
-                track_a_uid,
-                Box::new(TestControllerAlwaysSendsMidiMessage::default()),
-            )
-            .unwrap();
-        let receiver_1 = TestInstrumentCountsMidiMessages::default();
-        let counter_1 = Arc::clone(receiver_1.received_midi_message_count_mutex());
-        let receiver_1_uid = project
-            .add_entity(track_a_uid, Box::new(receiver_1))
-            .unwrap();
-
-        // On Track 2, put another receiver.
-        let receiver_2 = TestInstrumentCountsMidiMessages::default();
-        let counter_2 = Arc::clone(receiver_2.received_midi_message_count_mutex());
-        let receiver_2_uid = project
-            .add_entity(track_b_uid, Box::new(receiver_2))
-            .unwrap();
-
-        // We don't need to hook anyone up to MIDI, because add_entity() now
-        // does that for us.
-
-        // Fire everything up.
-        project.play();
-        project.work(&mut |_| {});
-
-        // Sender should have sent a message that receiver #1 should receive,
-        // because they're both in the same Track.
-        if let Ok(c) = counter_1.lock() {
-            assert_eq!(1, *c);
-        }
-        // But Receiver #2 shouldn't see that message, because it's in a
-        // different Track.
-        if let Ok(c) = counter_2.lock() {
-            assert_eq!(0, *c);
-        };
-    }
-
-    #[test]
-    fn sends_send() {
-        const EXPECTED_LEVEL: ParameterType = TestAudioSource::MEDIUM;
+    fn project_handles_mixer_pan_control() {
         let mut project = Project::default();
-        let midi_track_uid = project.new_midi_track().unwrap();
-        let aux_track_uid = project.new_aux_track().unwrap();
 
+        let track_uid = project.create_track().unwrap();
         let _ = project.add_entity(
-            midi_track_uid,
+            track_uid,
             Box::new(TestAudioSource::new_with(
                 Uid::default(),
                 TestAudioSourceCoreBuilder::default()
-                    .level(EXPECTED_LEVEL)
+                    .level(TestAudioSource::MEDIUM)
                     .build()
                     .unwrap(),
             )),
         );
-        let mut samples = [StereoSample::SILENCE; 64];
-        project.generate_audio(&mut samples, None);
-        let expected_sample = StereoSample::from(EXPECTED_LEVEL);
+        let uid = project
+            .add_entity(track_uid, Box::new(TestControllerSendsOneEvent::default()))
+            .unwrap();
         assert!(
-            samples.iter().all(|s| *s == expected_sample),
-            "Without a send, original signal should pass through unchanged."
+            project
+                .link(uid, Project::MIXER_UID, Mixer::pan_control_index(track_uid))
+                .is_ok(),
+            "Linking with a track's pan should work"
         );
 
-        assert!(project
-            .add_send(midi_track_uid, aux_track_uid, Normal::from(0.5))
-            .is_ok());
-        let mut samples = [StereoSample::SILENCE; 64];
+        let mut samples = [StereoSample::SILENCE; 4];
         project.generate_audio(&mut samples, None);
-        let expected_sample = StereoSample::from(0.75);
+        assert_eq!(
+            project.track_pan(track_uid),
+            BipolarNormal::from(1.0),
+            "After a cycle of work, the track should be panned by automation"
+        );
+        let expected_sample = StereoSample(Sample::SILENCE, Sample::from(TestAudioSource::MEDIUM));
         samples.iter().enumerate().for_each(|(index, s)| {
-            assert_eq!(*s, expected_sample, "With a 50% send to an aux track with no effects, we should see the original MEDIUM=0.5 plus 50% of it = 0.75, but at sample #{index} we got {:?}", s);
+            assert_eq!(*s, expected_sample, "A hard-right track should be silent on the left, but at sample #{index} we got {s:?}");
         });
 
-        // Add an effect to the aux track.
-        let _ = project.add_entity(aux_track_uid, Box::new(TestEffectNegatesInput::default()));
-
-        let mut samples = [StereoSample::SILENCE; 64];
+        project.set_pan_law(PanLaw::Linear);
+        project.set_track_pan(track_uid, BipolarNormal::default());
+        let mut samples = [StereoSample::SILENCE; 4];
         project.generate_audio(&mut samples, None);
-        let expected_sample = StereoSample::from(0.5 + 0.5 * 0.5 * -1.0);
-        samples.iter().enumerate().for_each(|(index,s)| {
-            assert_eq!(*s ,expected_sample, "With a 50% send to an aux with a negating effect, we should see the original 0.5 plus a negation of 50% of 0.5 = 0.250, but at sample #{index} we got {:?}", s);
+        let expected_sample = StereoSample::from(TestAudioSource::MEDIUM * 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "A centered track under the linear law should be at half level, but at sample #{index} we got {s:?}");
         });
     }
 
     #[test]
-    fn mixer_works() {
-        const EXPECTED_LEVEL: ParameterType = TestAudioSource::MEDIUM;
+    fn project_follows_tempo_map() {
         let mut project = Project::default();
-        let track_1_uid = project.new_midi_track().unwrap();
-        let track_2_uid = project.new_midi_track().unwrap();
+        project.update_sample_rate(SampleRate::from(1000));
+        project.update_tempo(Tempo(60.0));
+        assert!(project
+            .tempo_map
+            .add_event(TempoEvent::new_step(
+                MusicalTime::new_with_beats(2),
+                Tempo(120.0)
+            ))
+            .is_ok());
 
-        let _ = project.add_entity(
-            track_1_uid,
-            Box::new(TestAudioSource::new_with(
-                Uid::default(),
-                TestAudioSourceCoreBuilder::default()
-                    .level(EXPECTED_LEVEL)
+        project.play();
+        let mut frames = [StereoSample::SILENCE; 1000];
+        project.generate_audio(&mut frames, None);
+        project.generate_audio(&mut frames, None);
+        assert_eq!(
+            project.current_time(),
+            MusicalTime::new_with_beats(2),
+            "two seconds at 60 BPM should be two beats"
+        );
+        project.generate_audio(&mut frames, None);
+        assert_eq!(
+            project.current_time(),
+            MusicalTime::new_with_beats(4),
+            "one more second at 120 BPM should be two more beats"
+        );
+        assert_eq!(
+            project.tempo(),
+            Tempo(60.0),
+            "the tempo map shouldn't change the project's own tempo"
+        );
+        assert_eq!(project.tempo_at(project.current_time()), Tempo(120.0));
+
+        // Changing the tempo before the first tempo change used to move the
+        // playhead, because the position was recomputed from the start.
+        project.update_tempo(Tempo(30.0));
+        assert_eq!(project.current_time(), MusicalTime::new_with_beats(4));
+        project.generate_audio(&mut frames, None);
+        assert_eq!(
+            project.current_time(),
+            MusicalTime::new_with_beats(6),
+            "a tempo change shouldn't make the playhead jump"
+        );
+
+        project.skip_to_start();
+        assert_eq!(project.current_time(), MusicalTime::START);
+    }
+
+    #[test]
+    fn project_plays_audio_clips() {
+        let mut paths = Paths::default();
+        paths.push_hive(Paths::test_data_rel());
+        Paths::set_instance(paths);
+
+        let mut project = Project::default();
+        let track_uid = project.new_audio_track().unwrap();
+        let clip = AudioClipBuilder::default()
+            .source(SampleSource::Path(
+                "square-440Hz-1-second-mono-24-bit-PCM.wav".into(),
+            ))
+            .build()
+            .unwrap();
+        let clip_uid = project.add_audio_clip(track_uid, clip).unwrap();
+
+        let aux_uid = project.new_aux_track().unwrap();
+        let group_uid = project.new_group_track().unwrap();
+        for other_uid in [
+            aux_uid,
+            group_uid,
+            Orchestrator::MASTER_TRACK_UID,
+            TrackUid(9999),
+        ] {
+            let clip = AudioClipBuilder::default()
+                .source(SampleSource::Path(
+                    "square-440Hz-1-second-mono-24-bit-PCM.wav".into(),
+                ))
+                .build()
+                .unwrap();
+            assert!(
+                project.add_audio_clip(other_uid, clip).is_err(),
+                "clips belong only on audio tracks that exist"
+            );
+        }
+
+        // A project file should bring its clips back, and reload their audio.
+        project.before_ser();
+        let json = serde_json::to_string(&project).unwrap();
+        let mut project: Project = serde_json::from_str(&json).unwrap();
+        project.after_deser();
+        assert_eq!(project.audio_clip_uids(track_uid), &[clip_uid]);
+
+        project.play();
+        let mut frames = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut frames, None);
+        assert!(
+            frames.iter().any(|s| *s != StereoSample::SILENCE),
+            "an audio track should play its clips"
+        );
+        assert!(
+            !project.is_finished(),
+            "the project shouldn't finish before its clips do"
+        );
+
+        let _ = project.delete_track(track_uid);
+        assert!(project.orchestrator.audio_clip_repo.clips.is_empty());
+    }
+
+    #[test]
+    fn project_loops_playback() {
+        let mut project = Project::default();
+        project.update_sample_rate(SampleRate::from(1000));
+        project.update_tempo(Tempo(60.0));
+        let track_uid = project.new_midi_track().unwrap();
+        let pattern_uid = project
+            .add_pattern(
+                PatternBuilder::default()
+                    .note(Note::new_with_midi_note(
+                        MidiNote::A4,
+                        MusicalTime::START,
+                        MusicalTime::DURATION_QUARTER,
+                    ))
                     .build()
                     .unwrap(),
-            )),
+                None,
+            )
+            .unwrap();
+        let _ = project
+            .arrange_pattern(track_uid, pattern_uid, None, MusicalTime::ONE_BEAT)
+            .unwrap();
+
+        assert!(project
+            .set_loop_range(TimeRange(MusicalTime::ONE_BEAT..MusicalTime::ONE_BEAT))
+            .is_err());
+        assert!(project
+            .set_loop_range(TimeRange(
+                MusicalTime::ONE_BEAT..MusicalTime::new_with_beats(3)
+            ))
+            .is_ok());
+        assert!(
+            project.loop_range().is_none(),
+            "a loop range shouldn't matter until looping is enabled"
+        );
+        project.set_loop_enabled(true);
+
+        let mut note_on_count = 0;
+        let mut frames = [StereoSample::SILENCE; 500];
+        project.play();
+        for _ in 0..12 {
+            project.generate_audio(
+                &mut frames,
+                Some(&mut |_, message| {
+                    if matches!(message, MidiMessage::NoteOn { .. }) {
+                        note_on_count += 1;
+                    }
+                }),
+            );
+        }
+        assert!(project.is_performing(), "looping playback shouldn't finish");
+        assert_eq!(
+            project.current_time(),
+            MusicalTime::new_with_beats(2),
+            "six seconds at 60 BPM, wrapping every two beats starting at beat one, should end at beat two"
+        );
+        assert_eq!(note_on_count, 3, "the pattern should play on every pass");
+
+        project.set_loop_enabled(false);
+        project.generate_audio(&mut frames, None);
+        assert!(project.current_time() > MusicalTime::new_with_beats(2));
+
+        project.set_loop_enabled(true);
+        project.skip_to_start();
+        let frame_count = project.render_piece_frames().count();
+        assert!(
+            frame_count < 10 * 1000,
+            "an offline render should stop at the end of the piece even when looping is on"
+        );
+        assert!(
+            project.loop_range().is_some(),
+            "rendering should leave looping as it found it"
+        );
+    }
+
+    #[test]
+    fn midi_routing_from_external_reaches_instruments() {
+        let mut project = Project::default();
+        let track_uid = project.new_midi_track().unwrap();
+
+        let instrument = TestInstrumentCountsMidiMessages::default();
+        let midi_messages_received = Arc::clone(instrument.received_midi_message_count_mutex());
+        let instrument_uid = project.add_entity(track_uid, Box::new(instrument)).unwrap();
+
+        let test_message = MidiMessage::NoteOn {
+            key: 7.into(),
+            vel: 13.into(),
+        };
+        if let Ok(received) = midi_messages_received.lock() {
+            assert_eq!(
+                *received, 0,
+                "Before sending an external MIDI message to Project, count should be zero"
+            );
+        };
+        project.handle_midi_message(
+            MidiChannel::default(),
+            test_message,
+            &mut |channel, message| panic!("Didn't expect {channel:?} {message:?}",),
         );
+        if let Ok(received) = midi_messages_received.lock() {
+            assert_eq!(
+                *received, 1,
+                "Count should update after sending an external MIDI message to Project"
+            );
+        };
+    }
+
+    #[test]
+    fn midi_messages_from_track_a_do_not_reach_track_b() {
+        let mut project = Project::default();
+        let track_a_uid = project.new_midi_track().unwrap();
+        let track_b_uid = project.new_midi_track().unwrap();
+
+        // On Track 1, put a sender and receiver.
+        let sender_uid = project
+            .add_entity(
+                track_a_uid,
+                Box::new(TestControllerAlwaysSendsMidiMessage::default()),
+            )
+            .unwrap();
+        let receiver_1 = TestInstrumentCountsMidiMessages::default();
+        let counter_1 = Arc::clone(receiver_1.received_midi_message_count_mutex());
+        let receiver_1_uid = project
+            .add_entity(track_a_uid, Box::new(receiver_1))
+            .unwrap();
+
+        // On Track 2, put another receiver.
+        let receiver_2 = TestInstrumentCountsMidiMessages::default();
+        let counter_2 = Arc::clone(receiver_2.received_midi_message_count_mutex());
+        let receiver_2_uid = project
+            .add_entity(track_b_uid, Box::new(receiver_2))
+            .unwrap();
+
+        // We don't need to hook anyone up to MIDI, because add_entity() now
+        // does that for us.
+
+        // Fire everything up.
+        project.play();
+        project.work(&mut |_| {});
+
+        // Sender should have sent a message that receiver #1 should receive,
+        // because they're both in the same Track.
+        if let Ok(c) = counter_1.lock() {
+            assert_eq!(1, *c);
+        }
+        // But Receiver #2 shouldn't see that message, because it's in a
+        // different Track.
+        if let Ok(c) = counter_2.lock() {
+            assert_eq!(0, *c);
+        };
+    }
+
+    #[test]
+    fn sends_send() {
+        const EXPECTED_LEVEL: ParameterType = TestAudioSource::MEDIUM;
+        let mut project = Project::default();
+        let midi_track_uid = project.new_midi_track().unwrap();
+        let aux_track_uid = project.new_aux_track().unwrap();
+
         let _ = project.add_entity(
-            track_2_uid,
+            midi_track_uid,
             Box::new(TestAudioSource::new_with(
                 Uid::default(),
                 TestAudioSourceCoreBuilder::default()
@@ -1242,6 +1974,129 @@ mod tests {
                     .unwrap(),
             )),
         );
+        let mut samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(EXPECTED_LEVEL);
+        assert!(
+            samples.iter().all(|s| *s == expected_sample),
+            "Without a send, original signal should pass through unchanged."
+        );
+
+        assert!(project
+            .add_send(midi_track_uid, aux_track_uid, Normal::from(0.5))
+            .is_ok());
+        let mut samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.75);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "With a 50% send to an aux track with no effects, we should see the original MEDIUM=0.5 plus 50% of it = 0.75, but at sample #{index} we got {:?}", s);
+        });
+
+        // Add an effect to the aux track.
+        let _ = project.add_entity(aux_track_uid, Box::new(TestEffectNegatesInput::default()));
+
+        let mut samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.5 + 0.5 * 0.5 * -1.0);
+        samples.iter().enumerate().for_each(|(index,s)| {
+            assert_eq!(*s ,expected_sample, "With a 50% send to an aux with a negating effect, we should see the original 0.5 plus a negation of 50% of 0.5 = 0.250, but at sample #{index} we got {:?}", s);
+        });
+    }
+
+    #[test]
+    fn sidechain_ducks_track() {
+        const LEAD_LEVEL: ParameterType = 0.1;
+        let mut project = Project::default();
+
+        // The lead track comes first so that the key track has to be rendered
+        // out of order.
+        let lead_track_uid = new_source_track(&mut project, LEAD_LEVEL);
+        let key_track_uid = new_source_track(&mut project, TestAudioSource::MEDIUM);
+        let compressor_uid = project
+            .add_entity(
+                lead_track_uid,
+                Box::new(Compressor::new_with(
+                    Uid::default(),
+                    CompressorCoreBuilder::default()
+                        .threshold(-10.0)
+                        .ratio(10.0)
+                        .attack(0.0.into())
+                        .build()
+                        .unwrap(),
+                )),
+            )
+            .unwrap();
+        project.set_track_output(key_track_uid, Normal::zero());
+
+        let mut samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(LEAD_LEVEL);
+        assert!(
+            samples.iter().all(|s| *s == expected_sample),
+            "A quiet lead below the threshold shouldn't be compressed."
+        );
+
+        assert!(project.add_sidechain(compressor_uid, key_track_uid).is_ok());
+        assert_eq!(project.sidechain(compressor_uid), Some(key_track_uid));
+        let mut samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut samples, None);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert!(
+                s.0 .0 < LEAD_LEVEL,
+                "A loud key should duck the lead, but at sample #{index} we got {s:?}"
+            );
+        });
+
+        // The key is silent in the mix, but muting it shouldn't stop the ducking.
+        project.mute_track(key_track_uid, true);
+        let mut muted_samples = [StereoSample::SILENCE; 64];
+        project.generate_audio(&mut muted_samples, None);
+        assert_eq!(samples, muted_samples);
+
+        assert!(
+            project
+                .add_sidechain(compressor_uid, lead_track_uid)
+                .is_err(),
+            "A track shouldn't be able to key itself."
+        );
+        let key_compressor_uid = project
+            .add_entity(
+                key_track_uid,
+                Box::new(Compressor::new_with(
+                    Uid::default(),
+                    CompressorCoreBuilder::default().build().unwrap(),
+                )),
+            )
+            .unwrap();
+        assert!(
+            project
+                .add_sidechain(key_compressor_uid, lead_track_uid)
+                .is_err(),
+            "Two tracks shouldn't be able to key each other."
+        );
+
+        let negator_uid = project
+            .add_entity(
+                lead_track_uid,
+                Box::new(TestEffectNegatesInput::new_with(Uid::default())),
+            )
+            .unwrap();
+        assert!(
+            project.add_sidechain(negator_uid, key_track_uid).is_err(),
+            "An effect that doesn't take a key shouldn't be keyed."
+        );
+        assert_eq!(project.sidechain(negator_uid), None);
+
+        assert!(project.delete_track(key_track_uid).is_ok());
+        assert_eq!(project.sidechain(compressor_uid), None);
+    }
+
+    #[test]
+    fn mixer_works() {
+        const EXPECTED_LEVEL: ParameterType = TestAudioSource::MEDIUM;
+        let mut project = Project::default();
+        let track_1_uid = new_source_track(&mut project, EXPECTED_LEVEL);
+        let track_2_uid = new_source_track(&mut project, EXPECTED_LEVEL);
 
         let mut samples = [StereoSample::SILENCE; 4];
         project.generate_audio(&mut samples, None);
@@ -1276,6 +2131,409 @@ mod tests {
         });
     }
 
+    #[test]
+    fn send_taps_and_aux_to_aux_sends() {
+        let mut project = Project::default();
+        let track_uid = new_source_track(&mut project, TestAudioSource::MEDIUM);
+        let aux_1_uid = project.new_aux_track().unwrap();
+        let aux_2_uid = project.new_aux_track().unwrap();
+        assert!(
+            project
+                .add_send(aux_1_uid, track_uid, Normal::from(0.5))
+                .is_err(),
+            "Only aux tracks should receive sends"
+        );
+
+        // The track's fader is at zero, so only a pre-fader send is heard.
+        project.set_track_output(track_uid, Normal::zero());
+        assert!(project
+            .add_send(track_uid, aux_1_uid, Normal::from(0.5))
+            .is_ok());
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        assert!(
+            samples.iter().all(|s| *s == StereoSample::SILENCE),
+            "A post-fader send should follow the fader"
+        );
+        assert!(project
+            .set_send_tap(track_uid, aux_1_uid, SendTap::PreFader)
+            .is_ok());
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.5 * 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(
+                *s, expected_sample,
+                "A pre-fader send should ignore the fader, but at sample #{index} we got {s:?}"
+            );
+        });
+
+        // Panned hard left.
+        assert!(project
+            .set_send_pan(track_uid, aux_1_uid, BipolarNormal::from(-1.0))
+            .is_ok());
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample(Sample::from(0.5 * 0.5), Sample::SILENCE);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "A send panned left should reach only the left channel, but at sample #{index} we got {s:?}");
+        });
+
+        // Aux 1 feeds aux 2, so the signal is heard from both.
+        assert!(project
+            .add_send(aux_1_uid, aux_2_uid, Normal::from(0.5))
+            .is_ok());
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample(Sample::from(0.25 + 0.25 * 0.5), Sample::SILENCE);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "An aux-to-aux send should add the second aux's return, but at sample #{index} we got {s:?}");
+        });
+        assert!(
+            project
+                .add_send(aux_2_uid, aux_1_uid, Normal::from(0.5))
+                .is_err(),
+            "Aux tracks shouldn't be able to feed each other"
+        );
+    }
+
+    #[test]
+    fn legacy_sends_are_pre_fader() {
+        let mut project = Project::default();
+        let track_uid = new_source_track(&mut project, TestAudioSource::MEDIUM);
+        let aux_uid = project.new_aux_track().unwrap();
+        assert!(project
+            .add_send(track_uid, aux_uid, Normal::from(0.5))
+            .is_ok());
+        project.set_track_output(track_uid, Normal::zero());
+
+        // Projects saved before sends had a tap have no tap key at all.
+        let mut json = serde_json::to_value(&project.orchestrator.bus_station).unwrap();
+        json["routes"]
+            .as_object_mut()
+            .unwrap()
+            .values_mut()
+            .flat_map(|routes| routes.as_array_mut().unwrap())
+            .for_each(|route| {
+                route.as_object_mut().unwrap().remove("tap");
+            });
+        project.orchestrator.bus_station = serde_json::from_value(json).unwrap();
+
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.5 * 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(
+                *s, expected_sample,
+                "A legacy send should still ignore the fader, but at sample #{index} we got {s:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn group_tracks_sum_their_tracks() {
+        let mut project = Project::default();
+        let kick_uid = new_source_track(&mut project, 0.125);
+        let snare_uid = new_source_track(&mut project, 0.25);
+        let bass_uid = new_source_track(&mut project, 0.5);
+        let drums_uid = project.new_group_track().unwrap();
+        let rhythm_uid = project.new_group_track().unwrap();
+
+        assert!(project.set_track_group(kick_uid, Some(drums_uid)).is_ok());
+        assert!(project.set_track_group(snare_uid, Some(drums_uid)).is_ok());
+        assert!(project.set_track_group(drums_uid, Some(rhythm_uid)).is_ok());
+        assert!(project.set_track_group(bass_uid, Some(rhythm_uid)).is_ok());
+        assert_eq!(project.group_children(drums_uid), vec![kick_uid, snare_uid]);
+        assert!(
+            project
+                .set_track_group(rhythm_uid, Some(drums_uid))
+                .is_err(),
+            "A group shouldn't be able to contain itself"
+        );
+        assert!(
+            project.set_track_group(kick_uid, Some(bass_uid)).is_err(),
+            "Only group tracks should contain other tracks"
+        );
+
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.125 + 0.25 + 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "Nested groups should pass their tracks through once, but at sample #{index} we got {s:?}");
+        });
+
+        // The group's fader and effects apply to everything in it.
+        project.set_track_output(drums_uid, Normal::from(0.5));
+        let _ = project.add_entity(drums_uid, Box::new(TestEffectNegatesInput::default()));
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(-0.5 * (0.125 + 0.25) + 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "A group's effects and fader should process its tracks, but at sample #{index} we got {s:?}");
+        });
+
+        // Soloing a track in a group keeps the group audible.
+        project.set_track_solo(kick_uid, true);
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(-0.5 * 0.125);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "Soloing a grouped track should be heard through its groups, but at sample #{index} we got {s:?}");
+        });
+        project.set_solo_track(None);
+
+        // Deleting a group moves its tracks up a level.
+        project.set_group_folded(drums_uid, true);
+        assert!(project.delete_track(drums_uid).is_ok());
+        assert!(!project.is_group_folded(drums_uid));
+        assert_eq!(project.track_group(kick_uid), Some(rhythm_uid));
+        assert_eq!(
+            project.group_children(rhythm_uid),
+            vec![kick_uid, snare_uid, bass_uid]
+        );
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.125 + 0.25 + 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(
+                *s, expected_sample,
+                "Rendering should follow the new grouping, but at sample #{index} we got {s:?}"
+            );
+        });
+
+        project.set_group_folded(rhythm_uid, true);
+        assert!(project.is_track_folded_away(kick_uid));
+        assert!(!project.is_track_folded_away(rhythm_uid));
+    }
+
+    #[test]
+    fn parallel_rendering_matches_serial_rendering() {
+        // Builds a project whose tracks depend on each other in every way that
+        // affects the render order: groups, sidechains, and aux sends.
+        fn new_project() -> Project {
+            let mut project = Project::default();
+            let source_uids: Vec<TrackUid> = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6]
+                .into_iter()
+                .map(|level| new_source_track(&mut project, level))
+                .collect();
+            let group_uid = project.new_group_track().unwrap();
+            let aux_uid = project.new_aux_track().unwrap();
+
+            let compressor_uid = project
+                .add_entity(
+                    source_uids[0],
+                    Box::new(Compressor::new_with(
+                        Uid::default(),
+                        CompressorCoreBuilder::default()
+                            .threshold(-20.0)
+                            .ratio(4.0)
+                            .attack(0.001.into())
+                            .release(0.01.into())
+                            .build()
+                            .unwrap(),
+                    )),
+                )
+                .unwrap();
+            project.set_humidity(compressor_uid, Normal::from(0.75));
+            assert!(project
+                .add_sidechain(compressor_uid, source_uids[5])
+                .is_ok());
+            assert!(project
+                .set_track_group(source_uids[1], Some(group_uid))
+                .is_ok());
+            assert!(project
+                .set_track_group(source_uids[2], Some(group_uid))
+                .is_ok());
+            let _ = project.add_entity(group_uid, Box::new(TestEffectNegatesInput::default()));
+            let _ = project.add_entity(aux_uid, Box::new(TestEffectNegatesInput::default()));
+            assert!(project
+                .add_send(source_uids[3], aux_uid, Normal::from(0.5))
+                .is_ok());
+            project.set_track_pan(source_uids[4], BipolarNormal::from(-0.3));
+            project
+        }
+
+        let mut serial_project = new_project();
+        assert_eq!(
+            serial_project.render_thread_count(),
+            1,
+            "Playback shouldn't start threads unless asked to"
+        );
+        let mut parallel_project = new_project();
+        parallel_project.set_render_thread_count(Some(4));
+        assert_eq!(parallel_project.render_thread_count(), 4);
+
+        for _ in 0..16 {
+            let mut serial_samples = [StereoSample::SILENCE; 64];
+            serial_project.generate_audio(&mut serial_samples, None);
+            let mut parallel_samples = [StereoSample::SILENCE; 64];
+            parallel_project.generate_audio(&mut parallel_samples, None);
+            assert_eq!(
+                serial_samples, parallel_samples,
+                "Rendering on several threads should sound exactly like rendering on one"
+            );
+            assert!(serial_samples.iter().any(|s| *s != StereoSample::SILENCE));
+        }
+    }
+
+    #[test]
+    fn stem_capture_mainline() {
+        let mut project = Project::default();
+        let track_1_uid = new_source_track(&mut project, 0.5);
+        let track_2_uid = new_source_track(&mut project, 0.5);
+        let aux_uid = project.new_aux_track().unwrap();
+        project
+            .add_send(track_1_uid, aux_uid, Normal::maximum())
+            .unwrap();
+        project.set_track_output(track_2_uid, Normal::from(0.5));
+
+        let orchestrator = &project.orchestrator;
+        assert_eq!(
+            orchestrator.stem_track_uids(&StemExportOptions::default()),
+            vec![track_1_uid, track_2_uid, aux_uid]
+        );
+        assert_eq!(
+            orchestrator.stem_track_uids(&StemExportOptions {
+                include_sends: false,
+                ..Default::default()
+            }),
+            vec![track_1_uid, track_2_uid]
+        );
+        assert_eq!(
+            orchestrator.stem_track_uids(&StemExportOptions {
+                track_uids: Some(vec![track_1_uid]),
+                ..Default::default()
+            }),
+            vec![track_1_uid, aux_uid],
+            "Exporting a track with sends should also export the aux tracks it sends to"
+        );
+        assert_eq!(
+            orchestrator.stem_track_uids(&StemExportOptions {
+                track_uids: Some(vec![track_2_uid]),
+                ..Default::default()
+            }),
+            vec![track_2_uid]
+        );
+
+        project
+            .orchestrator
+            .start_stem_capture(&[track_1_uid, track_2_uid], true);
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let stem_1 = project.orchestrator.captured_stem(track_1_uid).unwrap();
+        let stem_2 = project.orchestrator.captured_stem(track_2_uid).unwrap();
+        assert_eq!(stem_1.len(), samples.len());
+        assert!(stem_1.iter().all(|s| *s == StereoSample::from(0.5)));
+        assert!(
+            stem_2.iter().all(|s| *s == StereoSample::from(0.5 * 0.5)),
+            "A post-fader stem should reflect the track's output level"
+        );
+        assert!(project.orchestrator.captured_stem(aux_uid).is_none());
+
+        project
+            .orchestrator
+            .start_stem_capture(&[track_1_uid, track_2_uid], false);
+        project.mute_track(track_1_uid, true);
+        project.generate_audio(&mut samples, None);
+        assert!(project
+            .orchestrator
+            .captured_stem(track_1_uid)
+            .unwrap()
+            .iter()
+            .all(|s| *s == StereoSample::SILENCE));
+        assert!(
+            project
+                .orchestrator
+                .captured_stem(track_2_uid)
+                .unwrap()
+                .iter()
+                .all(|s| *s == StereoSample::from(0.5)),
+            "A pre-fader stem should ignore the track's output level"
+        );
+
+        project.orchestrator.stop_stem_capture();
+        assert!(project.orchestrator.captured_stem(track_2_uid).is_none());
+        assert_eq!(Project::file_stem("Drums/Bass: 2 "), "Drums_Bass_ 2");
+    }
+
+    #[test]
+    fn master_track_processes_final_mix() {
+        let mut project = Project::default();
+        let track_uid = new_source_track(&mut project, 0.5);
+        let master_uid = Orchestrator::MASTER_TRACK_UID;
+        assert!(!project.track_uids().contains(&master_uid));
+
+        let effect_uid = project
+            .add_entity(master_uid, Box::new(TestEffectNegatesInput::default()))
+            .unwrap();
+        project.set_track_output(master_uid, Normal::from(0.5));
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(-0.5 * 0.5);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "The master track's effects and fader should process the final mix, but at sample #{index} we got {s:?}");
+        });
+
+        // A dry master effect leaves the mix alone.
+        project.set_humidity(effect_uid, Normal::zero());
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        assert!(samples.iter().all(|s| *s == StereoSample::from(0.5 * 0.5)));
+        project.set_humidity(effect_uid, Normal::maximum());
+
+        // The master track survives a round trip through serialization.
+        project.before_ser();
+        let json = serde_json::to_string(&project).unwrap();
+        let mut project = serde_json::from_str::<Project>(&json).unwrap();
+        project.after_deser();
+        assert_eq!(project.entity_uids(master_uid).unwrap(), &[effect_uid]);
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        assert!(samples.iter().all(|s| *s == expected_sample));
+
+        assert!(
+            project.delete_track(master_uid).is_err(),
+            "The master track shouldn't be deletable"
+        );
+        let aux_uid = project.new_aux_track().unwrap();
+        assert!(project
+            .add_send(master_uid, aux_uid, Normal::maximum())
+            .is_err());
+    }
+
+    #[test]
+    fn solo_keeps_aux_returns() {
+        let mut project = Project::default();
+        let kick_uid = new_source_track(&mut project, 0.125);
+        let snare_uid = new_source_track(&mut project, 0.25);
+        let _bass_uid = new_source_track(&mut project, 0.5);
+        let aux_track_uid = project.new_aux_track().unwrap();
+        assert!(project.is_track_solo_safe(aux_track_uid));
+        assert!(project
+            .add_send(kick_uid, aux_track_uid, Normal::from(0.5))
+            .is_ok());
+        assert!(project
+            .add_send(snare_uid, aux_track_uid, Normal::from(0.5))
+            .is_ok());
+
+        project.set_track_solo(kick_uid, true);
+        project.set_track_solo(snare_uid, true);
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.125 + 0.25 + 0.5 * (0.125 + 0.25));
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "Soloing two tracks should let both through along with their aux return, but at sample #{index} we got {s:?}");
+        });
+
+        project.set_track_solo_safe(aux_track_uid, false);
+        let mut samples = [StereoSample::SILENCE; 4];
+        project.generate_audio(&mut samples, None);
+        let expected_sample = StereoSample::from(0.125 + 0.25);
+        samples.iter().enumerate().for_each(|(index, s)| {
+            assert_eq!(*s, expected_sample, "An aux track that isn't solo-safe should be silenced by a solo, but at sample #{index} we got {s:?}");
+        });
+    }
+
     #[test]
     fn project_routes_midi_to_external() {
         let mut project = Project::default();
@@ -0,0 +1,269 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Renders tracks into their own buffers, several at a time when they don't
+//! depend on each other.
+
+use super::{humidity::Humidifier, AudioClipRepository, BusStation, Mixer, SendTap};
+use crate::{cores::effects::SIDECHAIN_KEY_CHANNELS, prelude::*};
+use ensnare::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// What a [TrackRenderJob] does with its track's buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum TrackRenderKind {
+    /// Generates audio and runs it through the track's effects.
+    Regular,
+    /// Runs what other tracks sent to it through its effects.
+    Aux,
+    /// Runs the final mix through the master track's effects.
+    Master,
+}
+
+/// One track's share of an [Orchestrator](super::Orchestrator) render. The
+/// job borrows the track's entities for the duration of the render, so jobs
+/// for different tracks can run on different threads.
+pub(super) struct TrackRenderJob<'a> {
+    pub(super) track_uid: TrackUid,
+    pub(super) kind: TrackRenderKind,
+    /// The track's entities, in signal-chain order.
+    pub(super) entities: Vec<(Uid, &'a mut Box<dyn Entity>)>,
+    /// Starts as the track's input, and ends as its output.
+    pub(super) buffer: Vec<StereoSample>,
+}
+
+/// The parts of the orchestrator that a [TrackRenderJob] only reads.
+pub(super) struct TrackRenderContext<'a> {
+    pub(super) audio_clip_repo: &'a AudioClipRepository,
+    pub(super) humidifier: &'a Humidifier,
+    pub(super) bus_station: &'a BusStation,
+    pub(super) mixer: &'a Mixer,
+    pub(super) audible_track_uids: &'a FxHashSet<TrackUid>,
+    pub(super) group_children: &'a FxHashMap<TrackUid, Vec<TrackUid>>,
+}
+impl<'a> TrackRenderContext<'a> {
+    /// Mixes the track's signal into the aux tracks that it sends to. A track
+    /// that isn't heard doesn't send anything, even if it was rendered to be a
+    /// sidechain key.
+    pub(super) fn mix_sends(
+        &self,
+        track_uid: TrackUid,
+        track_buffer: &[StereoSample],
+        aux_track_buffers: &mut FxHashMap<TrackUid, Vec<StereoSample>>,
+    ) {
+        if !self.audible_track_uids.contains(&track_uid) {
+            return;
+        }
+        let Some(routes) = self.bus_station.sends_for_track(&track_uid) else {
+            return;
+        };
+        for route in routes {
+            if let Some(aux) = aux_track_buffers.get_mut(&route.aux_track_uid) {
+                let fader = match route.tap {
+                    SendTap::PreFader => 1.0,
+                    SendTap::PostFader => self.mixer.track_output(track_uid).0,
+                };
+                let (left_gain, right_gain) = self.mixer.pan_law().gains(route.pan);
+                let left_gain = route.amount.0 * fader * left_gain;
+                let right_gain = route.amount.0 * fader * right_gain;
+                for (src, dst) in track_buffer.iter().zip(aux.iter_mut()) {
+                    *dst += StereoSample(src.0 * left_gain, src.1 * right_gain);
+                }
+            }
+        }
+    }
+}
+
+/// The output of every non-aux track rendered in an earlier wave.
+pub(super) type TrackBuffers = FxHashMap<TrackUid, Vec<StereoSample>>;
+
+impl<'a> TrackRenderJob<'a> {
+    fn render(
+        &mut self,
+        context: &TrackRenderContext,
+        track_buffers: &TrackBuffers,
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+    ) {
+        match self.kind {
+            TrackRenderKind::Regular => self.render_track(context, track_buffers, pre_effect),
+            TrackRenderKind::Aux => self.render_aux(context, track_buffers),
+            TrackRenderKind::Master => self.render_master(context, track_buffers, pre_effect),
+        }
+    }
+
+    fn render_track(
+        &mut self,
+        context: &TrackRenderContext,
+        track_buffers: &TrackBuffers,
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+    ) {
+        // A sidechain key is heard through the effect it keys, so it still has
+        // to be rendered even if the track itself isn't going to be mixed.
+        let should_work = context.audible_track_uids.contains(&self.track_uid)
+            || context.bus_station.is_sidechain_key(self.track_uid);
+        if !should_work {
+            return;
+        }
+
+        // A group's tracks are mixed into it first so that the group's effects
+        // can process them.
+        if let Some(children) = context.group_children.get(&self.track_uid) {
+            for child_uid in children {
+                if context.audible_track_uids.contains(child_uid) {
+                    if let Some(child_buffer) = track_buffers.get(child_uid) {
+                        context
+                            .mixer
+                            .mix_track(*child_uid, child_buffer, &mut self.buffer);
+                    }
+                }
+            }
+        }
+
+        // Audio clips come next so that the track's effects can process them.
+        context
+            .audio_clip_repo
+            .generate_for_track(self.track_uid, &mut self.buffer);
+        for (uid, entity) in self.entities.iter_mut() {
+            entity.generate(&mut self.buffer);
+            Self::transform_wet_dry(
+                context,
+                track_buffers,
+                pre_effect,
+                *uid,
+                entity,
+                &mut self.buffer,
+            );
+        }
+    }
+
+    fn render_master(
+        &mut self,
+        context: &TrackRenderContext,
+        track_buffers: &TrackBuffers,
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+    ) {
+        for (uid, entity) in self.entities.iter_mut() {
+            Self::transform_wet_dry(
+                context,
+                track_buffers,
+                pre_effect,
+                *uid,
+                entity,
+                &mut self.buffer,
+            );
+        }
+    }
+
+    // Runs the effect over the samples, mixing its output with the dry signal
+    // according to its humidity.
+    fn transform_wet_dry(
+        context: &TrackRenderContext,
+        track_buffers: &TrackBuffers,
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+        uid: Uid,
+        effect: &mut Box<dyn Entity>,
+        samples: &mut [StereoSample],
+    ) {
+        let humidity = context.humidifier.get_humidity(&uid);
+        if humidity == Normal::zero() {
+            return;
+        }
+        let key = context
+            .bus_station
+            .sidechain(uid)
+            .and_then(|key_track_uid| track_buffers.get(&key_track_uid));
+        context
+            .humidifier
+            .transform_batch_using(humidity, samples, pre_effect, |samples| {
+                if let Some(key) = key {
+                    transform_with_key(effect, key, samples);
+                } else {
+                    effect.transform(samples);
+                }
+            });
+    }
+
+    fn render_aux(&mut self, context: &TrackRenderContext, track_buffers: &TrackBuffers) {
+        if !context.audible_track_uids.contains(&self.track_uid) {
+            return;
+        }
+        for (uid, entity) in self.entities.iter_mut() {
+            let key = context
+                .bus_station
+                .sidechain(*uid)
+                .and_then(|key_track_uid| track_buffers.get(&key_track_uid));
+            if let Some(key) = key {
+                transform_with_key(entity, key, &mut self.buffer);
+            } else {
+                entity.transform(&mut self.buffer);
+            }
+        }
+    }
+}
+
+/// Renders a wave of tracks that don't depend on each other, spreading them
+/// across up to `thread_count` threads. Each job's result depends only on its
+/// own track and on earlier waves, so the output is the same no matter how
+/// many threads do the work. With one thread, nothing is spawned, and
+/// `pre_effect` is the only scratch space used.
+pub(super) fn render_wave(
+    jobs: &mut [TrackRenderJob],
+    context: &TrackRenderContext,
+    track_buffers: &TrackBuffers,
+    thread_count: usize,
+    pre_effect: &mut GenerationBuffer<StereoSample>,
+) {
+    fn render_serially(
+        jobs: &mut [TrackRenderJob],
+        context: &TrackRenderContext,
+        track_buffers: &TrackBuffers,
+        pre_effect: &mut GenerationBuffer<StereoSample>,
+    ) {
+        jobs.iter_mut()
+            .for_each(|job| job.render(context, track_buffers, pre_effect));
+    }
+
+    if thread_count <= 1 || jobs.len() <= 1 {
+        render_serially(jobs, context, track_buffers, pre_effect);
+        return;
+    }
+    let chunk_size = jobs.len().div_ceil(thread_count);
+    std::thread::scope(|scope| {
+        let mut chunks = jobs.chunks_mut(chunk_size);
+
+        // This thread takes a share of the work rather than waiting idly.
+        let first_chunk = chunks.next();
+        for chunk in chunks {
+            scope.spawn(move || {
+                render_serially(
+                    chunk,
+                    context,
+                    track_buffers,
+                    &mut GenerationBuffer::default(),
+                )
+            });
+        }
+        if let Some(chunk) = first_chunk {
+            render_serially(chunk, context, track_buffers, pre_effect);
+        }
+    });
+}
+
+// Runs an effect over the samples with a sidechain key. The effect implements
+// TransformsAudioWithKey, but an Entity can't be asked for that, so this gives
+// it each frame of the key on SIDECHAIN_KEY_CHANNELS just before the matching
+// frame of its input.
+fn transform_with_key(
+    effect: &mut Box<dyn Entity>,
+    key: &[StereoSample],
+    samples: &mut [StereoSample],
+) {
+    let [key_left, key_right] = SIDECHAIN_KEY_CHANNELS;
+    for (sample, key) in samples.iter_mut().zip(key.iter()) {
+        let _ = effect.transform_channel(key_left, key.0);
+        let _ = effect.transform_channel(key_right, key.1);
+        *sample = StereoSample(
+            effect.transform_channel(0, sample.0),
+            effect.transform_channel(1, sample.1),
+        );
+    }
+}
@@ -181,6 +181,30 @@ impl EntityRepository {
         &self.uids_for_track
     }
 
+    /// Lends out every track's entities at once, each track's in signal-chain
+    /// order, so that several tracks can be rendered side by side while their
+    /// entities stay where they are.
+    pub(crate) fn entities_by_track_mut(
+        &mut self,
+    ) -> FxHashMap<TrackUid, Vec<(Uid, &mut Box<dyn Entity>)>> {
+        let mut entities: FxHashMap<Uid, &mut Box<dyn Entity>> = self
+            .entities
+            .iter_mut()
+            .map(|(uid, entity)| (*uid, entity))
+            .collect();
+        self.uids_for_track
+            .iter()
+            .map(|(track_uid, uids)| {
+                (
+                    *track_uid,
+                    uids.iter()
+                        .filter_map(|uid| entities.remove(uid).map(|entity| (*uid, entity)))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
     fn update_is_finished(&mut self) {
         self.is_finished = self.entities.values().all(|e| e.is_finished());
     }
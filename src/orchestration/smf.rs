@@ -0,0 +1,938 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Conversion between [Project]s and Standard MIDI Files (SMF).
+
+use super::{TempoEvent, TempoTransition, TrackTitle};
+use crate::prelude::*;
+use anyhow::{anyhow, Result};
+use ensnare::{prelude::*, util::Rng};
+use midly::{
+    num::{u15, u24, u28, u4, u7},
+    Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use rustc_hash::FxHashMap;
+use std::path::{Path, PathBuf};
+
+/// The notes that one SMF track plays on one MIDI channel. Becomes one
+/// [Project] track.
+#[derive(Debug, Default)]
+struct SmfChannelNotes {
+    notes: Vec<Note>,
+
+    // Notes that have started but haven't yet ended, along with their
+    // velocities. Each key has a FIFO so that overlapping notes with the same
+    // key pair up in the order they started.
+    pending: FxHashMap<u8, Vec<(MusicalTime, u8)>>,
+}
+impl SmfChannelNotes {
+    fn note_on(&mut self, key: u8, velocity: u8, time: MusicalTime) {
+        self.pending.entry(key).or_default().push((time, velocity));
+    }
+
+    fn note_off(&mut self, key: u8, release_velocity: u8, time: MusicalTime) {
+        if let Some(starts) = self.pending.get_mut(&key) {
+            if !starts.is_empty() {
+                let (start, velocity) = starts.remove(0);
+                self.notes.push(
+                    Note::new_with_start_and_end(key, start, time)
+                        .with_velocity(velocity)
+                        .with_release_velocity(release_velocity),
+                );
+            }
+        }
+    }
+
+    // Ends any notes that never got a note-off.
+    fn finish(&mut self, time: MusicalTime) {
+        let pending: Vec<(u8, Vec<(MusicalTime, u8)>)> = self.pending.drain().collect();
+        for (key, starts) in pending {
+            for (start, velocity) in starts {
+                self.notes.push(
+                    Note::new_with_start_and_end(key, start, time.max(start))
+                        .with_velocity(velocity),
+                );
+            }
+        }
+        self.notes.sort_by_key(|note| note.extent.0.start);
+    }
+}
+
+impl Project {
+    /// Creates a new [Project] from the Standard MIDI File at the given path.
+    /// See [Project::import_smf()].
+    pub fn new_from_smf_path(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut r = Self::new_from_smf(&bytes)?;
+        if let Some(stem) = path.file_stem() {
+            r.title = Some(ProjectTitle(stem.to_string_lossy().to_string()));
+        }
+        Ok(r)
+    }
+
+    /// Creates a new [Project] from the contents of a Standard MIDI File. See
+    /// [Project::import_smf()].
+    pub fn new_from_smf(bytes: &[u8]) -> Result<Self> {
+        let mut r = Self::default();
+        r.set_rng_seed(Rng::generate_seed().unwrap());
+        r.import_smf(bytes)?;
+        Ok(r)
+    }
+
+    /// Adds the contents of a type 0 or type 1 Standard MIDI File to this
+    /// project. Each MIDI channel used by each SMF track becomes a new MIDI
+    /// track. Its notes are cut into [Pattern]s at bar boundaries (or at the
+    /// next free boundary if a note crosses a bar line), and each [Pattern] is
+    /// arranged where it was found. The file's first tempo and time signature
+    /// are applied to the project, and any later changes replace the project's
+    /// [TempoMap] and [TimeSignatureMap].
+    ///
+    /// Returns the [TrackUid]s of the new tracks.
+    pub fn import_smf(&mut self, bytes: &[u8]) -> Result<Vec<TrackUid>> {
+        let smf = Smf::parse(bytes)?;
+        if smf.header.format == Format::Sequential {
+            return Err(anyhow!("SMF type 2 (sequential) files aren't supported"));
+        }
+        let ticks_per_beat = match smf.header.timing {
+            Timing::Metrical(ticks_per_beat) => ticks_per_beat.as_int() as usize,
+            Timing::Timecode(..) => {
+                return Err(anyhow!("SMF files with SMPTE timing aren't supported"))
+            }
+        };
+        if ticks_per_beat == 0 {
+            return Err(anyhow!("SMF header has zero ticks per beat"));
+        }
+        let ticks_to_time = |ticks: usize| {
+            MusicalTime::new_with_units(ticks * MusicalTime::UNITS_IN_BEAT / ticks_per_beat)
+        };
+
+        let mut tempos: Vec<(MusicalTime, Tempo)> = Vec::default();
+        let mut time_signatures: Vec<(MusicalTime, TimeSignature)> = Vec::default();
+        let mut smf_tracks = Vec::default();
+        for track in smf.tracks.iter() {
+            let mut ticks = 0;
+            let mut name = None;
+            let mut channels: FxHashMap<u8, SmfChannelNotes> = FxHashMap::default();
+            for event in track.iter() {
+                ticks += event.delta.as_int() as usize;
+                let time = ticks_to_time(ticks);
+                match event.kind {
+                    TrackEventKind::Midi { channel, message } => {
+                        let notes = channels.entry(channel.as_int()).or_default();
+                        match message {
+                            midly::MidiMessage::NoteOn { key, vel } => {
+                                if vel.as_int() == 0 {
+                                    notes.note_off(
+                                        key.as_int(),
+                                        Note::DEFAULT_RELEASE_VELOCITY,
+                                        time,
+                                    );
+                                } else {
+                                    notes.note_on(key.as_int(), vel.as_int(), time);
+                                }
+                            }
+                            midly::MidiMessage::NoteOff { key, vel } => {
+                                notes.note_off(key.as_int(), vel.as_int(), time)
+                            }
+                            _ => {}
+                        }
+                    }
+                    TrackEventKind::Meta(MetaMessage::TrackName(bytes)) => {
+                        name = Some(String::from_utf8_lossy(bytes).trim().to_string());
+                    }
+                    TrackEventKind::Meta(MetaMessage::Tempo(usec_per_beat)) => {
+                        if usec_per_beat.as_int() != 0 {
+                            tempos
+                                .push((time, Tempo(60_000_000.0 / usec_per_beat.as_int() as f64)));
+                        }
+                    }
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(top, bottom_power, _, _)) => {
+                        time_signatures.push((
+                            time,
+                            TimeSignature::new_with(
+                                top as usize,
+                                1usize.checked_shl(bottom_power as u32).unwrap_or_default(),
+                            )?,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            let end_time = ticks_to_time(ticks);
+            let mut channels: Vec<(u8, SmfChannelNotes)> = channels
+                .into_iter()
+                .filter_map(|(channel, mut notes)| {
+                    notes.finish(end_time);
+                    if notes.notes.is_empty() {
+                        None
+                    } else {
+                        Some((channel, notes))
+                    }
+                })
+                .collect();
+            channels.sort_by_key(|(channel, _)| *channel);
+            smf_tracks.push((name, channels));
+        }
+
+        // The earliest tempo is the project's tempo, and the rest become its
+        // tempo map.
+        tempos.sort_by_key(|(time, _)| *time);
+        if let Some(((first_time, first_tempo), changes)) = tempos.split_first() {
+            let mut tempo = *first_tempo;
+            self.tempo_map.clear();
+            for (time, change) in changes {
+                if time == first_time {
+                    tempo = *change;
+                } else {
+                    self.tempo_map
+                        .add_event(TempoEvent::new_step(*time, *change))?;
+                }
+            }
+            self.update_tempo(tempo);
+        }
+        // Likewise, the earliest time signature is the project's, and the rest
+        // become its time signature map. A change that doesn't fall on a bar
+        // line waits for the next one.
+        time_signatures.sort_by_key(|(time, _)| *time);
+        let time_signature = time_signatures
+            .first()
+            .map(|(_, time_signature)| *time_signature)
+            .unwrap_or_default();
+        self.update_time_signature(time_signature);
+        if time_signatures.len() > 1 {
+            self.time_signature_map.clear();
+        }
+        for (time, change) in time_signatures.iter().skip(1) {
+            let mut bar = self.bar_at(*time);
+            if self.bar_start(bar) != *time {
+                bar += 1;
+            }
+            if bar == 0 {
+                self.update_time_signature(*change);
+            } else if self.time_signature_at(self.bar_start(bar)) != *change {
+                self.time_signature_map.add_change(bar, *change)?;
+            }
+        }
+        let time_signature = self.time_signature();
+
+        let mut imported_patterns: Vec<(TimeSignature, Vec<Note>, PatternUid)> = Vec::default();
+        let mut track_uids = Vec::default();
+        for (name, channels) in smf_tracks {
+            let channel_count = channels.len();
+            for (channel, notes) in channels {
+                let track_uid = self.new_midi_track()?;
+                track_uids.push(track_uid);
+                let midi_channel = MidiChannel::new(channel);
+                self.set_track_midi_channel(track_uid, midi_channel);
+                if let Some(name) = name.as_ref().filter(|name| !name.is_empty()) {
+                    let title = if channel_count > 1 {
+                        format!("{name} (ch {})", channel + 1)
+                    } else {
+                        name.clone()
+                    };
+                    self.track_titles.insert(track_uid, TrackTitle(title));
+                }
+
+                for (position, notes) in
+                    Self::cut_notes_at_bars(notes.notes, &time_signature, &self.time_signature_map)
+                {
+                    let pattern_time_signature = self.time_signature_at(position);
+                    let pattern_uid = if let Some((_, _, pattern_uid)) = imported_patterns
+                        .iter()
+                        .find(|(ts, n, _)| *ts == pattern_time_signature && *n == notes)
+                    {
+                        *pattern_uid
+                    } else {
+                        let pattern = PatternBuilder::default()
+                            .time_signature(pattern_time_signature)
+                            .notes(notes.clone())
+                            .color_scheme(self.composer.suggest_next_pattern_color_scheme())
+                            .build()?;
+                        let pattern_uid = self.add_pattern(pattern, None)?;
+                        imported_patterns.push((pattern_time_signature, notes, pattern_uid));
+                        pattern_uid
+                    };
+                    self.arrange_pattern(track_uid, pattern_uid, Some(midi_channel), position)?;
+                }
+            }
+        }
+
+        let extent = self.composer.extent();
+        if extent.0.end > self.view_state.view_range.0.end {
+            self.view_state.view_range = ViewRange(MusicalTime::START..extent.0.end);
+        }
+
+        Ok(track_uids)
+    }
+
+    /// The resolution of exported Standard MIDI Files.
+    pub const SMF_TICKS_PER_BEAT: usize = 960;
+
+    /// Writes this project's arrangements to the given path as a Standard MIDI
+    /// File. See [Project::to_smf()].
+    pub fn export_to_smf(&self, path: PathBuf) -> Result<()> {
+        std::fs::write(path, self.to_smf()?)?;
+        Ok(())
+    }
+
+    /// Returns this project's arrangements as the contents of a type 1
+    /// Standard MIDI File. The first SMF track carries the project's title,
+    /// tempo, and time signature. Each project track with at least one
+    /// arrangement follows as its own SMF track, with each arranged [Pattern]'s
+    /// notes on that arrangement's MIDI channel.
+    pub fn to_smf(&self) -> Result<Vec<u8>> {
+        let time_to_ticks = |time: MusicalTime| {
+            let ticks = (time.total_units() * Self::SMF_TICKS_PER_BEAT
+                + MusicalTime::UNITS_IN_BEAT / 2)
+                / MusicalTime::UNITS_IN_BEAT;
+            u32::try_from(ticks).unwrap_or(u32::MAX)
+        };
+
+        // midly borrows track names for the lifetime of the Smf, so gather
+        // them up front.
+        let project_title = self
+            .title
+            .as_ref()
+            .map(|title| title.0.clone())
+            .unwrap_or_default();
+        let track_titles: Vec<(TrackUid, String)> = self
+            .track_uids()
+            .iter()
+            .filter(|track_uid| {
+                self.composer
+                    .tracks_to_ordered_arrangement_uids
+                    .get(track_uid)
+                    .is_some_and(|arrangement_uids| !arrangement_uids.is_empty())
+            })
+            .map(|track_uid| {
+                let title = self
+                    .track_titles
+                    .get(track_uid)
+                    .map(|title| title.0.clone())
+                    .unwrap_or_else(|| format!("Track {track_uid}"));
+                (*track_uid, title)
+            })
+            .collect();
+
+        let mut smf = Smf::new(Header::new(
+            Format::Parallel,
+            Timing::Metrical(u15::new(Self::SMF_TICKS_PER_BEAT as u16)),
+        ));
+
+        let time_signature_meta = |time_signature: &TimeSignature| {
+            MetaMessage::TimeSignature(
+                time_signature.top as u8,
+                time_signature.bottom.trailing_zeros() as u8,
+                24,
+                8,
+            )
+        };
+        let tempo_meta =
+            |tempo: Tempo| MetaMessage::Tempo(u24::new((60_000_000.0 / tempo.0).round() as u32));
+        let mut conductor_events = Vec::default();
+        if !project_title.is_empty() {
+            conductor_events.push((0, MetaMessage::TrackName(project_title.as_bytes())));
+        }
+        conductor_events.push((0, tempo_meta(self.tempo())));
+        conductor_events.push((0, time_signature_meta(&self.time_signature())));
+        for change in self.time_signature_map.changes() {
+            conductor_events.push((
+                time_to_ticks(self.bar_start(change.bar)),
+                time_signature_meta(&change.time_signature),
+            ));
+        }
+        // SMF has no tempo ramps, so a ramp becomes a staircase of steps, one
+        // every quarter of a beat, each at the ramp's tempo in the middle of
+        // its step.
+        let mut previous_time = MusicalTime::START;
+        for event in self.tempo_map.events() {
+            if event.transition == TempoTransition::Ramp {
+                let mut step_start = previous_time;
+                while step_start < event.time {
+                    let step_end = (step_start + MusicalTime::DURATION_QUARTER).min(event.time);
+                    let step_middle = MusicalTime::new_with_units(
+                        (step_start.total_units() + step_end.total_units()) / 2,
+                    );
+                    conductor_events.push((
+                        time_to_ticks(step_start),
+                        tempo_meta(self.tempo_at(step_middle)),
+                    ));
+                    step_start = step_end;
+                }
+            }
+            conductor_events.push((time_to_ticks(event.time), tempo_meta(event.tempo)));
+            previous_time = event.time;
+        }
+        conductor_events.sort_by_key(|(ticks, _)| *ticks);
+        conductor_events.push((
+            conductor_events
+                .last()
+                .map(|(ticks, _)| *ticks)
+                .unwrap_or_default(),
+            MetaMessage::EndOfTrack,
+        ));
+        let mut last_ticks = 0;
+        let conductor: Vec<TrackEvent> = conductor_events
+            .into_iter()
+            .map(|(ticks, message)| {
+                let delta = ticks - last_ticks;
+                last_ticks = ticks;
+                TrackEvent {
+                    delta: u28::new(delta),
+                    kind: TrackEventKind::Meta(message),
+                }
+            })
+            .collect();
+        smf.tracks.push(conductor);
+
+        for (track_uid, title) in track_titles.iter() {
+            // (ticks, is_note_on, channel, key, velocity)
+            let mut events: Vec<(u32, bool, u8, u8, u8)> = Vec::default();
+            if let Some(arrangement_uids) = self
+                .composer
+                .tracks_to_ordered_arrangement_uids
+                .get(track_uid)
+            {
+                for arrangement_uid in arrangement_uids {
+                    let arrangement = self
+                        .composer
+                        .arrangements
+                        .get(arrangement_uid)
+                        .ok_or_else(|| anyhow!("missing arrangement {arrangement_uid}"))?;
+                    let pattern = self
+                        .pattern(arrangement.pattern_uid)
+                        .ok_or_else(|| anyhow!("missing pattern {}", arrangement.pattern_uid))?;
+                    let channel = arrangement.midi_channel.0;
+                    if channel > MidiChannel::MAX_VALUE {
+                        return Err(anyhow!(
+                            "arrangement {arrangement_uid} has invalid MIDI channel {channel}"
+                        ));
+                    }
+                    // Keys above 127 can't be played or written, so leave
+                    // them out.
+                    for note in pattern.notes().iter().filter(|note| note.key <= 127) {
+                        // Keep every note at least a tick long so that its
+                        // note-off doesn't sort ahead of its note-on.
+                        let start = time_to_ticks(arrangement.position + note.extent.0.start);
+                        let end =
+                            time_to_ticks(arrangement.position + note.extent.0.end).max(start + 1);
+                        events.push((start, true, channel, note.key, note.note_on_velocity()));
+                        events.push((end, false, channel, note.key, note.note_off_velocity()));
+                    }
+                }
+            }
+            // At any given tick, end notes before starting new ones, so that a
+            // repeated key doesn't cut itself off.
+            events.sort_by_key(|(ticks, is_note_on, ..)| (*ticks, *is_note_on));
+
+            let mut track = vec![TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::TrackName(title.as_bytes())),
+            }];
+            let mut last_ticks = 0;
+            for (ticks, is_note_on, channel, key, velocity) in events {
+                let message = if is_note_on {
+                    midly::MidiMessage::NoteOn {
+                        key: u7::new(key),
+                        vel: u7::new(velocity),
+                    }
+                } else {
+                    midly::MidiMessage::NoteOff {
+                        key: u7::new(key),
+                        vel: u7::new(velocity),
+                    }
+                };
+                track.push(TrackEvent {
+                    delta: u28::new(ticks - last_ticks),
+                    kind: TrackEventKind::Midi {
+                        channel: u4::new(channel),
+                        message,
+                    },
+                });
+                last_ticks = ticks;
+            }
+            track.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+            smf.tracks.push(track);
+        }
+
+        let mut bytes = Vec::default();
+        smf.write_std(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Groups the given notes into runs that begin on bar boundaries. A run ends
+    // at the first bar boundary that no note crosses. Returns each run's start
+    // along with its notes, which are made relative to that start.
+    fn cut_notes_at_bars(
+        notes: Vec<Note>,
+        time_signature: &TimeSignature,
+        time_signature_map: &TimeSignatureMap,
+    ) -> Vec<(MusicalTime, Vec<Note>)> {
+        let mut runs: Vec<(usize, usize, Vec<Note>)> = Vec::default();
+        for note in notes {
+            let first_bar = time_signature_map.bar_at(time_signature, note.extent.0.start);
+            let last_bar = if note.extent.0.end > note.extent.0.start {
+                time_signature_map.bar_at(time_signature, note.extent.0.end - MusicalTime::ONE_UNIT)
+            } else {
+                first_bar
+            };
+            match runs.last_mut() {
+                Some((_, run_last_bar, run_notes)) if first_bar <= *run_last_bar => {
+                    *run_last_bar = (*run_last_bar).max(last_bar);
+                    run_notes.push(note);
+                }
+                _ => runs.push((first_bar, last_bar, vec![note])),
+            }
+        }
+
+        runs.into_iter()
+            .map(|(first_bar, _, notes)| {
+                let position = time_signature_map.bar_start(time_signature, first_bar);
+                let notes = notes
+                    .into_iter()
+                    .map(|mut note| {
+                        note.extent =
+                            TimeRange(note.extent.0.start - position..note.extent.0.end - position);
+                        note
+                    })
+                    .collect();
+                (position, notes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn midi_test_data_path(filename: &str) -> PathBuf {
+        Paths::test_data_rel().join("midi").join(filename)
+    }
+
+    // Reads a drumstick-dumpsmf text dump (see test-data/midi/generate_dumps)
+    // and counts the sounding note-ons on each channel.
+    fn note_on_counts_from_dump(filename: &str) -> FxHashMap<u8, usize> {
+        let dump = std::fs::read_to_string(midi_test_data_path(filename)).unwrap();
+        let mut counts = FxHashMap::default();
+        for line in dump.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() == 7 && fields[3] == "Note" && fields[4] == "On" {
+                let channel: u8 = fields[2].parse().unwrap();
+                let velocity: u8 = fields[6].parse().unwrap();
+                if velocity != 0 {
+                    *counts.entry(channel).or_default() += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    fn imported_note_counts(project: &Project) -> FxHashMap<u8, usize> {
+        let mut counts = FxHashMap::default();
+        for track_uid in project.track_uids() {
+            let channel = project.track_midi_channel(*track_uid).unwrap().0;
+            if let Some(arrangement_uids) = project
+                .composer
+                .tracks_to_ordered_arrangement_uids
+                .get(track_uid)
+            {
+                for arrangement_uid in arrangement_uids {
+                    let arrangement = project.composer.arrangements.get(arrangement_uid).unwrap();
+                    let pattern = project.pattern(arrangement.pattern_uid).unwrap();
+                    *counts.entry(channel).or_default() += pattern.note_count();
+                }
+            }
+        }
+        counts
+    }
+
+    // Every arranged note's (velocity, release velocity), sorted.
+    fn arranged_velocities(project: &Project) -> Vec<(u8, u8)> {
+        let mut velocities: Vec<(u8, u8)> = project
+            .composer
+            .arrangements
+            .values()
+            .flat_map(|arrangement| {
+                project
+                    .pattern(arrangement.pattern_uid)
+                    .unwrap()
+                    .notes()
+                    .iter()
+                    .map(|note| (note.velocity, note.release_velocity))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        velocities.sort();
+        velocities
+    }
+
+    #[test]
+    fn smf_import_matches_dumps() {
+        for filename in [
+            "major-scale.mid",
+            "major-scale-overlapping.mid",
+            "major-scale-spaced-notes.mid",
+            "major-scale-spaced-identical-notes.mid",
+            "middle-a-for-six-seconds.mid",
+            "middle-c-for-six-seconds.mid",
+            "multi-channel.mid",
+            "sound_of_music.mid",
+            "clouds.mid",
+            "jingle_bells.mid",
+            "d-gonnamakeyousweat.mid",
+        ] {
+            let project = Project::new_from_smf_path(&midi_test_data_path(filename))
+                .unwrap_or_else(|e| panic!("importing {filename} failed: {e:?}"));
+            assert_eq!(
+                imported_note_counts(&project),
+                note_on_counts_from_dump(&format!("{filename}.txt")),
+                "{filename}: imported notes per channel should match the dump"
+            );
+        }
+    }
+
+    #[test]
+    fn smf_import_applies_tempo_and_time_signature() {
+        let project = Project::new_from_smf_path(&midi_test_data_path("major-scale.mid")).unwrap();
+        assert_eq!(
+            project.tempo(),
+            Tempo(60.0),
+            "1,000,000 usec per beat should be 60 BPM"
+        );
+        assert_eq!(project.time_signature(), TimeSignature::COMMON_TIME);
+        assert_eq!(
+            project.title,
+            Some(ProjectTitle("major-scale".to_string())),
+            "title should come from the filename"
+        );
+
+        let project =
+            Project::new_from_smf_path(&midi_test_data_path("sound_of_music.mid")).unwrap();
+        assert_eq!(project.tempo(), Tempo(120.0));
+    }
+
+    #[test]
+    fn smf_import_cuts_patterns_at_bars() {
+        let project = Project::new_from_smf_path(&midi_test_data_path("major-scale.mid")).unwrap();
+        assert_eq!(project.track_uids().len(), 1, "one SMF channel, one track");
+        let track_uid = project.track_uids()[0];
+        let arrangement_uids = project
+            .composer
+            .tracks_to_ordered_arrangement_uids
+            .get(&track_uid)
+            .unwrap();
+        assert_eq!(
+            arrangement_uids.len(),
+            2,
+            "eight one-beat notes in 4/4 should become two one-bar arrangements"
+        );
+        let positions: Vec<MusicalTime> = arrangement_uids
+            .iter()
+            .map(|auid| project.composer.arrangements.get(auid).unwrap().position)
+            .collect();
+        assert_eq!(
+            positions,
+            vec![MusicalTime::START, MusicalTime::new_with_beats(4)]
+        );
+
+        let project =
+            Project::new_from_smf_path(&midi_test_data_path("middle-c-for-six-seconds.mid"))
+                .unwrap();
+        let track_uid = project.track_uids()[0];
+        let arrangement_uids = project
+            .composer
+            .tracks_to_ordered_arrangement_uids
+            .get(&track_uid)
+            .unwrap();
+        assert_eq!(
+            arrangement_uids.len(),
+            1,
+            "a note that crosses a bar line shouldn't be split"
+        );
+        let arrangement = project
+            .composer
+            .arrangements
+            .get(&arrangement_uids[0])
+            .unwrap();
+        assert_eq!(
+            arrangement.duration,
+            MusicalTime::new_with_beats(8),
+            "a six-beat note should produce a two-bar pattern"
+        );
+
+        let project =
+            Project::new_from_smf_path(&midi_test_data_path("multi-channel.mid")).unwrap();
+        assert_eq!(
+            project.track_uids().len(),
+            3,
+            "each channel in the SMF track should become its own track"
+        );
+    }
+
+    #[test]
+    fn smf_import_reuses_identical_patterns() {
+        // Two bars of the same whole-bar note, then a bar with a different one.
+        let mut track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500000))),
+        }];
+        for key in [60, 60, 62] {
+            track.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message: midly::MidiMessage::NoteOn {
+                        key: u7::new(key),
+                        vel: u7::new(100),
+                    },
+                },
+            });
+            track.push(TrackEvent {
+                delta: u28::new(480 * 4),
+                kind: TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message: midly::MidiMessage::NoteOff {
+                        key: u7::new(key),
+                        vel: u7::new(0),
+                    },
+                },
+            });
+        }
+        track.push(TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(track);
+        let mut bytes = Vec::default();
+        smf.write_std(&mut bytes).unwrap();
+
+        let project = Project::new_from_smf(&bytes).unwrap();
+        assert_eq!(project.tempo(), Tempo(120.0));
+        assert_eq!(
+            project.composer.arrangements.len(),
+            3,
+            "each bar should be arranged"
+        );
+        assert_eq!(
+            project.composer.patterns.len(),
+            2,
+            "identical bars should share a pattern"
+        );
+    }
+
+    #[test]
+    fn smf_export_writes_conductor_and_track_names() {
+        let mut project =
+            Project::new_from_smf_path(&midi_test_data_path("multi-channel.mid")).unwrap();
+        project.update_tempo(Tempo(90.0));
+        project.update_time_signature(TimeSignature::new_with(3, 8).unwrap());
+        let bytes = project.to_smf().unwrap();
+
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(
+            smf.tracks.len(),
+            project.track_uids().len() + 1,
+            "conductor track plus one per project track"
+        );
+        let conductor = &smf.tracks[0];
+        assert!(conductor.iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::TrackName(name)) if name == b"multi-channel"
+        )));
+        assert!(conductor.iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::Tempo(usec)) if usec.as_int() == 666667
+        )));
+        assert!(conductor.iter().any(|e| matches!(
+            e.kind,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(3, 3, _, _))
+        )));
+        for (track_uid, smf_track) in project.track_uids().iter().zip(smf.tracks.iter().skip(1)) {
+            let title = project.track_titles.get(track_uid).unwrap();
+            assert!(smf_track.iter().any(|e| matches!(
+                e.kind,
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) if name == title.0.as_bytes()
+            )));
+        }
+    }
+
+    #[test]
+    fn smf_export_round_trips() {
+        for filename in ["major-scale.mid", "multi-channel.mid", "jingle_bells.mid"] {
+            let project = Project::new_from_smf_path(&midi_test_data_path(filename)).unwrap();
+            let reimported = Project::new_from_smf(&project.to_smf().unwrap()).unwrap();
+            assert_eq!(reimported.tempo(), project.tempo(), "{filename}");
+            assert_eq!(
+                reimported.time_signature(),
+                project.time_signature(),
+                "{filename}"
+            );
+            assert_eq!(
+                imported_note_counts(&reimported),
+                imported_note_counts(&project),
+                "{filename}: notes per channel should survive a round trip"
+            );
+            assert_eq!(
+                arranged_velocities(&reimported),
+                arranged_velocities(&project),
+                "{filename}: velocities should survive a round trip"
+            );
+        }
+
+        let project = Project::new_from_smf_path(&midi_test_data_path("major-scale.mid")).unwrap();
+        let reimported = Project::new_from_smf(&project.to_smf().unwrap()).unwrap();
+        let positions = |project: &Project| -> Vec<MusicalTime> {
+            let track_uid = project.track_uids()[0];
+            project
+                .composer
+                .tracks_to_ordered_arrangement_uids
+                .get(&track_uid)
+                .unwrap()
+                .iter()
+                .map(|auid| project.composer.arrangements.get(auid).unwrap().position)
+                .collect()
+        };
+        assert_eq!(positions(&reimported), positions(&project));
+    }
+
+    #[test]
+    fn smf_export_handles_out_of_range_notes() {
+        let mut project = Project::default();
+        let track_uid = project.new_midi_track().unwrap();
+        let pattern_uid = project
+            .add_pattern(
+                PatternBuilder::default()
+                    .note(Note::new_with(
+                        60,
+                        MusicalTime::START,
+                        MusicalTime::DURATION_QUARTER,
+                    ))
+                    .note(Note::new_with(
+                        200,
+                        MusicalTime::ONE_BEAT,
+                        MusicalTime::DURATION_QUARTER,
+                    ))
+                    .build()
+                    .unwrap(),
+                None,
+            )
+            .unwrap();
+        let arrangement_uid = project
+            .arrange_pattern(track_uid, pattern_uid, None, MusicalTime::START)
+            .unwrap();
+
+        let reimported = Project::new_from_smf(&project.to_smf().unwrap()).unwrap();
+        assert_eq!(
+            imported_note_counts(&reimported).values().sum::<usize>(),
+            1,
+            "a key that MIDI can't express should be left out"
+        );
+
+        project
+            .composer
+            .arrangements
+            .get_mut(&arrangement_uid)
+            .unwrap()
+            .midi_channel = MidiChannel(16);
+        assert!(
+            project.to_smf().is_err(),
+            "a MIDI channel that SMF can't express should be an error, not a panic"
+        );
+    }
+
+    #[test]
+    fn smf_export_skips_empty_tracks() {
+        let mut project = Project::default();
+        let _ = project.new_midi_track().unwrap();
+        let smf_bytes = project.to_smf().unwrap();
+        let smf = Smf::parse(&smf_bytes).unwrap();
+        assert_eq!(
+            smf.tracks.len(),
+            1,
+            "a project without arrangements should export only the conductor track"
+        );
+    }
+
+    #[test]
+    fn smf_tempo_changes_round_trip() {
+        let mut project = Project::default();
+        project.update_tempo(Tempo(100.0));
+        let _ = project.tempo_map.add_event(TempoEvent::new_step(
+            MusicalTime::new_with_beats(8),
+            Tempo(150.0),
+        ));
+        let _ = project.tempo_map.add_event(TempoEvent::new_ramp(
+            MusicalTime::new_with_beats(16),
+            Tempo(75.0),
+        ));
+        let reimported = Project::new_from_smf(&project.to_smf().unwrap()).unwrap();
+        assert_eq!(reimported.tempo(), Tempo(100.0));
+        assert_eq!(
+            reimported.tempo_at(MusicalTime::new_with_beats(7)),
+            Tempo(100.0)
+        );
+        assert_eq!(
+            reimported.tempo_at(MusicalTime::new_with_beats(20)),
+            Tempo(75.0),
+            "the ramp should end where it did"
+        );
+        for beats in [4, 8, 12, 16, 20] {
+            let time = MusicalTime::new_with_beats(beats);
+            let expected = project.tempo_map.time_to_seconds(project.tempo(), time).0;
+            let actual = reimported
+                .tempo_map
+                .time_to_seconds(reimported.tempo(), time)
+                .0;
+            assert!(
+                (expected - actual).abs() < 0.01,
+                "a ramp approximated by steps should keep nearly the same timing (beat {beats}: {expected} vs. {actual})"
+            );
+        }
+    }
+
+    #[test]
+    fn smf_time_signature_changes_round_trip() {
+        let mut project =
+            Project::new_from_smf_path(&midi_test_data_path("major-scale.mid")).unwrap();
+        let seven_eight = TimeSignature::new_with(7, 8).unwrap();
+        let _ = project.time_signature_map.add_change(2, seven_eight);
+        let _ = project
+            .time_signature_map
+            .add_change(5, TimeSignature::COMMON_TIME);
+        let reimported = Project::new_from_smf(&project.to_smf().unwrap()).unwrap();
+        assert_eq!(reimported.time_signature(), project.time_signature());
+        assert_eq!(
+            reimported.time_signature_map.changes(),
+            project.time_signature_map.changes()
+        );
+        assert_eq!(
+            imported_note_counts(&reimported),
+            imported_note_counts(&project)
+        );
+        for arrangement in reimported.composer.arrangements.values() {
+            let bar = reimported.bar_at(arrangement.position);
+            assert_eq!(
+                reimported.bar_start(bar),
+                arrangement.position,
+                "imported patterns should start on the new meter's bar lines"
+            );
+            let pattern = reimported
+                .composer
+                .patterns
+                .get(&arrangement.pattern_uid)
+                .unwrap();
+            assert_eq!(
+                pattern.time_signature(),
+                reimported.time_signature_at(arrangement.position)
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Support for exporting each track to its own audio file.
+
+use super::{Dither, WavSampleFormat};
+use crate::prelude::*;
+use derivative::Derivative;
+use rustc_hash::FxHashMap;
+
+/// Describes which tracks [Project::export_stems_to_wav()] exports, and what
+/// each stem contains.
+#[derive(Debug, Clone, Derivative)]
+#[derivative(Default)]
+pub struct StemExportOptions {
+    /// The tracks to export, or None for every track that isn't an aux track.
+    /// A group track's stem includes its children, so exporting both a group
+    /// and its children exports those children twice.
+    pub track_uids: Option<Vec<TrackUid>>,
+
+    /// Whether each stem is captured after the track's fader and pan, as the
+    /// mix hears it, or straight out of the track's last effect.
+    #[derivative(Default(value = "true"))]
+    pub is_post_fader: bool,
+
+    /// Whether to also export the aux tracks that the exported tracks send to,
+    /// so that send effects have stems of their own. Without them, each stem
+    /// holds only its own track's signal. Either way, the stems don't pass
+    /// through the master track's effects, so they won't add up exactly to
+    /// the full mix if it has any.
+    #[derivative(Default(value = "true"))]
+    pub include_sends: bool,
+
+    pub sample_format: WavSampleFormat,
+    /// Ignored for [WavSampleFormat::Float32].
+    pub dither: Dither,
+}
+
+/// Collects the output of the tracks being exported as stems, one
+/// [Generates::generate()] at a time.
+#[derive(Debug, Default)]
+pub(super) struct StemCapture {
+    pub(super) is_post_fader: bool,
+    pub(super) buffers: FxHashMap<TrackUid, Vec<StereoSample>>,
+}
+impl StemCapture {
+    pub(super) fn new_with(track_uids: &[TrackUid], is_post_fader: bool) -> Self {
+        Self {
+            is_post_fader,
+            buffers: track_uids
+                .iter()
+                .map(|track_uid| (*track_uid, Vec::default()))
+                .collect(),
+        }
+    }
+}
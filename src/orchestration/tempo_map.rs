@@ -0,0 +1,517 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+use crate::prelude::*;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// How the tempo arrives at a [TempoEvent]'s tempo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TempoTransition {
+    /// The tempo jumps to the new value at the event's time.
+    #[default]
+    Step,
+    /// The tempo changes linearly (in beats) from the previous event's tempo,
+    /// arriving at the new value at the event's time. This is how to write a
+    /// ritardando or accelerando.
+    Ramp,
+}
+
+/// A change in tempo at a point on the timeline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TempoEvent {
+    /// When the new tempo takes effect.
+    pub time: MusicalTime,
+    /// The new tempo.
+    pub tempo: Tempo,
+    /// How the tempo gets there.
+    #[serde(default)]
+    pub transition: TempoTransition,
+}
+impl TempoEvent {
+    /// Creates a [TempoEvent] that jumps to the given tempo at the given time.
+    pub fn new_step(time: MusicalTime, tempo: Tempo) -> Self {
+        Self {
+            time,
+            tempo,
+            transition: TempoTransition::Step,
+        }
+    }
+
+    /// Creates a [TempoEvent] that ramps to the given tempo, arriving at the
+    /// given time.
+    pub fn new_ramp(time: MusicalTime, tempo: Tempo) -> Self {
+        Self {
+            time,
+            tempo,
+            transition: TempoTransition::Ramp,
+        }
+    }
+}
+
+/// A [TempoMap] is the list of tempo changes over a project's timeline. The
+/// tempo before the first event is the project's own tempo (the one on its
+/// Transport), so an empty map means a constant tempo.
+///
+/// The map is the authority for converting between frames and [MusicalTime]
+/// once tempo can change. [MusicalTime::new_with_frames()] and
+/// [MusicalTime::as_frames()] are correct only for a constant tempo.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TempoMap {
+    // Always sorted by time, with at most one event at any time, and every
+    // tempo in range. add_event() and after_deser() keep it that way.
+    events: Vec<TempoEvent>,
+}
+impl TempoMap {
+    /// Adds a tempo change, replacing any existing change at the same time.
+    pub fn add_event(&mut self, event: TempoEvent) -> anyhow::Result<()> {
+        if !Self::is_valid_tempo(event.tempo) {
+            return Err(anyhow!("tempo {} is out of range", event.tempo));
+        }
+        match self.events.binary_search_by_key(&event.time, |e| e.time) {
+            Ok(index) => self.events[index] = event,
+            Err(index) => self.events.insert(index, event),
+        }
+        Ok(())
+    }
+
+    /// Removes the tempo change at the given time, if there is one.
+    pub fn remove_event(&mut self, time: MusicalTime) -> Option<TempoEvent> {
+        if let Ok(index) = self.events.binary_search_by_key(&time, |e| e.time) {
+            Some(self.events.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Removes all tempo changes.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Returns all the tempo changes, in time order.
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
+    /// Whether the map has no tempo changes.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the tempo in effect at the given time, given the tempo that
+    /// applies before the first change.
+    pub fn tempo_at(&self, initial_tempo: Tempo, time: MusicalTime) -> Tempo {
+        let time = Self::time_as_beats(time);
+        for segment in self.segments(initial_tempo) {
+            if time < segment.end_beat {
+                return Tempo(segment.tempo_at(time));
+            }
+        }
+        unreachable!("the last segment never ends")
+    }
+
+    /// Returns how many seconds elapse from time zero until the given time.
+    pub fn time_to_seconds(&self, initial_tempo: Tempo, time: MusicalTime) -> Seconds {
+        let time = Self::time_as_beats(time);
+        let mut seconds = 0.0;
+        for segment in self.segments(initial_tempo) {
+            if time <= segment.end_beat {
+                seconds += segment.seconds_until(time);
+                break;
+            }
+            seconds += segment.seconds_until(segment.end_beat);
+        }
+        Seconds(seconds)
+    }
+
+    /// Returns the [MusicalTime] that the given number of seconds after time
+    /// zero reaches.
+    pub fn seconds_to_time(&self, initial_tempo: Tempo, seconds: Seconds) -> MusicalTime {
+        let mut remaining = seconds.0.max(0.0);
+        for segment in self.segments(initial_tempo) {
+            let segment_seconds = segment.seconds_until(segment.end_beat);
+            if remaining < segment_seconds {
+                return Self::beats_as_time(segment.beat_after(remaining));
+            }
+            remaining -= segment_seconds;
+        }
+        unreachable!("the last segment never ends")
+    }
+
+    /// Converts a frame count since time zero into a [MusicalTime].
+    pub fn frames_to_time(
+        &self,
+        initial_tempo: Tempo,
+        sample_rate: SampleRate,
+        frames: usize,
+    ) -> MusicalTime {
+        self.seconds_to_time(initial_tempo, Seconds(frames as f64 / sample_rate.0 as f64))
+    }
+
+    /// Converts a [MusicalTime] into a frame count since time zero.
+    pub fn time_to_frames(
+        &self,
+        initial_tempo: Tempo,
+        sample_rate: SampleRate,
+        time: MusicalTime,
+    ) -> usize {
+        (self.time_to_seconds(initial_tempo, time).0 * sample_rate.0 as f64).round() as usize
+    }
+
+    /// Returns the position, in beats, that playback reaches after the given
+    /// number of seconds from the given position, following the tempo along
+    /// the way.
+    pub(crate) fn beats_after(
+        &self,
+        initial_tempo: Tempo,
+        start_beat: f64,
+        seconds: Seconds,
+    ) -> f64 {
+        let mut remaining = seconds.0.max(0.0);
+        for segment in self.segments(initial_tempo) {
+            if start_beat >= segment.end_beat {
+                continue;
+            }
+            let from = segment.seconds_until(start_beat.max(segment.start_beat));
+            let available = segment.seconds_until(segment.end_beat) - from;
+            if remaining < available {
+                return segment.beat_after(from + remaining);
+            }
+            remaining -= available;
+        }
+        unreachable!("the last segment never ends")
+    }
+
+    /// Returns how many seconds it takes to play from one position, in beats,
+    /// to a later one.
+    pub(crate) fn seconds_between(
+        &self,
+        initial_tempo: Tempo,
+        start_beat: f64,
+        end_beat: f64,
+    ) -> Seconds {
+        let mut seconds = 0.0;
+        for segment in self.segments(initial_tempo) {
+            if start_beat >= segment.end_beat {
+                continue;
+            }
+            let from = segment.seconds_until(start_beat.max(segment.start_beat));
+            if end_beat <= segment.end_beat {
+                seconds += segment.seconds_until(end_beat) - from;
+                break;
+            }
+            seconds += segment.seconds_until(segment.end_beat) - from;
+        }
+        Seconds(seconds.max(0.0))
+    }
+
+    pub(crate) fn time_as_beats(time: MusicalTime) -> f64 {
+        time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+    }
+
+    pub(crate) fn beats_as_time(beats: f64) -> MusicalTime {
+        MusicalTime::new_with_units((beats * MusicalTime::UNITS_IN_BEAT as f64).round() as usize)
+    }
+
+    // Breaks the timeline into spans whose tempo is either constant or changes
+    // linearly. The last one goes on forever. This runs on every conversion,
+    // so it walks the events in place rather than collecting them.
+    fn segments(&self, initial_tempo: Tempo) -> impl Iterator<Item = TempoSegment> + '_ {
+        let (last_beat, last_tempo) = self
+            .events
+            .last()
+            .map(|event| (Self::time_as_beats(event.time), event.tempo.0))
+            .unwrap_or((0.0, initial_tempo.0));
+        let mut start_beat = 0.0;
+        let mut start_tempo = initial_tempo.0;
+        self.events
+            .iter()
+            .filter_map(move |event| {
+                let end_beat = Self::time_as_beats(event.time);
+                let end_tempo = match event.transition {
+                    TempoTransition::Step => start_tempo,
+                    TempoTransition::Ramp => event.tempo.0,
+                };
+                let segment = (end_beat > start_beat).then_some(TempoSegment {
+                    start_beat,
+                    end_beat,
+                    start_tempo,
+                    end_tempo,
+                });
+                start_beat = end_beat;
+                start_tempo = event.tempo.0;
+                segment
+            })
+            .chain(std::iter::once(TempoSegment {
+                start_beat: last_beat,
+                end_beat: f64::INFINITY,
+                start_tempo: last_tempo,
+                end_tempo: last_tempo,
+            }))
+    }
+
+    fn is_valid_tempo(tempo: Tempo) -> bool {
+        tempo.0 > 0.0 && tempo.0 <= Tempo::MAX_VALUE
+    }
+}
+impl Serializable for TempoMap {
+    // A hand-edited or corrupt project file can hold events that add_event()
+    // would never have allowed. Drop the bad tempos and restore the ordering
+    // the conversions rely on, keeping the last of any events at the same
+    // time, as add_event() would have.
+    fn after_deser(&mut self) {
+        self.events
+            .retain(|event| Self::is_valid_tempo(event.tempo));
+        self.events.sort_by_key(|event| event.time);
+        let mut events: Vec<TempoEvent> = Vec::with_capacity(self.events.len());
+        for event in self.events.drain(..) {
+            match events.last_mut() {
+                Some(last) if last.time == event.time => *last = event,
+                _ => events.push(event),
+            }
+        }
+        self.events = events;
+    }
+}
+
+// A span of the timeline over which tempo (in BPM) changes linearly with beats.
+#[derive(Debug)]
+struct TempoSegment {
+    start_beat: f64,
+    end_beat: f64,
+    start_tempo: f64,
+    end_tempo: f64,
+}
+impl TempoSegment {
+    // How much the tempo changes per beat.
+    fn slope(&self) -> f64 {
+        if self.end_beat.is_finite() && self.end_beat > self.start_beat {
+            (self.end_tempo - self.start_tempo) / (self.end_beat - self.start_beat)
+        } else {
+            0.0
+        }
+    }
+
+    fn tempo_at(&self, beat: f64) -> f64 {
+        self.start_tempo + self.slope() * (beat - self.start_beat)
+    }
+
+    // Seconds from the start of this segment until the given beat. With tempo
+    // T(b) = T0 + k(b - b0), elapsed time is the integral of 60/T(b), which is
+    // 60 ln(T(b)/T0) / k, or just 60(b - b0)/T0 when the tempo is constant.
+    fn seconds_until(&self, beat: f64) -> f64 {
+        let beats = beat - self.start_beat;
+        let slope = self.slope();
+        if slope.abs() < f64::EPSILON {
+            60.0 * beats / self.start_tempo
+        } else {
+            60.0 * (self.tempo_at(beat) / self.start_tempo).ln() / slope
+        }
+    }
+
+    // The inverse of seconds_until().
+    fn beat_after(&self, seconds: f64) -> f64 {
+        let slope = self.slope();
+        if slope.abs() < f64::EPSILON {
+            self.start_beat + seconds * self.start_tempo / 60.0
+        } else {
+            self.start_beat + self.start_tempo * ((seconds * slope / 60.0).exp() - 1.0) / slope
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_tempo_map_is_constant() {
+        let map = TempoMap::default();
+        let tempo = Tempo(120.0);
+        let sample_rate = SampleRate::from(44100);
+        assert_eq!(
+            map.tempo_at(tempo, MusicalTime::new_with_beats(1000)),
+            tempo
+        );
+        assert_eq!(
+            map.frames_to_time(tempo, sample_rate, 44100),
+            MusicalTime::new_with_beats(2),
+            "two beats per second at 120 BPM"
+        );
+        assert_eq!(
+            map.time_to_frames(tempo, sample_rate, MusicalTime::new_with_beats(2)),
+            44100
+        );
+        for frames in [0, 1, 100, 12345, 44100 * 60] {
+            let map_units = map.frames_to_time(tempo, sample_rate, frames).total_units();
+            let constant_units =
+                MusicalTime::new_with_frames(tempo, sample_rate, frames).total_units();
+            assert!(
+                map_units.abs_diff(constant_units) <= 1,
+                "with no changes, the map should agree with the constant-tempo conversion"
+            );
+        }
+    }
+
+    #[test]
+    fn tempo_map_steps() {
+        let mut map = TempoMap::default();
+        assert!(map
+            .add_event(TempoEvent::new_step(
+                MusicalTime::new_with_beats(4),
+                Tempo(120.0)
+            ))
+            .is_ok());
+        let initial = Tempo(60.0);
+        assert_eq!(
+            map.tempo_at(initial, MusicalTime::new_with_beats(3)),
+            initial
+        );
+        assert_eq!(
+            map.tempo_at(initial, MusicalTime::new_with_beats(4)),
+            Tempo(120.0)
+        );
+
+        // Four beats at 60 BPM, then four at 120.
+        assert_eq!(
+            map.time_to_seconds(initial, MusicalTime::new_with_beats(8)),
+            Seconds(6.0)
+        );
+        assert_eq!(
+            map.seconds_to_time(initial, Seconds(6.0)),
+            MusicalTime::new_with_beats(8)
+        );
+        assert_eq!(
+            map.frames_to_time(initial, SampleRate::from(1000), 5000),
+            MusicalTime::new_with_beats(6)
+        );
+    }
+
+    #[test]
+    fn tempo_map_ramps() {
+        let mut map = TempoMap::default();
+        let _ = map.add_event(TempoEvent::new_ramp(
+            MusicalTime::new_with_beats(8),
+            Tempo(60.0),
+        ));
+        let initial = Tempo(120.0);
+        assert_eq!(
+            map.tempo_at(initial, MusicalTime::new_with_beats(4)),
+            Tempo(90.0),
+            "halfway through the ramp should be halfway between tempos"
+        );
+        assert_eq!(
+            map.tempo_at(initial, MusicalTime::new_with_beats(100)),
+            Tempo(60.0)
+        );
+
+        // A ritardando should take longer than the starting tempo and less
+        // time than the ending tempo would.
+        let seconds = map
+            .time_to_seconds(initial, MusicalTime::new_with_beats(8))
+            .0;
+        assert!(seconds > 4.0 && seconds < 8.0);
+        let expected = 60.0 * 8.0 / (60.0 - 120.0) * (60.0f64 / 120.0).ln();
+        assert!((seconds - expected).abs() < 1e-9);
+
+        for beats in [1, 3, 8, 13] {
+            let time = MusicalTime::new_with_beats(beats);
+            let seconds = map.time_to_seconds(initial, time);
+            assert_eq!(
+                map.seconds_to_time(initial, seconds),
+                time,
+                "conversions should round-trip"
+            );
+        }
+    }
+
+    #[test]
+    fn tempo_map_editing() {
+        let mut map = TempoMap::default();
+        assert!(map
+            .add_event(TempoEvent::new_step(MusicalTime::START, Tempo(0.0)))
+            .is_err());
+        let _ = map.add_event(TempoEvent::new_step(
+            MusicalTime::new_with_beats(8),
+            Tempo(100.0),
+        ));
+        let _ = map.add_event(TempoEvent::new_step(
+            MusicalTime::new_with_beats(4),
+            Tempo(90.0),
+        ));
+        let _ = map.add_event(TempoEvent::new_ramp(
+            MusicalTime::new_with_beats(8),
+            Tempo(110.0),
+        ));
+        assert_eq!(map.events().len(), 2, "an event at the same time replaces");
+        assert_eq!(map.events()[0].time, MusicalTime::new_with_beats(4));
+        assert_eq!(map.events()[1].tempo, Tempo(110.0));
+
+        assert!(map.remove_event(MusicalTime::new_with_beats(4)).is_some());
+        assert!(map.remove_event(MusicalTime::new_with_beats(4)).is_none());
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn advancing_in_steps_matches_whole_conversion() {
+        let mut map = TempoMap::default();
+        let _ = map.add_event(TempoEvent::new_step(
+            MusicalTime::new_with_beats(2),
+            Tempo(90.0),
+        ));
+        let _ = map.add_event(TempoEvent::new_ramp(
+            MusicalTime::new_with_beats(10),
+            Tempo(150.0),
+        ));
+        let initial = Tempo(120.0);
+
+        let mut beat = 0.0;
+        for _ in 0..1000 {
+            beat = map.beats_after(initial, beat, Seconds(0.01));
+        }
+        let whole = TempoMap::time_as_beats(map.seconds_to_time(initial, Seconds(10.0)));
+        assert!(
+            (beat - whole).abs() < 0.001,
+            "a thousand small steps should land where one big one does, but {beat} != {whole}"
+        );
+
+        let seconds = map.seconds_between(initial, 1.0, 12.0);
+        assert!(
+            (map.beats_after(initial, 1.0, seconds) - 12.0).abs() < 0.000001,
+            "seconds_between() should be the inverse of beats_after()"
+        );
+    }
+
+    #[test]
+    fn tempo_map_repairs_itself_after_deserialization() {
+        // What a hand-edited project file might hold.
+        let mut map = TempoMap {
+            events: vec![
+                TempoEvent::new_step(MusicalTime::new_with_beats(8), Tempo(100.0)),
+                TempoEvent::new_step(MusicalTime::new_with_beats(4), Tempo(0.0)),
+                TempoEvent::new_step(MusicalTime::new_with_beats(2), Tempo(80.0)),
+                TempoEvent::new_ramp(MusicalTime::new_with_beats(2), Tempo(90.0)),
+            ],
+        };
+        map.after_deser();
+        assert_eq!(
+            map.events()
+                .iter()
+                .map(|event| (event.time, event.tempo))
+                .collect::<Vec<_>>(),
+            vec![
+                (MusicalTime::new_with_beats(2), Tempo(90.0)),
+                (MusicalTime::new_with_beats(8), Tempo(100.0)),
+            ],
+            "events should be sorted, with zero tempos dropped and the last duplicate kept"
+        );
+        assert!(map
+            .time_to_seconds(Tempo(120.0), MusicalTime::new_with_beats(100))
+            .0
+            .is_finite());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+use crate::prelude::*;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// A change of meter at the start of a bar.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TimeSignatureChange {
+    /// The zero-based bar where the new time signature starts.
+    pub bar: usize,
+    /// The new time signature.
+    pub time_signature: TimeSignature,
+}
+
+/// A [TimeSignatureMap] is the list of meter changes over a project's
+/// timeline. The time signature before the first change is the project's own
+/// (the one on its Transport), so an empty map means a constant meter.
+///
+/// Changes are kept by bar rather than by [MusicalTime] so that they always
+/// land on a bar line, even after an earlier change makes the bars before them
+/// longer or shorter. [MusicalTime::total_bars()] and
+/// [MusicalTime::quantized_to_measure()] know only a single time signature;
+/// use this map's versions when the meter can change.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TimeSignatureMap {
+    // Always sorted by bar, with at most one change per bar.
+    changes: Vec<TimeSignatureChange>,
+}
+impl TimeSignatureMap {
+    /// Changes the time signature starting at the given bar, replacing any
+    /// existing change there. Bar zero belongs to the project's own time
+    /// signature, so it can't be changed here.
+    pub fn add_change(&mut self, bar: usize, time_signature: TimeSignature) -> anyhow::Result<()> {
+        if bar == 0 {
+            return Err(anyhow!(
+                "the first bar's time signature is the project's time signature"
+            ));
+        }
+        let change = TimeSignatureChange {
+            bar,
+            time_signature,
+        };
+        match self.changes.binary_search_by_key(&bar, |c| c.bar) {
+            Ok(index) => self.changes[index] = change,
+            Err(index) => self.changes.insert(index, change),
+        }
+        Ok(())
+    }
+
+    /// Removes the change at the given bar, if there is one.
+    pub fn remove_change(&mut self, bar: usize) -> Option<TimeSignatureChange> {
+        if let Ok(index) = self.changes.binary_search_by_key(&bar, |c| c.bar) {
+            Some(self.changes.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Removes all changes.
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Returns all the changes, in bar order.
+    pub fn changes(&self) -> &[TimeSignatureChange] {
+        &self.changes
+    }
+
+    /// Whether the map has no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the time signature in effect at the given bar.
+    pub fn time_signature_at_bar(&self, initial: &TimeSignature, bar: usize) -> TimeSignature {
+        self.changes
+            .iter()
+            .take_while(|c| c.bar <= bar)
+            .last()
+            .map(|c| c.time_signature)
+            .unwrap_or(*initial)
+    }
+
+    /// Returns the time signature in effect at the given time.
+    pub fn time_signature_at(&self, initial: &TimeSignature, time: MusicalTime) -> TimeSignature {
+        self.time_signature_at_bar(initial, self.bar_at(initial, time))
+    }
+
+    /// Returns when the given zero-based bar starts.
+    pub fn bar_start(&self, initial: &TimeSignature, bar: usize) -> MusicalTime {
+        let mut start = MusicalTime::START;
+        let mut segment_bar = 0;
+        let mut segment_time_signature = *initial;
+        for change in self.changes.iter().take_while(|c| c.bar <= bar) {
+            start += MusicalTime::new_with_bars(&segment_time_signature, change.bar - segment_bar);
+            segment_bar = change.bar;
+            segment_time_signature = change.time_signature;
+        }
+        start + MusicalTime::new_with_bars(&segment_time_signature, bar - segment_bar)
+    }
+
+    /// Returns the zero-based bar that contains the given time.
+    pub fn bar_at(&self, initial: &TimeSignature, time: MusicalTime) -> usize {
+        let mut start = MusicalTime::START;
+        let mut segment_bar = 0;
+        let mut segment_time_signature = *initial;
+        for change in self.changes.iter() {
+            let change_start = start
+                + MusicalTime::new_with_bars(&segment_time_signature, change.bar - segment_bar);
+            if time < change_start {
+                break;
+            }
+            start = change_start;
+            segment_bar = change.bar;
+            segment_time_signature = change.time_signature;
+        }
+        segment_bar + (time - start).total_bars(&segment_time_signature)
+    }
+
+    /// Returns the start of the bar closest to the given time.
+    pub fn quantized_to_measure(&self, initial: &TimeSignature, time: MusicalTime) -> MusicalTime {
+        let bar = self.bar_at(initial, time);
+        let start = self.bar_start(initial, bar);
+        let end = self.bar_start(initial, bar + 1);
+        if time - start < end - time {
+            start
+        } else {
+            end
+        }
+    }
+
+    /// Returns each bar that starts within the given range, along with its
+    /// start time.
+    pub fn bar_starts(
+        &self,
+        initial: &TimeSignature,
+        range: &TimeRange,
+    ) -> Vec<(usize, MusicalTime)> {
+        let mut bar = self.bar_at(initial, range.0.start);
+        let mut bar_starts = Vec::default();
+        loop {
+            let start = self.bar_start(initial, bar);
+            if start >= range.0.end {
+                break;
+            }
+            if start >= range.0.start {
+                bar_starts.push((bar, start));
+            }
+            bar += 1;
+        }
+        bar_starts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two bars of 4/4, then 7/8 from the third bar on.
+    fn four_four_then_seven_eight() -> TimeSignatureMap {
+        let mut map = TimeSignatureMap::default();
+        assert!(map
+            .add_change(2, TimeSignature::new_with(7, 8).unwrap())
+            .is_ok());
+        map
+    }
+
+    #[test]
+    fn empty_time_signature_map_is_constant() {
+        let map = TimeSignatureMap::default();
+        let ts = TimeSignature::COMMON_TIME;
+        for bar in [0, 1, 17] {
+            assert_eq!(
+                map.bar_start(&ts, bar),
+                MusicalTime::new_with_bars(&ts, bar)
+            );
+        }
+        let time = MusicalTime::new_with_beats(13);
+        assert_eq!(map.bar_at(&ts, time), time.total_bars(&ts));
+        assert_eq!(
+            map.quantized_to_measure(&ts, time),
+            time.quantized_to_measure(&ts)
+        );
+        assert!(map.add_change(0, TimeSignature::CUT_TIME).is_err());
+    }
+
+    #[test]
+    fn time_signature_map_changes_bar_lengths() {
+        let map = four_four_then_seven_eight();
+        let ts = TimeSignature::COMMON_TIME;
+        let seven_eight = TimeSignature::new_with(7, 8).unwrap();
+
+        assert_eq!(map.bar_start(&ts, 2), MusicalTime::new_with_beats(8));
+        assert_eq!(
+            map.bar_start(&ts, 3),
+            MusicalTime::new_with_beats(8) + MusicalTime::new_with_bars(&seven_eight, 1)
+        );
+        assert_eq!(map.bar_at(&ts, MusicalTime::new_with_beats(7)), 1);
+        assert_eq!(map.bar_at(&ts, MusicalTime::new_with_beats(8)), 2);
+        assert_eq!(map.bar_at(&ts, map.bar_start(&ts, 5)), 5);
+        assert_eq!(
+            map.time_signature_at(&ts, MusicalTime::new_with_beats(7)),
+            ts
+        );
+        assert_eq!(
+            map.time_signature_at(&ts, MusicalTime::new_with_beats(100)),
+            seven_eight
+        );
+
+        let starts = map.bar_starts(&ts, &TimeRange(MusicalTime::START..map.bar_start(&ts, 4)));
+        let bars: Vec<usize> = starts.iter().map(|(bar, _)| *bar).collect();
+        assert_eq!(bars, vec![0, 1, 2, 3]);
+        assert_eq!(starts[3].1, map.bar_start(&ts, 3));
+    }
+
+    #[test]
+    fn time_signature_map_quantizes_to_local_bars() {
+        let map = four_four_then_seven_eight();
+        let ts = TimeSignature::COMMON_TIME;
+        let third_bar = map.bar_start(&ts, 2);
+        let fourth_bar = map.bar_start(&ts, 3);
+        assert_eq!(
+            map.quantized_to_measure(&ts, third_bar + MusicalTime::ONE_BEAT),
+            third_bar
+        );
+        assert_eq!(
+            map.quantized_to_measure(&ts, fourth_bar - MusicalTime::ONE_BEAT),
+            fourth_bar,
+            "bars after the change should be as long as the new meter says"
+        );
+    }
+
+    #[test]
+    fn time_signature_map_editing() {
+        let mut map = four_four_then_seven_eight();
+        let _ = map.add_change(2, TimeSignature::CUT_TIME);
+        let _ = map.add_change(1, TimeSignature::new_with(3, 4).unwrap());
+        assert_eq!(map.changes().len(), 2, "a change at the same bar replaces");
+        assert_eq!(map.changes()[0].bar, 1);
+        assert_eq!(map.changes()[1].time_signature, TimeSignature::CUT_TIME);
+        assert!(map.remove_change(1).is_some());
+        assert!(map.remove_change(1).is_none());
+        map.clear();
+        assert!(map.is_empty());
+    }
+}
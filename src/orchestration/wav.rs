@@ -0,0 +1,252 @@
+// Copyright (c) 2024 Mike Tsao. All rights reserved.
+
+//! Support for writing rendered audio to WAV files.
+
+use crate::{
+    cores::effects::{db_to_linear, linear_to_db},
+    prelude::*,
+};
+use ensnare::{prelude::*, util::Rng};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+/// The kind of samples in an exported WAV file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// 16-bit integers, as on a CD.
+    #[default]
+    Int16,
+    /// 24-bit integers, as mastering engineers usually want.
+    Int24,
+    /// 32-bit floating point. It doesn't clip, and it doesn't need dither.
+    Float32,
+}
+impl WavSampleFormat {
+    fn bits_per_sample(&self) -> u16 {
+        match self {
+            WavSampleFormat::Int16 => 16,
+            WavSampleFormat::Int24 => 24,
+            WavSampleFormat::Float32 => 32,
+        }
+    }
+}
+
+/// How to hide the error that comes from rounding samples to an integer
+/// [WavSampleFormat].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Rounds each sample to the nearest integer. Quiet passages can sound
+    /// gritty as the rounding error follows the signal.
+    None,
+    /// Adds triangular (TPDF) noise of up to one step before rounding, which
+    /// turns the rounding error into a steady hiss that doesn't depend on the
+    /// signal.
+    #[default]
+    Triangular,
+    /// Triangular dither whose error is subtracted from the next sample,
+    /// which moves the hiss up to high frequencies where it's harder to hear.
+    NoiseShaped,
+}
+
+/// Sets the level of an export by a measurement of the whole piece.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Scales the export so that its loudest sample is at this level, in dBFS.
+    Peak(ParameterType),
+    /// Scales the export so that its integrated loudness is this many LUFS.
+    /// Loud targets can push peaks past full scale, which clips integer
+    /// formats.
+    Loudness(ParameterType),
+}
+impl Normalization {
+    /// The gain that brings a piece with the given peak amplitude and
+    /// integrated loudness to this target. A silent piece is left alone.
+    pub fn gain_for(&self, peak: f64, integrated_loudness: Option<f64>) -> f64 {
+        let (target, level) = match self {
+            Normalization::Peak(target) => (*target, (peak > 0.0).then(|| linear_to_db(peak))),
+            Normalization::Loudness(target) => (*target, integrated_loudness),
+        };
+        match level {
+            Some(level) if level.is_finite() => db_to_linear(target - level),
+            _ => 1.0,
+        }
+    }
+}
+
+/// Describes the file that [Project::export_to_wav_with()] writes.
+#[derive(Debug, Default, Clone)]
+pub struct WavExportOptions {
+    pub sample_format: WavSampleFormat,
+    /// Ignored for [WavSampleFormat::Float32].
+    pub dither: Dither,
+    /// If set, the project is rendered twice: once to measure it, and again to
+    /// write it at the level that meets the target.
+    pub normalization: Option<Normalization>,
+}
+
+/// Writes [StereoSample]s to a WAV file in a [WavSampleFormat], dithering them
+/// on the way to an integer format.
+pub(super) struct WavSampleWriter<W: Write + Seek> {
+    writer: hound::WavWriter<W>,
+    sample_format: WavSampleFormat,
+    dither: Dither,
+    gain: f64,
+    // Dither noise comes from a seeded generator so that the same project
+    // always exports the same file.
+    rng: Rng,
+    // Each channel's most recent rounding error, for noise shaping.
+    errors: [f64; 2],
+}
+impl WavSampleWriter<BufWriter<File>> {
+    pub(super) fn create(
+        path: &Path,
+        sample_rate: SampleRate,
+        sample_format: WavSampleFormat,
+        dither: Dither,
+        seed: u128,
+    ) -> Result<Self, hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.into(),
+            bits_per_sample: sample_format.bits_per_sample(),
+            sample_format: match sample_format {
+                WavSampleFormat::Int16 | WavSampleFormat::Int24 => hound::SampleFormat::Int,
+                WavSampleFormat::Float32 => hound::SampleFormat::Float,
+            },
+        };
+        Ok(Self {
+            writer: hound::WavWriter::create(path, spec)?,
+            sample_format,
+            dither,
+            gain: 1.0,
+            rng: Rng::new_with_seed(seed),
+            errors: Default::default(),
+        })
+    }
+}
+impl<W: Write + Seek> WavSampleWriter<W> {
+    /// Scales every sample written from now on.
+    pub(super) fn set_gain(&mut self, gain: f64) {
+        self.gain = gain;
+    }
+
+    pub(super) fn write(&mut self, frame: &StereoSample) -> Result<(), hound::Error> {
+        self.write_channel(0, frame.0 .0)?;
+        self.write_channel(1, frame.1 .0)
+    }
+
+    pub(super) fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
+    }
+
+    fn write_channel(&mut self, channel: usize, value: f64) -> Result<(), hound::Error> {
+        let value = value * self.gain;
+        if self.sample_format == WavSampleFormat::Float32 {
+            return self.writer.write_sample(value as f32);
+        }
+        let max = ((1_i32 << (self.sample_format.bits_per_sample() - 1)) - 1) as f64;
+        let quantized = self.quantize(channel, value * max);
+        self.writer
+            .write_sample(quantized.clamp(-max - 1.0, max) as i32)
+    }
+
+    // Rounds a sample that has been scaled to the integer range.
+    fn quantize(&mut self, channel: usize, value: f64) -> f64 {
+        match self.dither {
+            Dither::None => value.round(),
+            Dither::Triangular => (value + self.triangular_noise()).round(),
+            Dither::NoiseShaped => {
+                let shaped = value - self.errors[channel];
+                let quantized = (shaped + self.triangular_noise()).round();
+                self.errors[channel] = quantized - shaped;
+                quantized
+            }
+        }
+    }
+
+    // The sum of two uniform random values has a triangular distribution.
+    fn triangular_noise(&mut self) -> f64 {
+        self.rng.rand_float() - self.rng.rand_float()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn normalization_gain() {
+        assert!(approx_eq!(
+            f64,
+            Normalization::Peak(0.0).gain_for(0.5, None),
+            2.0,
+            epsilon = 0.000001
+        ));
+        assert!(approx_eq!(
+            f64,
+            Normalization::Peak(-6.0).gain_for(0.5, Some(-20.0)),
+            db_to_linear(-6.0) / 0.5,
+            epsilon = 0.000001
+        ));
+        assert!(approx_eq!(
+            f64,
+            Normalization::Loudness(-14.0).gain_for(0.5, Some(-20.0)),
+            db_to_linear(6.0),
+            epsilon = 0.000001
+        ));
+        assert_eq!(
+            Normalization::Peak(0.0).gain_for(0.0, None),
+            1.0,
+            "A silent piece shouldn't be normalized"
+        );
+        assert_eq!(Normalization::Loudness(-14.0).gain_for(0.5, None), 1.0);
+    }
+
+    #[test]
+    fn sample_formats_and_dither() {
+        let path = std::env::temp_dir().join("ensnare-wav-sample-formats.wav");
+        let frames = [StereoSample::from(0.5), StereoSample::from(-2.0)];
+        let write = |sample_format, dither| {
+            let mut writer =
+                WavSampleWriter::create(&path, SampleRate::default(), sample_format, dither, 1)
+                    .unwrap();
+            frames.iter().for_each(|frame| writer.write(frame).unwrap());
+            writer.finalize().unwrap();
+            hound::WavReader::open(&path).unwrap()
+        };
+
+        let mut reader = write(WavSampleFormat::Int24, Dither::None);
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        let samples: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        assert_eq!(
+            samples,
+            vec![4194304, 4194304, -8388608, -8388608],
+            "Samples should be rounded, and out-of-range ones clipped"
+        );
+
+        let mut reader = write(WavSampleFormat::Float32, Dither::Triangular);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(
+            samples,
+            vec![0.5, 0.5, -2.0, -2.0],
+            "Float samples should pass through untouched"
+        );
+
+        for dither in [Dither::Triangular, Dither::NoiseShaped] {
+            let mut reader = write(WavSampleFormat::Int16, dither);
+            let samples: Vec<i32> = reader.samples::<i16>().map(|s| s.unwrap() as i32).collect();
+            assert!(
+                (16382..=16385).contains(&samples[0]),
+                "{dither:?} should stay within a couple of steps of the signal, but got {}",
+                samples[0]
+            );
+            assert_eq!(samples[2], -32768);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
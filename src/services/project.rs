@@ -1,7 +1,14 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
-use crate::{orchestration::AudioSenderFn, prelude::*, types::VisualizationQueue};
-use anyhow::Error;
+use crate::{
+    orchestration::{
+        AudioSenderFn, OfflineRenderJob, OfflineRenderOutcome, OfflineRenderTarget, RenderProgress,
+        StemExportOptions, WavExportOptions,
+    },
+    prelude::*,
+    types::{MeterReadingsQueue, VisualizationQueue},
+};
+use anyhow::{anyhow, Error};
 use crossbeam_channel::{Receiver, Sender};
 #[cfg(feature = "egui")]
 use eframe::egui::Key;
@@ -19,29 +26,48 @@ pub enum ProjectServiceInput {
     FramesNeeded(usize),
     #[cfg(feature = "egui")]
     KeyEvent(Key, bool, Option<Key>),
+    /// Where to publish meter readings as the project renders.
+    MeterReadingsQueue(MeterReadingsQueue),
     Midi(MidiChannel, MidiMessage),
     NextTimelineDisplayer,
-    ProjectExportToWav(Option<PathBuf>),
+    /// Cancels the running WAV or stem export.
+    ProjectExportCancel,
+    ProjectExportToSmf(Option<PathBuf>),
+    /// Renders tracks to separate WAV files in the given directory, in the
+    /// background.
+    ProjectExportStemsToWav(Option<PathBuf>, StemExportOptions),
+    /// Renders the project to a WAV file in the given format, in the
+    /// background.
+    ProjectExportToWav(Option<PathBuf>, WavExportOptions),
     ProjectLinkControl(Uid, Uid, ControlIndex),
     ProjectLoad(PathBuf),
     ProjectNew,
     ProjectPlay,
+    ProjectRedo,
     ProjectRemoveEntity(Uid),
     ProjectSave(Option<PathBuf>),
     ProjectSetSampleRate(SampleRate),
     ProjectStop,
+    ProjectUndo,
     ServiceInit,
     ServiceQuit,
     TrackAddEntity(TrackUid, EntityKey),
     TrackNewAudio,
     TrackNewAux,
+    /// Creates a group track containing the selected tracks.
+    TrackNewGroup,
     TrackNewMidi,
     VisualizationQueue(VisualizationQueue),
 }
 
 #[derive(Debug)]
 pub enum ProjectServiceEvent {
+    /// A WAV or stem export was cancelled, and its files deleted.
+    ExportCancelled(PathBuf),
     ExportFailed(Error),
+    /// A WAV or stem export to the given path has started. Follow or cancel it
+    /// with the [RenderProgress].
+    ExportStarted(PathBuf, RenderProgress),
     Exported(PathBuf),
     IsPerformingChanged(bool),
     LoadFailed(PathBuf, Error),
@@ -109,6 +135,8 @@ struct ProjectServiceDaemon {
     key_handler: KeyHandler,
 
     visualization_queue: Option<VisualizationQueue>,
+    meter_readings_queue: Option<MeterReadingsQueue>,
+    export_progress: Option<RenderProgress>,
 }
 impl ProjectServiceDaemon {
     pub fn new_with(
@@ -127,6 +155,8 @@ impl ProjectServiceDaemon {
             #[cfg(feature = "egui")]
             key_handler: Default::default(),
             visualization_queue: Default::default(),
+            meter_readings_queue: Default::default(),
+            export_progress: Default::default(),
         }
     }
 
@@ -140,6 +170,9 @@ impl ProjectServiceDaemon {
         if let Some(queue) = self.visualization_queue.as_ref() {
             new_project.e.visualization_queue = Some(queue.clone());
         }
+        if let Some(queue) = self.meter_readings_queue.as_ref() {
+            new_project.e.meter_readings_queue = Some(queue.clone());
+        }
     }
 
     fn swap_project(&mut self, mut new_project: Project) {
@@ -148,6 +181,44 @@ impl ProjectServiceDaemon {
         self.notify_new_project();
     }
 
+    // Renders a copy of the project on a thread of its own, so that playback
+    // and editing carry on during the export, and reports how it went. Only
+    // one export runs at a time, so that it can always be cancelled.
+    fn start_export(&mut self, target: OfflineRenderTarget) {
+        if self
+            .export_progress
+            .as_ref()
+            .is_some_and(|progress| !progress.is_finished())
+        {
+            let _ = self.sender.send(ProjectServiceEvent::ExportFailed(anyhow!(
+                "An export is already running"
+            )));
+            return;
+        }
+        let job = match OfflineRenderJob::new_with(&mut self.project.write().unwrap(), target) {
+            Ok(job) => job,
+            Err(e) => {
+                let _ = self.sender.send(ProjectServiceEvent::ExportFailed(e));
+                return;
+            }
+        };
+        let path = job.target().path().clone();
+        let progress = job.progress();
+        self.export_progress = Some(progress.clone());
+        let _ = self
+            .sender
+            .send(ProjectServiceEvent::ExportStarted(path.clone(), progress));
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            let event = match job.run() {
+                Ok(OfflineRenderOutcome::Finished) => ProjectServiceEvent::Exported(path),
+                Ok(OfflineRenderOutcome::Cancelled) => ProjectServiceEvent::ExportCancelled(path),
+                Err(e) => ProjectServiceEvent::ExportFailed(e),
+            };
+            let _ = sender.send(event);
+        });
+    }
+
     fn execute(&mut self) {
         while let Ok(input) = self.receiver.recv() {
             match input {
@@ -201,11 +272,21 @@ impl ProjectServiceDaemon {
                         .sender
                         .send(ProjectServiceEvent::IsPerformingChanged(false));
                 }
+                ProjectServiceInput::ProjectUndo => {
+                    if let Err(e) = self.project.write().unwrap().undo() {
+                        eprintln!("ProjectServiceInput::ProjectUndo failed: {e:?}");
+                    }
+                }
+                ProjectServiceInput::ProjectRedo => {
+                    if let Err(e) = self.project.write().unwrap().redo() {
+                        eprintln!("ProjectServiceInput::ProjectRedo failed: {e:?}");
+                    }
+                }
                 ProjectServiceInput::TrackAddEntity(track_uid, key) => {
                     if let Ok(mut project) = self.project.write() {
                         let uid = project.mint_entity_uid();
                         if let Some(entity) = self.factory.new_entity(&key, uid) {
-                            let _ = project.add_entity(track_uid, entity);
+                            let _ = project.edit(|project| project.add_entity(track_uid, entity));
                         } else {
                             eprintln!("ProjectServiceInput::TrackAddEntity failed");
                         }
@@ -216,7 +297,7 @@ impl ProjectServiceDaemon {
                         .project
                         .write()
                         .unwrap()
-                        .link(source_uid, target_uid, index);
+                        .edit(|project| project.link(source_uid, target_uid, index));
                 }
                 #[cfg(feature = "egui")]
                 ProjectServiceInput::KeyEvent(key, pressed, _physical_key) => {
@@ -242,6 +323,10 @@ impl ProjectServiceDaemon {
                     self.visualization_queue = Some(queue.clone());
                     self.project.write().unwrap().e.visualization_queue = Some(queue)
                 }
+                ProjectServiceInput::MeterReadingsQueue(queue) => {
+                    self.meter_readings_queue = Some(queue.clone());
+                    self.project.write().unwrap().e.meter_readings_queue = Some(queue)
+                }
                 ProjectServiceInput::Midi(channel, message) => self
                     .project
                     .write()
@@ -250,20 +335,77 @@ impl ProjectServiceDaemon {
                         eprintln!("TODO: {c:?} {m:?}");
                     }),
                 ProjectServiceInput::ProjectRemoveEntity(uid) => {
-                    let _ = self.project.write().unwrap().remove_entity(uid);
+                    let _ = self
+                        .project
+                        .write()
+                        .unwrap()
+                        .edit(|project| project.remove_entity(uid));
                 }
                 ProjectServiceInput::TrackNewAudio => {
-                    let _ = self.project.write().unwrap().new_audio_track();
+                    let _ = self
+                        .project
+                        .write()
+                        .unwrap()
+                        .edit(|project| project.new_audio_track());
                 }
                 ProjectServiceInput::TrackNewAux => {
-                    let _ = self.project.write().unwrap().new_aux_track();
+                    let _ = self
+                        .project
+                        .write()
+                        .unwrap()
+                        .edit(|project| project.new_aux_track());
+                }
+                ProjectServiceInput::TrackNewGroup => {
+                    let _ = self.project.write().unwrap().edit(|project| {
+                        let selected_track_uids: Vec<TrackUid> = project
+                            .view_state
+                            .track_selection_set
+                            .iter()
+                            .copied()
+                            .collect();
+                        let group_uid = project.new_group_track()?;
+                        // Aux tracks can't be grouped, so they stay where
+                        // they are.
+                        for track_uid in project.track_uids().to_vec() {
+                            if selected_track_uids.contains(&track_uid)
+                                && !project.orchestrator.aux_track_uids.contains(&track_uid)
+                            {
+                                project.set_track_group(track_uid, Some(group_uid))?;
+                            }
+                        }
+                        Ok(group_uid)
+                    });
                 }
                 ProjectServiceInput::TrackNewMidi => {
-                    let _ = self.project.write().unwrap().new_midi_track();
+                    let _ = self
+                        .project
+                        .write()
+                        .unwrap()
+                        .edit(|project| project.new_midi_track());
+                }
+                ProjectServiceInput::ProjectExportToSmf(path) => {
+                    let path = path.unwrap_or(PathBuf::from("exported-project.mid"));
+                    match self.project.read().unwrap().export_to_smf(path.clone()) {
+                        Ok(_) => {
+                            let _ = self.sender.send(ProjectServiceEvent::Exported(path));
+                        }
+                        Err(e) => {
+                            let _ = self.sender.send(ProjectServiceEvent::ExportFailed(e));
+                        }
+                    }
+                }
+                ProjectServiceInput::ProjectExportStemsToWav(path, options) => {
+                    let path = path.unwrap_or(PathBuf::from("exported-stems"));
+                    self.start_export(OfflineRenderTarget::Stems(path, options));
                 }
-                ProjectServiceInput::ProjectExportToWav(path) => {
+                ProjectServiceInput::ProjectExportToWav(path, options) => {
                     let path = path.unwrap_or(PathBuf::from("exported-project.wav"));
-                    let _ = self.project.write().unwrap().export_to_wav(path);
+                    self.start_export(OfflineRenderTarget::Wav(path, options));
+                }
+                ProjectServiceInput::ProjectExportCancel => {
+                    if let Some(progress) = self.export_progress.take() {
+                        progress.cancel();
+                    }
                 }
                 ProjectServiceInput::FramesNeeded(count) => {
                     self.project.write().unwrap().generate_and_dispatch_audio(
@@ -12,7 +12,7 @@ pub mod prelude {
         Controllable, Controls, ControlsAsProxy, DisplaysAction, Generates, GeneratesEnvelope,
         GenerationBuffer, HandlesMidi, HasMetadata, HasSettings, IsStereoSampleVoice, IsVoice,
         MidiMessagesFn, PlaysNotes, Projects, Sequences, SequencesMidi, Serializable, StoresVoices,
-        TransformsAudio, WorkEvent,
+        TransformsAudio, TransformsAudioWithKey, WorkEvent,
     };
 }
 
@@ -107,6 +107,15 @@ pub trait IsVoice<V: Default + Clone>: Generates<V> + PlaysNotes + Send + Sync {
 /// Same as IsVoice, but stereo.
 pub trait IsStereoSampleVoice: IsVoice<StereoSample> {}
 
+/// An effect that can listen to a sidechain key: a second signal that drives
+/// the effect's level detector in place of its own input. This is how a
+/// compressor on one track ducks it under another.
+pub trait TransformsAudioWithKey: TransformsAudio {
+    /// Transforms the samples in place, detecting levels in the key rather than
+    /// in the samples. The key has a frame for each sample.
+    fn transform_with_key(&mut self, key: &[StereoSample], samples: &mut [StereoSample]);
+}
+
 /// Records and replays MIDI events.
 ///
 /// This trait does not specify behavior in case of duplicate events, which
@@ -16,7 +16,7 @@ pub use ensnare::types::{
     SampleRate, Seconds, StereoSample, Tempo, TimeRange, TimeSignature, Uid, UidFactory, ViewRange,
 };
 pub use numbers::{FrequencyRange, SampleType, SignalType};
-pub use queues::VisualizationQueue;
+pub use queues::{MeterReadingsQueue, VisualizationQueue};
 
 mod colors;
 mod numbers;
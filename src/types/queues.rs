@@ -1,6 +1,6 @@
 // Copyright (c) 2024 Mike Tsao. All rights reserved.
 
-use crate::prelude::*;
+use crate::{orchestration::MeterReadings, prelude::*};
 use bounded_vec_deque::BoundedVecDeque;
 use std::{
     collections::VecDeque,
@@ -25,3 +25,13 @@ impl Clone for VisualizationQueue {
         Self(Arc::clone(&self.0))
     }
 }
+
+/// The most recent [MeterReadings], published by the audio thread for the UI
+/// to draw.
+#[derive(Debug, Default)]
+pub struct MeterReadingsQueue(pub Arc<RwLock<MeterReadings>>);
+impl Clone for MeterReadingsQueue {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
@@ -2,10 +2,14 @@
 
 //! Provides a programmatic way to load music samples.
 
-use crate::midi::MidiNote;
+use crate::{midi::MidiNote, util::Paths};
+use anyhow::anyhow;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 use synonym::Synonym;
 
 static INSTANCE: OnceCell<SampleLibrary> = OnceCell::new();
@@ -26,7 +30,7 @@ pub fn init_sample_libraries() {
 pub struct SampleIndex(pub usize);
 
 /// Generally identifies a sample.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum SampleSource {
     SampleLibrary(SampleIndex),
     Path(PathBuf),
@@ -36,6 +40,24 @@ impl Default for SampleSource {
         SampleSource::SampleLibrary(SampleIndex::default())
     }
 }
+impl SampleSource {
+    /// Finds the sample's file in the [Paths] hives and opens it.
+    pub fn open(&self) -> anyhow::Result<File> {
+        let path = match self {
+            SampleSource::SampleLibrary(index) => {
+                if let Some(path) = SampleLibrary::global().path(*index) {
+                    Paths::global().build_sample(&Vec::default(), Path::new(&path))
+                } else {
+                    return Err(anyhow!("Couldn't find sample {index} in library"));
+                }
+            }
+            SampleSource::Path(path_buf) => {
+                Paths::global().build_sample(&Vec::default(), Path::new(&path_buf))
+            }
+        };
+        Paths::global().search_and_open(path.as_path())
+    }
+}
 
 #[derive(Debug)]
 pub struct SampleItem {
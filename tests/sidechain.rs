@@ -3,7 +3,7 @@
 use ensnare::prelude::*;
 use ensnare_toys::prelude::*;
 use ensnare_v1::{
-    entities::{Drumkit, Gain},
+    entities::{Compressor, Drumkit},
     prelude::*,
 };
 
@@ -18,11 +18,10 @@ use ensnare_v1::{
 // than mixing it into the final track, should leave empty spaces in the lead
 // output and make it easier to see the effect in Audacity.
 //
-// TODO: this isn't a useful form of sidechaining, because it uses a
-// sample-by-sample correction, which basically means that the lead track gets
-// all sorts of transients and awfulness, rather than being nicely ducked. The
-// missing piece is an envelope that adjusts relatively slowly to the amplitude
-// of the source signal.
+// The ducking is done by a compressor on the lead track that listens to the
+// rhythm track through its sidechain key input. Its envelope follower keeps
+// the gain changes smooth, so the lead is turned down for each kick without
+// picking up the kick's waveform.
 #[test]
 fn demo_sidechaining() {
     Paths::set_instance(Paths::default());
@@ -75,19 +74,6 @@ fn demo_sidechaining() {
         )
         .unwrap();
 
-    // This turns the chain's audio output into Control events.
-    let signal_passthrough_uid = project
-        .add_entity(
-            sidechain_track_uid,
-            factory
-                .new_entity(
-                    &EntityKey::from("signal-amplitude-inverted-passthrough"),
-                    Uid::default(),
-                )
-                .unwrap(),
-        )
-        .unwrap();
-
     // In this demo, we don't want to hear the kick track.
     project.set_track_output(sidechain_track_uid, Normal::zero());
 
@@ -118,15 +104,19 @@ fn demo_sidechaining() {
         )
         .unwrap();
 
-    let entity = factory
-        .new_entity(&EntityKey::from(Gain::ENTITY_KEY), Uid::default())
+    let compressor_uid = project
+        .add_entity(
+            lead_track_uid,
+            factory
+                .new_entity(&EntityKey::from(Compressor::ENTITY_KEY), Uid::default())
+                .unwrap(),
+        )
         .unwrap();
-    let gain_ceiling_param_index = entity.control_index_for_name("ceiling").unwrap();
-    let gain_uid = project.add_entity(lead_track_uid, entity).unwrap();
 
-    // Link the sidechain control to the synth's gain.
+    // Key the lead's compressor with the kick track. The kick still drives the
+    // compressor even though its own output is turned all the way down.
     assert!(project
-        .link(signal_passthrough_uid, gain_uid, gain_ceiling_param_index)
+        .add_sidechain(compressor_uid, sidechain_track_uid)
         .is_ok());
 
     let output_prefix: std::path::PathBuf = [env!("CARGO_TARGET_TMPDIR"), "sidechaining"]